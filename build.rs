@@ -0,0 +1,18 @@
+use std::process::Command;
+
+// Bakes the commit being built into the binary for /healthz and /readyz. A GIT_SHA set in the
+// build environment wins, for builds outside a checkout.
+fn main() {
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+
+    if std::env::var("GIT_SHA").is_ok() {
+        return;
+    }
+    if let Ok(output) = Command::new("git").args(["rev-parse", "--short=12", "HEAD"]).output()
+        && output.status.success()
+    {
+        println!("cargo:rustc-env=GIT_SHA={}", String::from_utf8_lossy(&output.stdout).trim());
+    }
+}
@@ -0,0 +1,35 @@
+use crate::handlers::csrf::CSRF_HEADER;
+use crate::handlers::request_id::REQUEST_ID_HEADER;
+
+use axum::http::{
+    header::{AUTHORIZATION, CONTENT_TYPE},
+    HeaderName, HeaderValue, Method,
+};
+use std::time::Duration;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+// How long browsers may cache a preflight answer
+const PREFLIGHT_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+// Lets a frontend served from one of the given origins call the API with its session cookie.
+// Origins are checked when the config is read, so only valid header values get here.
+pub fn cors_layer(origins: &[String]) -> CorsLayer {
+    let origins: Vec<HeaderValue> = origins
+        .iter()
+        .filter_map(|origin| HeaderValue::from_str(origin).ok())
+        .collect();
+
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_credentials(true)
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE])
+        .allow_headers([
+            CONTENT_TYPE,
+            AUTHORIZATION,
+            HeaderName::from_static(CSRF_HEADER),
+            HeaderName::from_static(REQUEST_ID_HEADER),
+        ])
+        .expose_headers([HeaderName::from_static(REQUEST_ID_HEADER)])
+        .max_age(PREFLIGHT_MAX_AGE)
+}
+
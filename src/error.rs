@@ -0,0 +1,226 @@
+use crate::handlers::request_id::current_request_id;
+use crate::supabase_client::MgmtApiError;
+
+use axum::{
+    extract::rejection::JsonRejection,
+    http::{header::RETRY_AFTER, StatusCode},
+    response::{IntoResponse, Json},
+};
+use serde::Serialize;
+
+// The JSON body of every error response
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    // Set when the error came from the Management API
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream_status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<u64>,
+    // To quote when reporting the failure; matches the X-Request-Id response header
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl ErrorResponse {
+    pub fn new(error: String) -> Self {
+        ErrorResponse {
+            error,
+            code: None,
+            upstream_status: None,
+            retry_after: None,
+            request_id: current_request_id(),
+        }
+    }
+}
+
+// Error returned by every handler, rendered as an ErrorResponse with the matching status
+#[derive(Debug)]
+pub enum AppError {
+    Unauthorized,
+    BadRequest(String),
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    // A well-formed request whose content is invalid, e.g. unknown service names
+    Unprocessable(String),
+    RateLimited(String),
+    Timeout(String),
+    // The handler ran past REQUEST_TIMEOUT_SECS
+    RequestTimeout(String),
+    PayloadTooLarge(String),
+    // Upstream requests are paused; carries the seconds until they are tried again
+    Unavailable(String, u64),
+    ApiError(String),
+    Management(MgmtApiError),
+    JsonError(serde_json::Error),
+    SessionError(String),
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> axum::response::Response {
+        // Failures on this side or upstream, not in the request, are logged as errors, which is
+        // also what gets them reported to Sentry
+        let failed = match &self {
+            AppError::ApiError(_) | AppError::SessionError(_) => true,
+            AppError::Management(error) => error.status >= 500,
+            _ => false,
+        };
+        if failed {
+            tracing::error!(error = %self, "request failed");
+        }
+
+        let (status, body) = match self {
+            AppError::Management(error) => {
+                // Client errors keep their status; upstream failures surface as a bad gateway
+                let status = StatusCode::from_u16(error.status)
+                    .ok()
+                    .filter(|status| status.is_client_error())
+                    .unwrap_or(StatusCode::BAD_GATEWAY);
+                let body = ErrorResponse {
+                    code: error.code,
+                    upstream_status: Some(error.status),
+                    ..ErrorResponse::new(error.message)
+                };
+                (status, body)
+            }
+            AppError::Unavailable(msg, retry_after) => {
+                let body = ErrorResponse { retry_after: Some(retry_after), ..ErrorResponse::new(msg) };
+                (StatusCode::SERVICE_UNAVAILABLE, body)
+            }
+            AppError::Unauthorized => (StatusCode::UNAUTHORIZED, ErrorResponse::new("Unauthorized".to_string())),
+            AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, ErrorResponse::new(msg)),
+            AppError::Forbidden(msg) => (StatusCode::FORBIDDEN, ErrorResponse::new(msg)),
+            AppError::NotFound(msg) => (StatusCode::NOT_FOUND, ErrorResponse::new(msg)),
+            AppError::Conflict(msg) => (StatusCode::CONFLICT, ErrorResponse::new(msg)),
+            AppError::Unprocessable(msg) => (StatusCode::UNPROCESSABLE_ENTITY, ErrorResponse::new(msg)),
+            AppError::RateLimited(msg) => (StatusCode::TOO_MANY_REQUESTS, ErrorResponse::new(msg)),
+            AppError::Timeout(msg) => (StatusCode::GATEWAY_TIMEOUT, ErrorResponse::new(msg)),
+            AppError::RequestTimeout(msg) => (StatusCode::REQUEST_TIMEOUT, ErrorResponse::new(msg)),
+            AppError::PayloadTooLarge(msg) => (StatusCode::PAYLOAD_TOO_LARGE, ErrorResponse::new(msg)),
+            AppError::ApiError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, ErrorResponse::new(msg)),
+            AppError::JsonError(err) => (StatusCode::BAD_REQUEST, ErrorResponse::new(format!("JSON error: {}", err))),
+            AppError::SessionError(msg) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorResponse::new(format!("Session error: {}", msg)),
+            ),
+        };
+
+        match body.retry_after {
+            Some(retry_after) => (status, [(RETRY_AFTER, retry_after.to_string())], Json(body)).into_response(),
+            None => (status, Json(body)).into_response(),
+        }
+    }
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppError::Unauthorized => write!(f, "Unauthorized"),
+            AppError::BadRequest(msg)
+            | AppError::Forbidden(msg)
+            | AppError::NotFound(msg)
+            | AppError::Conflict(msg)
+            | AppError::Unprocessable(msg)
+            | AppError::RateLimited(msg)
+            | AppError::Timeout(msg)
+            | AppError::RequestTimeout(msg)
+            | AppError::PayloadTooLarge(msg)
+            | AppError::ApiError(msg) => write!(f, "{}", msg),
+            AppError::Management(error) => write!(f, "{}", error.message),
+            AppError::Unavailable(msg, _) => write!(f, "{}", msg),
+            AppError::JsonError(err) => write!(f, "JSON error: {}", err),
+            AppError::SessionError(msg) => write!(f, "Session error: {}", msg),
+        }
+    }
+}
+
+impl AppError {
+    // Prefixes the message of API failures with what was being attempted, keeping the variant
+    pub fn context(self, action: impl std::fmt::Display) -> Self {
+        match self {
+            AppError::ApiError(msg) => AppError::ApiError(format!("{}: {}", action, msg)),
+            AppError::Management(mut error) => {
+                error.message = format!("{}: {}", action, error.message);
+                AppError::Management(error)
+            }
+            other => other,
+        }
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(err: serde_json::Error) -> Self {
+        AppError::JsonError(err)
+    }
+}
+
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        match rejection {
+            JsonRejection::JsonDataError(e) => AppError::Unprocessable(e.body_text()),
+            other if other.status() == StatusCode::PAYLOAD_TOO_LARGE => AppError::PayloadTooLarge(other.body_text()),
+            other => AppError::BadRequest(other.body_text()),
+        }
+    }
+}
+
+impl From<reqwest::Error> for AppError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            AppError::Timeout(format!(
+                "Supabase Management API did not respond in time: {}",
+                err.url().map_or("", |url| url.path())
+            ))
+        } else {
+            AppError::ApiError(format!("Request failed: {}", err))
+        }
+    }
+}
+
+impl From<tower_sessions::session::Error> for AppError {
+    fn from(err: tower_sessions::session::Error) -> Self {
+        AppError::SessionError(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    async fn render(error: AppError) -> (StatusCode, Option<String>, Value) {
+        let response = error.into_response();
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .map(|value| value.to_str().unwrap().to_string());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, retry_after, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_errors_rendered_as_envelope() {
+        let (status, _, body) = render(AppError::NotFound("No snapshot found with id x".to_string())).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body, json!({"error": "No snapshot found with id x"}));
+
+        let upstream = MgmtApiError::from_body(500, r#"{"message": "boom"}"#);
+        let (status, _, body) = render(AppError::Management(upstream).context("Failed to fetch Auth config")).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(body["error"], json!("Failed to fetch Auth config: boom"));
+        assert_eq!(body["upstream_status"], json!(500));
+
+        let (status, _, body) = render(AppError::Management(MgmtApiError::from_body(404, r#"{"message": "gone"}"#))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["upstream_status"], json!(404));
+
+        let (status, retry_after, body) = render(AppError::Unavailable("paused".to_string(), 30)).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(retry_after.as_deref(), Some("30"));
+        assert_eq!(body["retry_after"], json!(30));
+    }
+}
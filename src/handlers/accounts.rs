@@ -0,0 +1,228 @@
+use crate::error::AppError;
+use crate::models::oauth::{AccountSummary, MeResponse, StoredAccount, SwitchAccountRequest};
+use crate::models::AppState;
+use crate::supabase_client::{ManagementApi, ResponseCache};
+
+use axum::{
+    extract::{rejection::JsonRejection, State},
+    response::Json,
+};
+use time::OffsetDateTime;
+use tower_sessions::Session;
+
+pub const ACCOUNTS_KEY: &str = "supabase_accounts";
+pub const ACTIVE_ACCOUNT_KEY: &str = "active_account";
+// The active account's token is kept here as well, which is where requests read it from
+pub const ACCESS_TOKEN_KEY: &str = "supabase_access_token";
+
+pub async fn accounts_handler(session: Session) -> Result<Json<Vec<AccountSummary>>, AppError> {
+    let active = active_account_id(&session).await?;
+    let accounts = stored_accounts(&session)
+        .await?
+        .into_iter()
+        .map(|account| summary(&account, active.as_deref()))
+        .collect();
+
+    Ok(Json(accounts))
+}
+
+pub async fn me_handler(
+    State(app_state): State<AppState>,
+    session: Session,
+) -> Result<Json<MeResponse>, AppError> {
+    let active = active_account_id(&session).await?;
+    let account = stored_accounts(&session)
+        .await?
+        .into_iter()
+        .find(|account| Some(&account.id) == active.as_ref());
+
+    let me = match account {
+        Some(account) => MeResponse {
+            authenticated: account.expires_at.is_none_or(|expires_at| expires_at > OffsetDateTime::now_utc()),
+            auth_mode: Some("oauth"),
+            expires_at: account.expires_at,
+            scopes: account.scopes.clone(),
+            missing_scopes: missing_scopes(&app_state.config.oauth_scopes, &account.scopes),
+            account: Some(summary(&account, active.as_deref())),
+        },
+        None => {
+            // Sessions from before accounts were stored only hold the token
+            let session_token: Option<String> = session.get(ACCESS_TOKEN_KEY).await?;
+            let auth_mode = if session_token.is_some() {
+                Some("oauth")
+            } else {
+                app_state.config.personal_access_token.as_ref().map(|_| "personal_access_token")
+            };
+            MeResponse {
+                authenticated: auth_mode.is_some(),
+                auth_mode,
+                account: None,
+                expires_at: None,
+                scopes: Vec::new(),
+                missing_scopes: Vec::new(),
+            }
+        }
+    };
+
+    Ok(Json(me))
+}
+
+pub async fn switch_account_handler(
+    session: Session,
+    body: Result<Json<SwitchAccountRequest>, JsonRejection>,
+) -> Result<Json<AccountSummary>, AppError> {
+    let Json(request) = body?;
+    let account = stored_accounts(&session)
+        .await?
+        .into_iter()
+        .find(|account| account.id == request.account_id)
+        .ok_or_else(|| AppError::NotFound(format!("No account found with id {}", request.account_id)))?;
+
+    activate(&session, &account).await?;
+    Ok(Json(summary(&account, Some(&account.id))))
+}
+
+// Names the account after the organization the token was granted for. Tokens whose
+// organization cannot be listed are still stored, under an id derived from the token.
+pub async fn account_for_token(app_state: &AppState, access_token: String) -> StoredAccount {
+    let organization = app_state
+        .management_client(access_token.clone())
+        .list_organizations()
+        .await
+        .map(|organizations| organizations.into_iter().next());
+
+    match organization {
+        Ok(Some(organization)) => StoredAccount {
+            id: organization.id,
+            name: organization.name,
+            access_token,
+            refresh_token: None,
+            expires_at: None,
+            scopes: Vec::new(),
+        },
+        other => {
+            if let Err(e) = other {
+                tracing::warn!(error = %e, "failed to look up the organization for a new token");
+            }
+            StoredAccount {
+                id: ResponseCache::token_key(&access_token)[..12].to_string(),
+                name: "Supabase account".to_string(),
+                access_token,
+                refresh_token: None,
+                expires_at: None,
+                scopes: Vec::new(),
+            }
+        }
+    }
+}
+
+// Requested scopes the token lacks. Token responses without a scope list are taken as
+// granting what was requested.
+pub fn missing_scopes(requested: &[String], granted: &[String]) -> Vec<String> {
+    if granted.is_empty() {
+        return Vec::new();
+    }
+
+    requested
+        .iter()
+        .filter(|scope| !granted.contains(scope))
+        .cloned()
+        .collect()
+}
+
+// Adds the account, replacing an earlier token for it, and makes it the active one
+pub async fn remember_account(session: &Session, account: StoredAccount) -> Result<(), AppError> {
+    let mut accounts = stored_accounts(session).await?;
+    accounts.retain(|stored| stored.id != account.id);
+    accounts.push(account.clone());
+
+    session.insert(ACCOUNTS_KEY, accounts).await?;
+    activate(session, &account).await
+}
+
+async fn activate(session: &Session, account: &StoredAccount) -> Result<(), AppError> {
+    session.insert(ACTIVE_ACCOUNT_KEY, &account.id).await?;
+    session
+        .insert(ACCESS_TOKEN_KEY, &account.access_token)
+        .await
+        .map_err(AppError::from)
+}
+
+async fn stored_accounts(session: &Session) -> Result<Vec<StoredAccount>, AppError> {
+    session
+        .get(ACCOUNTS_KEY)
+        .await
+        .map(Option::unwrap_or_default)
+        .map_err(AppError::from)
+}
+
+async fn active_account_id(session: &Session) -> Result<Option<String>, AppError> {
+    session
+        .get(ACTIVE_ACCOUNT_KEY)
+        .await
+        .map_err(AppError::from)
+}
+
+fn summary(account: &StoredAccount, active: Option<&str>) -> AccountSummary {
+    AccountSummary {
+        id: account.id.clone(),
+        name: account.name.clone(),
+        active: active == Some(account.id.as_str()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tower_sessions::MemoryStore;
+
+    fn account(id: &str, token: &str) -> StoredAccount {
+        StoredAccount {
+            id: id.to_string(),
+            name: id.to_string(),
+            access_token: token.to_string(),
+            refresh_token: None,
+            expires_at: None,
+            scopes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_missing_scopes_compares_against_grant() {
+        let requested = vec!["projects:read".to_string(), "secrets:write".to_string()];
+
+        assert_eq!(
+            missing_scopes(&requested, &["projects:read".to_string()]),
+            vec!["secrets:write".to_string()]
+        );
+        assert!(missing_scopes(&requested, &[]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_switching_accounts_changes_active_token() {
+        let session = Session::new(None, Arc::new(MemoryStore::default()), None);
+        remember_account(&session, account("org-a", "token-a")).await.unwrap();
+        remember_account(&session, account("org-b", "token-b")).await.unwrap();
+
+        let token: Option<String> = session.get(ACCESS_TOKEN_KEY).await.unwrap();
+        assert_eq!(token.as_deref(), Some("token-b"));
+
+        let switched = switch_account_handler(
+            session.clone(),
+            Ok(Json(SwitchAccountRequest {
+                account_id: "org-a".to_string(),
+            })),
+        )
+        .await
+        .unwrap();
+        assert!(switched.active);
+
+        let token: Option<String> = session.get(ACCESS_TOKEN_KEY).await.unwrap();
+        assert_eq!(token.as_deref(), Some("token-a"));
+
+        let Json(accounts) = accounts_handler(session).await.unwrap();
+        let active: Vec<_> = accounts.iter().map(|account| (account.id.as_str(), account.active)).collect();
+        assert_eq!(active, vec![("org-a", true), ("org-b", false)]);
+    }
+}
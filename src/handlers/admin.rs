@@ -0,0 +1,60 @@
+use crate::handlers::auth::bearer_token;
+use crate::error::AppError;
+use crate::models::admin::SessionReport;
+use crate::models::AppState;
+
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::Json,
+};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Admin endpoints answer as if they did not exist while ADMIN_TOKEN is unset
+pub fn require_admin(app_state: &AppState, headers: &HeaderMap) -> Result<(), AppError> {
+    let Some(admin_token) = &app_state.config.admin_token else {
+        return Err(AppError::NotFound("Not found".to_string()));
+    };
+
+    match bearer_token(headers) {
+        Some(token) if token_matches(admin_token, &token) => Ok(()),
+        _ => Err(AppError::Unauthorized),
+    }
+}
+
+// Compares HMAC digests of the tokens in constant time, so how long the check takes does not
+// give away how much of the token was right
+fn token_matches(admin_token: &str, presented: &str) -> bool {
+    let digest = |token: &str| {
+        let mut mac = HmacSha256::new_from_slice(admin_token.as_bytes()).expect("HMAC accepts keys of any length");
+        mac.update(token.as_bytes());
+        mac
+    };
+    digest(presented).verify_slice(&digest(admin_token).finalize().into_bytes()).is_ok()
+}
+
+// Helps debug unexpected logouts. Only available when ADMIN_TOKEN is set, and only to
+// requests that send it as a bearer token.
+pub async fn admin_sessions_handler(
+    State(app_state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<SessionReport>, AppError> {
+    require_admin(&app_state, &headers)?;
+    Ok(Json(app_state.session_stats.report(&app_state.config.session_store)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_admin_token_must_match_exactly() {
+        assert!(token_matches("s3cret", "s3cret"));
+        assert!(!token_matches("s3cret", "s3cre"));
+        assert!(!token_matches("s3cret", "s3cret!"));
+        assert!(!token_matches("s3cret", "S3cret"));
+    }
+}
@@ -0,0 +1,67 @@
+use crate::models::AppState;
+
+use axum::{
+    extract::State,
+    http::Uri,
+    response::Redirect,
+};
+
+pub const API_PREFIX: &str = "/api/v1";
+
+// Where the JSON endpoints lived before they moved under API_PREFIX. Requests to them are
+// redirected so existing clients keep working.
+pub const LEGACY_API_PATHS: [&str; 25] = [
+    "/preview",
+    "/preview/report",
+    "/preview/value",
+    "/migrate/plan",
+    "/migrate/apply",
+    "/migrate/rollback/{apply_id}",
+    "/migrate/jobs/{job_id}",
+    "/migrate/jobs/{job_id}/events",
+    "/migrate/jobs/{job_id}/resume",
+    "/organizations",
+    "/projects",
+    "/projects/{project_id}/branches",
+    "/projects/{project_id}/snapshots",
+    "/projects/{project_id}/drift",
+    "/projects/{project_id}/export",
+    "/projects/{project_id}/import",
+    "/snapshots/{from_id}/diff/{to_id}",
+    "/drift/status",
+    "/me",
+    "/accounts",
+    "/accounts/switch",
+    "/admin/sessions",
+    "/admin/webhooks",
+    "/admin/webhooks/{webhook_id}",
+    "/csrf-token",
+];
+
+// 308 keeps the method and body, so POSTs to the old paths are replayed at the new ones
+pub async fn legacy_api_redirect(State(app_state): State<AppState>, uri: Uri) -> Redirect {
+    Redirect::permanent(&versioned_path(app_state.config.base_path.as_deref(), &uri))
+}
+
+fn versioned_path(base_path: Option<&str>, uri: &Uri) -> String {
+    let mut path = format!("{}{}{}", base_path.unwrap_or_default(), API_PREFIX, uri.path());
+    if let Some(query) = uri.query() {
+        path.push('?');
+        path.push_str(query);
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_legacy_paths_redirect_under_prefix() {
+        let uri: Uri = "/preview?source_id=a&dest_id=b".parse().unwrap();
+        assert_eq!(versioned_path(None, &uri), "/api/v1/preview?source_id=a&dest_id=b");
+
+        let uri: Uri = "/migrate/apply".parse().unwrap();
+        assert_eq!(versioned_path(Some("/tools"), &uri), "/tools/api/v1/migrate/apply");
+    }
+}
@@ -0,0 +1,101 @@
+use crate::error::AppError;
+use crate::handlers::accounts::ACTIVE_ACCOUNT_KEY;
+use crate::models::AppState;
+
+use axum::{
+    extract::FromRequestParts,
+    http::{header::AUTHORIZATION, request::Parts, HeaderMap},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sha2::{Digest, Sha256};
+use tower_sessions::Session;
+
+// A request that carries a Management API token. Rejects with a 401 JSON error when there is
+// none, so protected handlers only have to take it as an argument.
+pub struct AuthenticatedUser {
+    pub access_token: String,
+    // The session's active account, when the token came from an OAuth login
+    pub account_id: Option<String>,
+}
+
+impl FromRequestParts<AppState> for AuthenticatedUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let user = Self::authenticate(parts, state).await?;
+        // Errors reported to Sentry name the token by its fingerprint, never the token itself
+        sentry::configure_scope(|scope| {
+            scope.set_user(Some(sentry::User {
+                id: Some(user.fingerprint()),
+                ..Default::default()
+            }))
+        });
+        Ok(user)
+    }
+}
+
+impl AuthenticatedUser {
+    // A bearer token sent with the request wins, then the OAuth session, then the personal
+    // access token the server was configured with
+    async fn authenticate(parts: &mut Parts, state: &AppState) -> Result<Self, AppError> {
+        if let Some(access_token) = bearer_token(&parts.headers) {
+            return Ok(AuthenticatedUser { access_token, account_id: None });
+        }
+
+        let session = Session::from_request_parts(parts, state)
+            .await
+            .map_err(|(_, msg)| AppError::SessionError(msg.to_string()))?;
+        let token_option: Option<String> = session.get("supabase_access_token").await?;
+        if let Some(access_token) = token_option {
+            let account_id = session.get(ACTIVE_ACCOUNT_KEY).await?;
+            return Ok(AuthenticatedUser { access_token, account_id });
+        }
+
+        state
+            .config
+            .personal_access_token
+            .clone()
+            .map(|access_token| AuthenticatedUser { access_token, account_id: None })
+            .ok_or(AppError::Unauthorized)
+    }
+
+    // Who owns what the user keeps on the server. OAuth tokens change with every login, so
+    // their account is used; bearer and personal access tokens stand for themselves.
+    pub fn owner(&self) -> String {
+        match &self.account_id {
+            Some(account_id) => format!("account:{}", account_id),
+            None => self.fingerprint(),
+        }
+    }
+
+    // Identifies the token in logs without revealing it
+    pub fn fingerprint(&self) -> String {
+        let digest = Sha256::digest(self.access_token.as_bytes());
+        format!("token:{}", &URL_SAFE_NO_PAD.encode(digest)[..12])
+    }
+}
+
+pub fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    let token = token.trim();
+
+    (scheme.eq_ignore_ascii_case("bearer") && !token.is_empty()).then(|| token.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bearer_token_from_authorization_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(bearer_token(&headers), None);
+
+        headers.insert(AUTHORIZATION, "Bearer sbp_123".parse().unwrap());
+        assert_eq!(bearer_token(&headers).as_deref(), Some("sbp_123"));
+
+        headers.insert(AUTHORIZATION, "Basic dXNlcjpwYXNz".parse().unwrap());
+        assert_eq!(bearer_token(&headers), None);
+    }
+}
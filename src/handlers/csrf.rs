@@ -0,0 +1,93 @@
+use crate::error::AppError;
+use crate::handlers::auth::bearer_token;
+use crate::models::oauth::CsrfTokenResponse;
+
+use axum::{
+    extract::Request,
+    http::{HeaderMap, Method},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use tower_sessions::Session;
+use uuid::Uuid;
+
+const CSRF_SESSION_KEY: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+// Starting a device login happens without a browser session, so there is no token to send
+const EXEMPT_PATHS: [&str; 1] = ["/api/auth/device"];
+
+// Token the frontend sends back in the X-CSRF-Token header on every non-GET request
+pub async fn csrf_token_handler(session: Session) -> Result<Json<CsrfTokenResponse>, AppError> {
+    let existing: Option<String> = session.get(CSRF_SESSION_KEY).await?;
+
+    let csrf_token = match existing {
+        Some(token) => token,
+        None => {
+            let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+            session.insert(CSRF_SESSION_KEY, &token).await?;
+            token
+        }
+    };
+
+    Ok(Json(CsrfTokenResponse { csrf_token }))
+}
+
+// Rejects state-changing requests that do not carry the session's CSRF token. Requests
+// authenticated with a bearer token are exempt, since browsers never add one; any other
+// Authorization header still leaves the request on the cookie session, so it is checked.
+pub async fn require_csrf_token(session: Session, request: Request, next: Next) -> Response {
+    if !needs_check(request.method(), request.uri().path(), request.headers()) {
+        return next.run(request).await;
+    }
+
+    let expected: Option<String> = match session.get(CSRF_SESSION_KEY).await {
+        Ok(token) => token,
+        Err(e) => {
+            return AppError::from(e).into_response();
+        }
+    };
+
+    if token_matches(request.headers(), expected.as_deref()) {
+        next.run(request).await
+    } else {
+        AppError::Forbidden("Missing or invalid CSRF token".to_string()).into_response()
+    }
+}
+
+fn needs_check(method: &Method, path: &str, headers: &HeaderMap) -> bool {
+    let safe = matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS);
+    !safe && !EXEMPT_PATHS.contains(&path) && bearer_token(headers).is_none()
+}
+
+fn token_matches(headers: &HeaderMap, expected: Option<&str>) -> bool {
+    let sent = headers.get(CSRF_HEADER).and_then(|value| value.to_str().ok());
+    matches!((sent, expected), (Some(sent), Some(expected)) if sent == expected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::header::AUTHORIZATION;
+
+    #[test]
+    fn test_csrf_check_applies_to_cookie_authenticated_writes() {
+        let mut headers = HeaderMap::new();
+        assert!(!needs_check(&Method::GET, "/migrate/apply", &headers));
+        assert!(needs_check(&Method::POST, "/migrate/apply", &headers));
+        assert!(!needs_check(&Method::POST, "/api/auth/device", &headers));
+
+        assert!(!token_matches(&headers, Some("abc")));
+        headers.insert(CSRF_HEADER, "abc".parse().unwrap());
+        assert!(token_matches(&headers, Some("abc")));
+        assert!(!token_matches(&headers, Some("other")));
+        assert!(!token_matches(&headers, None));
+
+        // Headers authentication does not accept fall back to the cookie session
+        for value in ["Basic dXNlcjpwYXNz", "Bearer ", "sbp_123"] {
+            headers.insert(AUTHORIZATION, value.parse().unwrap());
+            assert!(needs_check(&Method::POST, "/migrate/apply", &headers));
+        }
+        headers.insert(AUTHORIZATION, "Bearer sbp_123".parse().unwrap());
+        assert!(!needs_check(&Method::POST, "/migrate/apply", &headers));
+    }
+}
@@ -0,0 +1,71 @@
+use crate::models::health::{BuildInfo, HealthResponse, ReadinessCheck, ReadinessResponse};
+use crate::models::AppState;
+use crate::sessions::check_session_store;
+use crate::supabase_client::check_reachable;
+
+use axum::{extract::State, http::StatusCode, response::Json};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::Duration;
+use tower_sessions::SessionStore;
+
+// Longest a single readiness check may take before it counts as failed
+const CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+// Liveness: the process is up and serving requests
+pub async fn healthz_handler() -> Json<HealthResponse> {
+    Json(HealthResponse {
+        status: "ok",
+        build: BuildInfo::CURRENT,
+    })
+}
+
+// Readiness: the session store answers and, with READYZ_CHECK_API, so does the Management API.
+// Answers 503 when any check fails, so load balancers hold traffic back.
+pub async fn readyz_handler(State(app_state): State<AppState>) -> (StatusCode, Json<ReadinessResponse>) {
+    let http = app_state.config.readyz_check_api.then_some(&app_state.http);
+    let readiness = readiness(app_state.session_probe.as_ref(), http).await;
+    let status = if readiness.ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(readiness))
+}
+
+async fn readiness(sessions: &dyn SessionStore, http: Option<&reqwest::Client>) -> ReadinessResponse {
+    let mut checks = BTreeMap::new();
+    checks.insert("session_store", run_check(check_session_store(sessions)).await);
+    if let Some(http) = http {
+        checks.insert("management_api", run_check(check_reachable(http, CHECK_TIMEOUT)).await);
+    }
+
+    ReadinessResponse {
+        ready: checks.values().all(|check| check.ok),
+        build: BuildInfo::CURRENT,
+        checks,
+    }
+}
+
+async fn run_check(check: impl Future<Output = Result<(), String>>) -> ReadinessCheck {
+    let result = tokio::time::timeout(CHECK_TIMEOUT, check)
+        .await
+        .unwrap_or_else(|_| Err(format!("timed out after {}s", CHECK_TIMEOUT.as_secs())));
+    ReadinessCheck {
+        ok: result.is_ok(),
+        error: result.err(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_sessions::MemoryStore;
+
+    #[tokio::test]
+    async fn test_readiness_reports_each_check() {
+        let readiness = readiness(&MemoryStore::default(), None).await;
+        assert!(readiness.ready);
+        assert_eq!(readiness.checks.keys().copied().collect::<Vec<_>>(), vec!["session_store"]);
+
+        let failed = run_check(async { Err("connection refused".to_string()) }).await;
+        assert!(!failed.ok);
+        assert_eq!(failed.error.as_deref(), Some("connection refused"));
+    }
+}
@@ -0,0 +1,602 @@
+use crate::error::AppError;
+use crate::handlers::auth::AuthenticatedUser;
+use crate::handlers::migrate::audit::audit_entry;
+use crate::handlers::migrate::branches::resolve_project_ref;
+use crate::handlers::migrate::confirmation::{diff_hash, verify_token};
+use crate::handlers::migrate::plan::build_plan;
+use crate::handlers::migrate::services::{
+    fetch_service_config, find_service, ApplyStrategy, ServiceSpec, DEFAULTS_SOURCE,
+};
+use crate::handlers::migrate::functions::{apply_functions, restore_functions, FunctionSource};
+use crate::handlers::migrate::secrets::{apply_secrets, restore_secrets};
+use crate::handlers::migrate::webhooks::{apply_webhooks, restore_webhooks};
+use crate::handlers::migrate::preview_handler::{json_diff, redact_diffs, DiffOptions};
+use crate::models::migrate::{
+    ApplyRequest, ApplySnapshot, AuditAction, DiffEntry, JobAccepted, JobStatus, JobStep, MigrationJob, Notification, ProjectConfig,
+    ServiceApply, ServiceSnapshot, StepStatus,
+};
+use crate::models::AppState;
+use crate::supabase_client::ManagementApi;
+
+use axum::{
+    extract::{rejection::JsonRejection, State},
+    http::StatusCode,
+    response::{IntoResponse, Json},
+};
+use reqwest::Method;
+use serde_json::{Map, Value};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+#[tracing::instrument(skip_all, fields(source_id, dest_id))]
+pub async fn apply_handler(
+    State(app_state): State<AppState>,
+    user: AuthenticatedUser,
+    body: Result<Json<ApplyRequest>, JsonRejection>,
+) -> Result<impl IntoResponse, AppError> {
+    let Json(mut request) = body?;
+    let span = tracing::Span::current();
+    span.record("source_id", request.source_id.as_str());
+    span.record("dest_id", request.dest_id.as_str());
+    let requested_by = user.fingerprint();
+    let api = app_state.management_client(user.access_token);
+
+    // Resolve branch references the same way preview did, so they match the token's claims
+    request.source_id = resolve_project_ref(&api, &request.source_id).await?;
+    request.dest_id = resolve_project_ref(&api, &request.dest_id).await?;
+    if request.source_id == DEFAULTS_SOURCE || request.dest_id == DEFAULTS_SOURCE {
+        return Err(AppError::BadRequest(format!(
+            "{} can only be previewed, not applied",
+            DEFAULTS_SOURCE
+        )));
+    }
+
+    request.services.retain(|selection| !selection.keys.is_empty());
+    if request.services.is_empty() {
+        return Err(AppError::BadRequest("No services selected to apply".to_string()));
+    }
+
+    for selection in &request.services {
+        applyable_service(&selection.service)?;
+    }
+
+    check_confirmation(
+        &app_state.config.confirmation_secret,
+        &api,
+        &request.confirmation_token,
+        &request.source_id,
+        &request.dest_id,
+        request
+            .services
+            .iter()
+            .map(|selection| (selection.service.as_str(), selection.keys.as_slice())),
+    )
+    .await?;
+
+    // Run the steps in dependency order rather than the order they were requested in
+    let plan = build_plan(&request.source_id, &request.dest_id, &request.services)?;
+    let ordered = plan
+        .steps
+        .into_iter()
+        .map(|step| ServiceApply {
+            delete_missing: request
+                .services
+                .iter()
+                .any(|selection| selection.service == step.service && selection.delete_missing),
+            service: step.service,
+            keys: step.keys,
+        })
+        .collect();
+    request.services = ordered;
+
+    let job_id = Uuid::new_v4().to_string();
+    let apply_id = Uuid::new_v4().to_string();
+    let now = OffsetDateTime::now_utc();
+
+    app_state.jobs.insert(MigrationJob {
+        id: job_id.clone(),
+        apply_id: apply_id.clone(),
+        source_id: request.source_id.clone(),
+        dest_id: request.dest_id.clone(),
+        status: JobStatus::Queued,
+        steps: request
+            .services
+            .iter()
+            .map(|selection| JobStep {
+                service: selection.service.clone(),
+                keys: selection.keys.clone(),
+                delete_missing: selection.delete_missing,
+                status: StepStatus::Pending,
+                error: None,
+            })
+            .collect(),
+        error: None,
+        residual_diffs: None,
+        requested_by: Some(requested_by),
+        created_at: now,
+        updated_at: now,
+    });
+
+    app_state
+        .jobs
+        .spawn(run_apply_job(app_state.clone(), api, job_id.clone()));
+
+    Ok((StatusCode::ACCEPTED, Json(JobAccepted { job_id, apply_id })))
+}
+
+// Runs every step of the job that has not succeeded yet, so the same function starts fresh
+// jobs and resumes interrupted or failed ones. The outcome is audited and sent to webhooks.
+#[tracing::instrument(skip(app_state, api))]
+pub async fn run_apply_job(app_state: AppState, api: impl ManagementApi + 'static, job_id: String) {
+    apply_job(&app_state, api, &job_id).await;
+
+    if let Some(job) = app_state.jobs.get(&job_id)
+        && job.status.is_finished()
+    {
+        app_state.audit.record(audit_entry(
+            job.requested_by.clone().unwrap_or_else(|| "unknown".to_string()),
+            AuditAction::Apply,
+            &job.apply_id,
+            Some(&job.source_id),
+            &job.dest_id,
+            job.steps.iter().map(|step| (step.service.as_str(), step.keys.len())),
+            job.error.clone(),
+        ));
+        app_state.notifier.notify(Notification::ApplyFinished {
+            job_id: job.id,
+            apply_id: job.apply_id,
+            source_id: job.source_id,
+            dest_id: job.dest_id,
+            status: job.status,
+            error: job.error,
+        });
+    }
+}
+
+async fn apply_job(app_state: &AppState, api: impl ManagementApi, job_id: &str) {
+    let jobs = &app_state.jobs;
+    let Some(job) = jobs.get(job_id) else {
+        return;
+    };
+    jobs.set_status(job_id, JobStatus::Running, None);
+
+    let selections: Vec<ServiceApply> = job
+        .steps
+        .iter()
+        .map(|step| ServiceApply {
+            service: step.service.clone(),
+            keys: step.keys.clone(),
+            delete_missing: step.delete_missing,
+        })
+        .collect();
+    let pending: Vec<usize> = job
+        .steps
+        .iter()
+        .enumerate()
+        .filter(|(_, step)| step.status != StepStatus::Succeeded)
+        .map(|(index, _)| index)
+        .collect();
+
+    // Capture the destination's current config before changing anything. A resumed job keeps
+    // the snapshot from its first run; capturing a new one would leave the steps that already
+    // succeeded out of the rollback.
+    if !app_state.apply_snapshots.contains(&job.apply_id) {
+        if pending.len() < job.steps.len() {
+            jobs.set_status(
+                job_id,
+                JobStatus::Failed,
+                Some(format!("Rollback snapshot of apply {} is missing, start a new apply instead", job.apply_id)),
+            );
+            return;
+        }
+        let pending_selections: Vec<ServiceApply> =
+            pending.iter().map(|&step| selections[step].clone()).collect();
+        match capture_snapshot(&api, &job.dest_id, &pending_selections, &job.apply_id).await {
+            Ok(snapshot) => app_state.apply_snapshots.insert(snapshot).await,
+            Err(e) => {
+                jobs.set_status(job_id, JobStatus::Failed, Some(e.to_string()));
+                return;
+            }
+        }
+    }
+
+    for step in pending {
+        let selection = &selections[step];
+        jobs.set_step_status(job_id, step, StepStatus::Running, None);
+
+        if let Err(e) = apply_service(&api, &job.source_id, &job.dest_id, selection).await {
+            jobs.set_step_status(job_id, step, StepStatus::Failed, Some(e.to_string()));
+            jobs.set_status(
+                job_id,
+                JobStatus::Failed,
+                Some(format!(
+                    "Failed to apply {} config (apply_id {} can be rolled back)",
+                    selection.service, job.apply_id
+                )),
+            );
+            return;
+        }
+
+        jobs.set_step_status(job_id, step, StepStatus::Succeeded, None);
+    }
+
+    // Re-diff so the job shows whether the destination now matches for the applied keys
+    match verify_apply(&api, &job.source_id, &job.dest_id, &selections).await {
+        Ok(residual_diffs) => jobs.update(job_id, |job| job.residual_diffs = Some(residual_diffs)),
+        Err(e) => tracing::warn!(job_id, error = %e, "post-apply verification failed"),
+    }
+
+    jobs.set_status(job_id, JobStatus::Succeeded, None);
+}
+
+async fn verify_apply(
+    api: &impl ManagementApi,
+    source_id: &str,
+    dest_id: &str,
+    selections: &[ServiceApply],
+) -> Result<Vec<ProjectConfig>, AppError> {
+    let mut residual_diffs = Vec::new();
+
+    for selection in selections {
+        let spec = applyable_service(&selection.service)?;
+        let source = fetch_service_config(api, source_id, spec).await?;
+        let dest = fetch_service_config(api, dest_id, spec).await?;
+
+        if let Some(config) = json_diff(selection.service.clone(), source, dest, &DiffOptions::for_service(&selection.service)).await?
+            && let Some(mut residual) = residual_for_keys(config, &selection.keys)
+        {
+            redact_diffs(&mut residual);
+            residual_diffs.push(residual);
+        }
+    }
+
+    Ok(residual_diffs)
+}
+
+// Keeps only the diff entries at or below one of the applied keys
+pub fn residual_for_keys(mut config: ProjectConfig, keys: &[String]) -> Option<ProjectConfig> {
+    config.diffs.retain(|diff| {
+        keys.iter().any(|key| {
+            diff.key == *key
+                || diff
+                    .key
+                    .strip_prefix(key.as_str())
+                    .is_some_and(|rest| rest.starts_with('.'))
+        })
+    });
+
+    if config.diffs.is_empty() {
+        None
+    } else {
+        Some(ProjectConfig::new(config.name, config.diffs))
+    }
+}
+
+// Checks that the confirmation token was issued for these projects, that each service's diff
+// is still the one it was issued for, and that the selected keys are part of that diff
+pub async fn check_confirmation<'a>(
+    secret: &str,
+    api: &impl ManagementApi,
+    token: &str,
+    source_id: &str,
+    dest_id: &str,
+    selections: impl Iterator<Item = (&'a str, &'a [String])>,
+) -> Result<(), AppError> {
+    let claims = verify_token(secret, token, OffsetDateTime::now_utc().unix_timestamp())?;
+    if claims.source_id != source_id || claims.dest_id != dest_id {
+        return Err(AppError::Forbidden(
+            "Confirmation token was issued for different projects".to_string(),
+        ));
+    }
+    for (service, keys) in selections {
+        let expected_hash = claims.diff_hashes.get(service).ok_or_else(|| {
+            AppError::Forbidden(format!("Confirmation token does not cover service {}", service))
+        })?;
+        let options = DiffOptions::for_service(service).with_ignore(&claims.ignore);
+        let diffs = current_diffs(api, source_id, dest_id, service, &options).await?;
+        if &diff_hash(&diffs) != expected_hash {
+            return Err(AppError::Conflict(format!(
+                "{} config changed since the preview, run the preview again",
+                service
+            )));
+        }
+        check_confirmed_keys(service, keys, &diffs, &options)?;
+    }
+    Ok(())
+}
+
+// The diff matches the confirmed one, so the keys must select entries of it. Ignored fields
+// never showed up in the preview and cannot be selected even where the diff is empty.
+pub fn check_confirmed_keys(service: &str, keys: &[String], diffs: &[DiffEntry], options: &DiffOptions) -> Result<(), AppError> {
+    for key in keys {
+        if options.ignores_key(key) {
+            return Err(AppError::Forbidden(format!(
+                "Key {} of {} was left out of the confirmed preview",
+                key, service
+            )));
+        }
+        let confirmed = diffs.iter().any(|diff| {
+            let (shorter, longer) = if diff.key.len() <= key.len() {
+                (diff.key.as_str(), key.as_str())
+            } else {
+                (key.as_str(), diff.key.as_str())
+            };
+            longer == shorter || longer.strip_prefix(shorter).is_some_and(|rest| rest.starts_with('.'))
+        });
+        if !confirmed {
+            return Err(AppError::Forbidden(format!(
+                "Key {} of {} is not part of the confirmed diff",
+                key, service
+            )));
+        }
+    }
+    Ok(())
+}
+
+async fn current_diffs(
+    api: &impl ManagementApi,
+    source_id: &str,
+    dest_id: &str,
+    service: &str,
+    options: &DiffOptions,
+) -> Result<Vec<DiffEntry>, AppError> {
+    let spec = applyable_service(service)?;
+    let source = fetch_service_config(api, source_id, spec).await?;
+    let dest = fetch_service_config(api, dest_id, spec).await?;
+
+    Ok(json_diff(service.to_string(), source, dest, options)
+        .await?
+        .map(|config| config.diffs)
+        .unwrap_or_default())
+}
+
+pub async fn capture_snapshot(
+    api: &impl ManagementApi,
+    dest_id: &str,
+    selections: &[ServiceApply],
+    apply_id: &str,
+) -> Result<ApplySnapshot, AppError> {
+    let mut snapshot = ApplySnapshot {
+        apply_id: apply_id.to_string(),
+        dest_id: dest_id.to_string(),
+        services: Vec::new(),
+        created_at: OffsetDateTime::now_utc(),
+    };
+
+    for selection in selections {
+        snapshot.services.push(ServiceSnapshot {
+            service: selection.service.clone(),
+            keys: selection.keys.clone(),
+            config: fetch_service_config(api, dest_id, applyable_service(&selection.service)?)
+                .await?,
+        });
+    }
+
+    Ok(snapshot)
+}
+
+async fn apply_service(
+    api: &impl ManagementApi,
+    source_id: &str,
+    dest_id: &str,
+    selection: &ServiceApply,
+) -> Result<(), AppError> {
+    let spec = applyable_service(&selection.service)?;
+    let source = fetch_service_config(api, source_id, spec).await?;
+    apply_config(api, FunctionSource::Project(source_id), dest_id, spec, &source, selection).await
+}
+
+// Makes the selected keys of the destination match `source`, wherever that config came from
+pub async fn apply_config(
+    api: &impl ManagementApi,
+    bodies: FunctionSource<'_>,
+    dest_id: &str,
+    spec: &ServiceSpec,
+    source: &Value,
+    selection: &ServiceApply,
+) -> Result<(), AppError> {
+    match &spec.apply {
+        ApplyStrategy::Patch(method) => {
+            let patch = build_patch(source, &selection.keys).map_err(AppError::BadRequest)?;
+            send_patch(api, method, dest_id, spec, patch).await
+        }
+        ApplyStrategy::Webhooks => {
+            let dest = fetch_service_config(api, dest_id, spec).await?;
+            apply_webhooks(api, dest_id, source, &dest, &selection.keys).await
+        }
+        ApplyStrategy::Secrets => {
+            let dest = fetch_service_config(api, dest_id, spec).await?;
+            apply_secrets(
+                api,
+                dest_id,
+                source,
+                &dest,
+                &selection.keys,
+                selection.delete_missing,
+            )
+            .await
+        }
+        ApplyStrategy::Functions => {
+            let dest = fetch_service_config(api, dest_id, spec).await?;
+            apply_functions(api, bodies, dest_id, source, &dest, &selection.keys).await
+        }
+        ApplyStrategy::Unsupported => Err(unsupported(&selection.service)),
+    }
+}
+
+// Writes the snapshot's values for the applied keys back to the destination
+pub async fn restore_service(
+    api: &impl ManagementApi,
+    dest_id: &str,
+    snapshot: &ServiceSnapshot,
+) -> Result<(), AppError> {
+    let spec = applyable_service(&snapshot.service)?;
+
+    match &spec.apply {
+        ApplyStrategy::Patch(method) => {
+            // Keys the destination did not have before the apply have nothing to go back to
+            let keys: Vec<String> = snapshot
+                .keys
+                .iter()
+                .filter(|key| field_at(&snapshot.config, key).is_some())
+                .cloned()
+                .collect();
+            if keys.is_empty() {
+                return Ok(());
+            }
+            let patch = build_patch(&snapshot.config, &keys).map_err(AppError::BadRequest)?;
+            send_patch(api, method, dest_id, spec, patch).await
+        }
+        ApplyStrategy::Webhooks => {
+            let current = fetch_service_config(api, dest_id, spec).await?;
+            restore_webhooks(api, dest_id, &snapshot.config, &current, &snapshot.keys).await
+        }
+        ApplyStrategy::Secrets => {
+            let current = fetch_service_config(api, dest_id, spec).await?;
+            restore_secrets(api, dest_id, &snapshot.config, &current, &snapshot.keys).await
+        }
+        ApplyStrategy::Functions => {
+            let current = fetch_service_config(api, dest_id, spec).await?;
+            restore_functions(api, dest_id, &snapshot.config, &current, &snapshot.keys).await
+        }
+        ApplyStrategy::Unsupported => Err(unsupported(&snapshot.service)),
+    }
+}
+
+async fn send_patch(
+    api: &impl ManagementApi,
+    method: &Method,
+    dest_id: &str,
+    spec: &ServiceSpec,
+    patch: Value,
+) -> Result<(), AppError> {
+    let patch = match spec.patch_transform {
+        Some(patch_transform) => patch_transform(patch),
+        None => patch,
+    };
+
+    api.update_config(method.clone(), dest_id, spec.path, &patch).await
+}
+
+pub fn applyable_service(service: &str) -> Result<&'static ServiceSpec, AppError> {
+    find_service(service)
+        .filter(|spec| !matches!(spec.apply, ApplyStrategy::Unsupported))
+        .ok_or_else(|| unsupported(service))
+}
+
+fn unsupported(service: &str) -> AppError {
+    AppError::BadRequest(format!("Service {} cannot be applied", service))
+}
+
+/// Builds a partial config object containing only the selected diff keys, with the values
+/// taken from `source`. A key the source does not have is an error rather than a `null`, so a
+/// typo or a destination-only field cannot wipe a live setting.
+pub fn build_patch(source: &Value, keys: &[String]) -> Result<Value, String> {
+    let mut patch = Map::new();
+
+    for key in keys {
+        if key.is_empty() || key == "root" || key.contains("id:") || key.contains('[') {
+            return Err(format!("Key {} does not address a config field", key));
+        }
+
+        let segments: Vec<&str> = key.split('.').collect();
+        let value = field_at(source, key)
+            .cloned()
+            .ok_or_else(|| format!("Key {} does not exist on the source config", key))?;
+
+        let mut target = &mut patch;
+        for segment in &segments[..segments.len() - 1] {
+            let entry = target
+                .entry(segment.to_string())
+                .or_insert_with(|| Value::Object(Map::new()));
+            if !entry.is_object() {
+                *entry = Value::Object(Map::new());
+            }
+            target = entry.as_object_mut().expect("entry was just made an object");
+        }
+        target.insert(segments[segments.len() - 1].to_string(), value);
+    }
+
+    Ok(Value::Object(patch))
+}
+
+// The value at a dotted key, if the config has it
+fn field_at<'a>(config: &'a Value, key: &str) -> Option<&'a Value> {
+    key.split('.').try_fold(config, |current, segment| current.get(segment))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_patch_selected_keys_only() {
+        let source: Value = serde_json::from_str(
+            r#"{"site_url": "https://a.com", "jwt_exp": 3600, "smtp": {"host": "mail", "port": 25}}"#,
+        )
+        .unwrap();
+        let keys = vec!["site_url".to_string(), "smtp.port".to_string()];
+
+        let patch = build_patch(&source, &keys).unwrap();
+        assert_eq!(patch, serde_json::json!({"site_url": "https://a.com", "smtp": {"port": 25}}));
+
+        // A key the source lacks would otherwise clear the destination's value
+        assert!(build_patch(&source, &["missing".to_string()]).is_err());
+        assert!(build_patch(&source, &["smtp.user".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_residual_for_keys_keeps_applied_keys_only() {
+        let entry = |key: &str| crate::models::migrate::DiffEntry {
+            key: key.to_string(),
+            change_type: crate::models::migrate::ChangeType::Modified,
+            source_value: Some("a".to_string()),
+            dest_value: Some("b".to_string()),
+            risk: crate::models::migrate::RiskLevel::Medium,
+            truncated: false,
+        };
+        let config = ProjectConfig::new(
+            "Auth".to_string(),
+            vec![entry("smtp.port"), entry("smtp_host"), entry("site_url")],
+        );
+        assert_eq!(config.summary.modified, 3);
+
+        let residual = residual_for_keys(config.clone(), &["smtp".to_string()]).unwrap();
+        assert_eq!(residual.diffs.len(), 1);
+        assert_eq!(residual.summary.total, 1);
+        assert_eq!(residual.diffs[0].key, "smtp.port");
+
+        assert!(residual_for_keys(config, &["jwt_exp".to_string()]).is_none());
+    }
+
+    #[test]
+    fn test_only_confirmed_keys_can_be_applied() {
+        let entry = |key: &str| DiffEntry {
+            key: key.to_string(),
+            change_type: crate::models::migrate::ChangeType::Modified,
+            source_value: Some("a".to_string()),
+            dest_value: Some("b".to_string()),
+            risk: crate::models::migrate::RiskLevel::Medium,
+            truncated: false,
+        };
+        let diffs = vec![entry("site_url"), entry("smtp.port"), entry("id:API_KEY.value")];
+        let options = DiffOptions::default().with_ignore(&["jwt_exp".to_string(), "smtp.host".to_string()]);
+        let check = |key: &str, diffs: &[DiffEntry]| check_confirmed_keys("Auth", &[key.to_string()], diffs, &options);
+
+        assert!(check("site_url", &diffs).is_ok());
+        assert!(check("smtp", &diffs).is_ok());
+        assert!(check("id:API_KEY", &diffs).is_ok());
+        assert!(matches!(check("disable_signup", &diffs), Err(AppError::Forbidden(_))));
+        assert!(matches!(check("site", &diffs), Err(AppError::Forbidden(_))));
+        // Ignored fields are refused even though an empty diff confirms nothing to hide
+        assert!(matches!(check("jwt_exp", &[]), Err(AppError::Forbidden(_))));
+        assert!(matches!(check("smtp.host", &diffs), Err(AppError::Forbidden(_))));
+    }
+
+    #[test]
+    fn test_build_patch_rejects_array_keys() {
+        let source = serde_json::json!([{"id": "a"}]);
+        assert!(build_patch(&source, &["id:a".to_string()]).is_err());
+        assert!(build_patch(&source, &["[0]".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,178 @@
+use crate::models::migrate::{ApplySnapshot, SealedApplySnapshot};
+use crate::storage::Records;
+use crate::token_cipher::TokenCipher;
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use time::OffsetDateTime;
+
+// Destination configs captured before each apply, by apply ID, for rollback. They hold the
+// destination's secrets, so they are only saved to storage sealed with TOKEN_ENCRYPTION_KEY;
+// without a key they live until restart.
+#[derive(Clone, Default)]
+pub struct ApplySnapshots {
+    snapshots: Arc<RwLock<HashMap<String, ApplySnapshot>>>,
+    records: Option<(Records, TokenCipher)>,
+}
+
+impl ApplySnapshots {
+    pub async fn load(records: Option<Records>, cipher: Option<TokenCipher>) -> Result<Self, String> {
+        let (Some(records), Some(cipher)) = (records, cipher) else {
+            return Ok(Self::default());
+        };
+
+        let snapshots = records
+            .load::<SealedApplySnapshot>()
+            .await?
+            .into_iter()
+            .filter_map(|sealed| match open(&cipher, &sealed) {
+                Ok(snapshot) => Some((snapshot.apply_id.clone(), snapshot)),
+                Err(e) => {
+                    tracing::warn!(apply_id = %sealed.apply_id, error = %e, "skipping unreadable rollback snapshot");
+                    None
+                }
+            })
+            .collect();
+        Ok(Self {
+            snapshots: Arc::new(RwLock::new(snapshots)),
+            records: Some((records, cipher)),
+        })
+    }
+
+    // Returns once the snapshot is written, so nothing is applied before it could be rolled back
+    pub async fn insert(&self, snapshot: ApplySnapshot) {
+        if let Some((records, cipher)) = &self.records {
+            match serde_json::to_string(&snapshot) {
+                Ok(json) => {
+                    records.save(
+                        &snapshot.apply_id,
+                        &SealedApplySnapshot {
+                            apply_id: snapshot.apply_id.clone(),
+                            created_at: snapshot.created_at,
+                            sealed: cipher.seal(&json),
+                        },
+                    );
+                    records.flush().await;
+                }
+                Err(e) => tracing::error!(apply_id = %snapshot.apply_id, error = %e, "failed to encode rollback snapshot"),
+            }
+        }
+        self.snapshots
+            .write()
+            .expect("apply snapshot lock poisoned")
+            .insert(snapshot.apply_id.clone(), snapshot);
+    }
+
+    pub fn get(&self, apply_id: &str) -> Option<ApplySnapshot> {
+        self.snapshots
+            .read()
+            .expect("apply snapshot lock poisoned")
+            .get(apply_id)
+            .cloned()
+    }
+
+    pub fn contains(&self, apply_id: &str) -> bool {
+        self.snapshots
+            .read()
+            .expect("apply snapshot lock poisoned")
+            .contains_key(apply_id)
+    }
+
+    pub fn remove(&self, apply_id: &str) {
+        self.snapshots
+            .write()
+            .expect("apply snapshot lock poisoned")
+            .remove(apply_id);
+        if let Some((records, _)) = &self.records {
+            records.delete(apply_id);
+        }
+    }
+
+    // Drops snapshots taken before the cutoff unless their apply is in keep, returning how many
+    pub fn prune(&self, cutoff: OffsetDateTime, keep: &HashSet<String>) -> usize {
+        let pruned: Vec<String> = self
+            .snapshots
+            .write()
+            .expect("apply snapshot lock poisoned")
+            .extract_if(|apply_id, snapshot| snapshot.created_at < cutoff && !keep.contains(apply_id))
+            .map(|(apply_id, _)| apply_id)
+            .collect();
+
+        if let Some((records, _)) = &self.records {
+            for apply_id in &pruned {
+                records.delete(apply_id);
+            }
+        }
+        pruned.len()
+    }
+}
+
+fn open(cipher: &TokenCipher, sealed: &SealedApplySnapshot) -> Result<ApplySnapshot, String> {
+    let json = cipher.open(&sealed.sealed)?;
+    serde_json::from_str(&json).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::migrate::ServiceSnapshot;
+    use crate::storage::{Collection, FileStorage};
+    use serde_json::json;
+
+    fn snapshot(apply_id: &str, age_hours: i64) -> ApplySnapshot {
+        ApplySnapshot {
+            apply_id: apply_id.to_string(),
+            dest_id: "dst".to_string(),
+            services: vec![ServiceSnapshot {
+                service: "Smtp".to_string(),
+                keys: vec!["smtp_pass".to_string()],
+                config: json!({"smtp_pass": "hunter2"}),
+            }],
+            created_at: OffsetDateTime::now_utc() - time::Duration::hours(age_hours),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_snapshots_sealed_reloaded_and_pruned() {
+        let dir = std::env::temp_dir().join(format!("supabasemm-apply-{}", uuid::Uuid::new_v4()));
+        let storage = Arc::new(FileStorage {
+            job_state_dir: Some(dir.clone()),
+            snapshot_dir: None,
+            audit_log_path: None,
+            profile_dir: None,
+        });
+        let cipher = TokenCipher::new(&[3; 32]);
+        let records = Records::new(storage.clone(), Collection::ApplySnapshots);
+        let snapshots = ApplySnapshots::load(Some(records.clone()), Some(cipher.clone())).await.unwrap();
+        snapshots.insert(snapshot("old", 48)).await;
+        snapshots.insert(snapshot("kept", 48)).await;
+        snapshots.insert(snapshot("recent", 1)).await;
+
+        let at_rest = std::fs::read_to_string(dir.join("apply_snapshots").join("old.json")).unwrap();
+        assert!(!at_rest.contains("hunter2"));
+
+        let cutoff = OffsetDateTime::now_utc() - time::Duration::hours(24);
+        assert_eq!(snapshots.prune(cutoff, &HashSet::from(["kept".to_string()])), 1);
+        records.flush().await;
+
+        let reloaded = ApplySnapshots::load(
+            Some(Records::new(storage.clone(), Collection::ApplySnapshots)),
+            Some(cipher),
+        )
+        .await
+        .unwrap();
+        assert!(!reloaded.contains("old"));
+        assert_eq!(reloaded.get("kept").unwrap().services[0].config, json!({"smtp_pass": "hunter2"}));
+
+        // Under another key nothing opens
+        let other_key = ApplySnapshots::load(
+            Some(Records::new(storage, Collection::ApplySnapshots)),
+            Some(TokenCipher::new(&[4; 32])),
+        )
+        .await
+        .unwrap();
+        assert!(!other_key.contains("kept"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,162 @@
+use crate::handlers::admin::require_admin;
+use crate::error::AppError;
+use crate::models::migrate::{AuditAction, AuditEntry, AuditOutcome};
+use crate::models::AppState;
+use crate::storage::Records;
+
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::Json,
+};
+use serde::Deserialize;
+use std::sync::{Arc, RwLock};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+// Every apply, import and rollback this server ran. With storage configured entries are
+// saved there as well and read back on startup, so the log outlives a restart.
+#[derive(Clone, Default)]
+pub struct AuditLog {
+    entries: Arc<RwLock<Vec<AuditEntry>>>,
+    records: Option<Records>,
+}
+
+impl AuditLog {
+    pub async fn load(records: Option<Records>) -> Result<Self, String> {
+        let Some(records) = records else {
+            return Ok(Self::default());
+        };
+
+        let entries = records.load::<AuditEntry>().await?;
+        Ok(Self {
+            entries: Arc::new(RwLock::new(entries)),
+            records: Some(records),
+        })
+    }
+
+    pub fn record(&self, entry: AuditEntry) {
+        if let Some(records) = &self.records {
+            records.save(&entry.id, &entry);
+        }
+        self.entries.write().expect("audit log lock poisoned").push(entry);
+    }
+
+    // Entries touching the project, within the time range, newest first
+    pub fn query(&self, query: &AuditQuery) -> Vec<AuditEntry> {
+        let entries = self.entries.read().expect("audit log lock poisoned");
+        entries
+            .iter()
+            .rev()
+            .filter(|entry| {
+                query.project_id.as_ref().is_none_or(|project_id| {
+                    entry.dest_id == *project_id || entry.source_id.as_ref() == Some(project_id)
+                })
+            })
+            .filter(|entry| query.from.is_none_or(|from| entry.at >= from))
+            .filter(|entry| query.to.is_none_or(|to| entry.at <= to))
+            .cloned()
+            .collect()
+    }
+}
+
+// What a change touched, as (service, number of keys) pairs, and whether it went through
+pub fn audit_entry<'a>(
+    actor: String,
+    action: AuditAction,
+    apply_id: &str,
+    source_id: Option<&str>,
+    dest_id: &str,
+    services: impl IntoIterator<Item = (&'a str, usize)>,
+    error: Option<String>,
+) -> AuditEntry {
+    let (services, keys): (Vec<String>, Vec<usize>) =
+        services.into_iter().map(|(service, keys)| (service.to_string(), keys)).unzip();
+    AuditEntry {
+        id: Uuid::new_v4().to_string(),
+        at: OffsetDateTime::now_utc(),
+        actor,
+        action,
+        apply_id: apply_id.to_string(),
+        source_id: source_id.map(str::to_string),
+        dest_id: dest_id.to_string(),
+        services,
+        changes: keys.iter().sum(),
+        outcome: if error.is_none() { AuditOutcome::Succeeded } else { AuditOutcome::Failed },
+        error,
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    // Matches entries with the project as source or destination
+    pub project_id: Option<String>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub from: Option<OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub to: Option<OffsetDateTime>,
+}
+
+// Covers every user's changes, so it is only open to admins
+pub async fn audit_handler(
+    State(app_state): State<AppState>,
+    Query(query): Query<AuditQuery>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<AuditEntry>>, AppError> {
+    require_admin(&app_state, &headers)?;
+    Ok(Json(app_state.audit.query(&query)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{Collection, FileStorage};
+    use time::Duration;
+
+    fn entry(dest_id: &str, age_hours: i64) -> AuditEntry {
+        let mut entry = audit_entry(
+            "token:abc".to_string(),
+            AuditAction::Apply,
+            "apply",
+            Some("src"),
+            dest_id,
+            [("Auth", 2), ("Postgrest", 1)],
+            None,
+        );
+        entry.at -= Duration::hours(age_hours);
+        entry
+    }
+
+    #[tokio::test]
+    async fn test_audit_log_filtered_and_reloaded() {
+        let path = std::env::temp_dir().join(format!("supabasemm-audit-{}.jsonl", Uuid::new_v4()));
+        let storage = Arc::new(FileStorage {
+            job_state_dir: None,
+            snapshot_dir: None,
+            audit_log_path: Some(path.clone()),
+            profile_dir: None,
+        });
+        let records = Records::new(storage.clone(), Collection::Audit);
+        let log = AuditLog::load(Some(records.clone())).await.unwrap();
+        log.record(entry("dst", 48));
+        log.record(entry("dst", 1));
+        log.record(entry("other", 1));
+        records.flush().await;
+
+        let reloaded = AuditLog::load(Some(Records::new(storage, Collection::Audit))).await.unwrap();
+        assert_eq!(reloaded.query(&AuditQuery::default()).len(), 3);
+
+        let recent = reloaded.query(&AuditQuery {
+            project_id: Some("dst".to_string()),
+            from: Some(OffsetDateTime::now_utc() - Duration::hours(24)),
+            to: None,
+        });
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].dest_id, "dst");
+        assert_eq!((recent[0].changes, recent[0].outcome), (3, AuditOutcome::Succeeded));
+        // The source side matches as well
+        assert_eq!(reloaded.query(&AuditQuery { project_id: Some("src".to_string()), ..Default::default() }).len(), 3);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
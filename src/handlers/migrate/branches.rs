@@ -0,0 +1,126 @@
+use crate::handlers::auth::AuthenticatedUser;
+use crate::error::AppError;
+use crate::models::migrate::{Branch, OrganizationProjects, Project};
+use crate::models::AppState;
+use crate::supabase_client::ManagementApi;
+
+use axum::{
+    extract::{Path, State},
+    response::Json,
+};
+use futures::try_join;
+
+pub async fn projects_handler(
+    State(app_state): State<AppState>,
+    user: AuthenticatedUser,
+) -> Result<Json<Vec<Project>>, AppError> {
+    let api = app_state.management_client(user.access_token);
+    api.list_projects()
+        .await
+        .map(Json)
+        .map_err(|e| e.context("Failed to list projects"))
+}
+
+pub async fn organizations_handler(
+    State(app_state): State<AppState>,
+    user: AuthenticatedUser,
+) -> Result<Json<Vec<OrganizationProjects>>, AppError> {
+    let api = app_state.management_client(user.access_token);
+    list_organizations(&api).await.map(Json)
+}
+
+// Each organization with its projects, in the order the Management API lists them
+async fn list_organizations(api: &impl ManagementApi) -> Result<Vec<OrganizationProjects>, AppError> {
+    let (organizations, projects) = try_join!(
+        async { api.list_organizations().await.map_err(|e| e.context("Failed to list organizations")) },
+        async { api.list_projects().await.map_err(|e| e.context("Failed to list projects")) }
+    )?;
+
+    Ok(organizations
+        .into_iter()
+        .map(|organization| OrganizationProjects {
+            projects: projects
+                .iter()
+                .filter(|project| project.organization_id == organization.id)
+                .cloned()
+                .collect(),
+            id: organization.id,
+            name: organization.name,
+        })
+        .collect())
+}
+
+pub async fn branches_handler(
+    State(app_state): State<AppState>,
+    Path(project_id): Path<String>,
+    user: AuthenticatedUser,
+) -> Result<Json<Vec<Branch>>, AppError> {
+    let api = app_state.management_client(user.access_token);
+    list_branches(&api, &project_id).await.map(Json)
+}
+
+async fn list_branches(api: &impl ManagementApi, project_id: &str) -> Result<Vec<Branch>, AppError> {
+    api.list_branches(project_id)
+        .await
+        .map_err(|e| e.context("Failed to list branches"))
+}
+
+// Resolves `<project_ref>/<branch name>` to the branch's own project ref. Anything else is
+// taken as a project ref, which includes branch refs copied from the branches list.
+pub async fn resolve_project_ref(api: &impl ManagementApi, id: &str) -> Result<String, AppError> {
+    let Some((parent, branch_name)) = id.split_once('/') else {
+        return Ok(id.to_string());
+    };
+
+    list_branches(api, parent)
+        .await?
+        .into_iter()
+        .find(|branch| branch.name == branch_name)
+        .map(|branch| branch.project_ref)
+        .ok_or_else(|| AppError::NotFound(format!("Branch {} not found on project {}", branch_name, parent)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::supabase_client::mock::MockApi;
+    use reqwest::Method;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_projects_grouped_by_organization() {
+        let api = MockApi::default()
+            .with_response(Method::GET, "/organizations", json!([{"id": "org-a", "name": "A"}, {"id": "org-b", "name": "B"}]))
+            .with_response(
+                Method::GET,
+                "/projects",
+                json!([
+                    {"id": "one", "name": "One", "organization_id": "org-b", "region": "us-east-1"},
+                    {"id": "two", "name": "Two", "organization_id": "org-a", "region": "eu-west-1"}
+                ]),
+            );
+
+        let organizations = list_organizations(&api).await.unwrap();
+        let grouped: Vec<(&str, Vec<&str>)> = organizations
+            .iter()
+            .map(|org| (org.id.as_str(), org.projects.iter().map(|project| project.id.as_str()).collect()))
+            .collect();
+        assert_eq!(grouped, vec![("org-a", vec!["two"]), ("org-b", vec!["one"])]);
+    }
+
+    #[tokio::test]
+    async fn test_projects_listed_with_their_organization_and_region() {
+        let api = MockApi::default().with_response(
+            Method::GET,
+            "/projects",
+            json!([{"id": "abc", "name": "App", "organization_id": "org", "region": "eu-west-1", "status": "ACTIVE_HEALTHY"}]),
+        );
+
+        let projects = api.list_projects().await.unwrap();
+        assert_eq!(projects.len(), 1);
+        assert_eq!(
+            serde_json::to_value(&projects[0]).unwrap(),
+            json!({"id": "abc", "name": "App", "organization_id": "org", "region": "eu-west-1"})
+        );
+    }
+}
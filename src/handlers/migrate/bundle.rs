@@ -0,0 +1,398 @@
+use crate::error::AppError;
+use crate::handlers::auth::AuthenticatedUser;
+use crate::handlers::migrate::apply_handler::{apply_config, applyable_service, capture_snapshot, check_confirmed_keys};
+use crate::handlers::migrate::apply_snapshots::ApplySnapshots;
+use crate::handlers::migrate::audit::audit_entry;
+use crate::handlers::migrate::confirmation::{diff_hash, issue_token, verify_token, ConfirmationClaims};
+use crate::handlers::migrate::functions::FunctionSource;
+use crate::handlers::migrate::plan::build_plan;
+use crate::handlers::migrate::preview_handler::{parse_list, truncate_diffs, DiffOptions};
+use crate::handlers::migrate::services::{find_service, ApplyStrategy, ServiceSpec, DEFAULTS_SOURCE, SERVICES};
+use crate::handlers::migrate::snapshots::{diff_snapshot, fetch_configs};
+use crate::models::migrate::{
+    ApplyResponse, ApplyResult, AuditAction, ConfigBundle, ImportPreview, ImportRequest, PreviewSummary, ProjectConfig, ServiceApply,
+};
+use crate::models::AppState;
+use crate::supabase_client::redact::{mask_sensitive_fields, MASK};
+use crate::supabase_client::ManagementApi;
+
+use axum::{
+    extract::{rejection::JsonRejection, Path, Query, State},
+    http::header::CONTENT_DISPOSITION,
+    response::{IntoResponse, Json, Response},
+};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+pub const BUNDLE_VERSION: u32 = 1;
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    // Comma-separated service names; all of them when left out
+    pub services: Option<String>,
+    // Also download each edge function's deployed bundle
+    pub function_bodies: Option<bool>,
+}
+
+// Every selected config of a project as one JSON file, for backups and version control.
+// Sensitive values are masked, so the file is safe to commit.
+#[tracing::instrument(skip_all, fields(project_id = %project_id))]
+pub async fn export_handler(
+    State(app_state): State<AppState>,
+    Path(project_id): Path<String>,
+    Query(params): Query<ExportQuery>,
+    user: AuthenticatedUser,
+) -> Result<Response, AppError> {
+    if project_id == DEFAULTS_SOURCE {
+        return Err(AppError::BadRequest(format!("{} cannot be exported", DEFAULTS_SOURCE)));
+    }
+
+    let api = app_state.management_client(user.access_token);
+    let specs = selected_specs(params.services.as_deref())?;
+    let bundle = export_bundle(&api, &project_id, specs, params.function_bodies.unwrap_or(false)).await?;
+
+    let disposition = format!("attachment; filename=\"{}-config.json\"", project_id);
+    Ok(([(CONTENT_DISPOSITION, disposition)], Json(bundle)).into_response())
+}
+
+fn selected_specs(services: Option<&str>) -> Result<Vec<&'static ServiceSpec>, AppError> {
+    let names = parse_list(services);
+    if names.is_empty() {
+        return Ok(SERVICES.iter().collect());
+    }
+
+    names
+        .iter()
+        .map(|name| find_service(name).ok_or_else(|| AppError::BadRequest(format!("Unknown service {}", name))))
+        .collect()
+}
+
+pub async fn export_bundle(
+    api: &impl ManagementApi,
+    project_id: &str,
+    specs: Vec<&'static ServiceSpec>,
+    function_bodies: bool,
+) -> Result<ConfigBundle, AppError> {
+    let mut configs = fetch_configs(api, project_id, specs.into_iter()).await?;
+
+    let mut bodies = BTreeMap::new();
+    if function_bodies && let Some(Value::Array(functions)) = configs.get("EdgeFunctions") {
+        for slug in functions.iter().filter_map(|function| function.get("slug")?.as_str()) {
+            let body = api
+                .get_function_body(project_id, slug)
+                .await
+                .map_err(|e| e.context(format!("Failed to download function {}", slug)))?;
+            bodies.insert(slug.to_string(), STANDARD.encode(body));
+        }
+    }
+
+    configs.values_mut().for_each(mask_sensitive_fields);
+    Ok(ConfigBundle {
+        version: BUNDLE_VERSION,
+        project_id: project_id.to_string(),
+        exported_at: OffsetDateTime::now_utc(),
+        configs,
+        function_bodies: bodies,
+    })
+}
+
+// Restores a bundle onto the project. Without a confirmation token the bundle is only diffed
+// against the live configs; sending the returned token back with the selected keys applies
+// them, in plan order and with a snapshot the apply can be rolled back to.
+#[tracing::instrument(skip_all, fields(project_id = %project_id))]
+pub async fn import_handler(
+    State(app_state): State<AppState>,
+    Path(project_id): Path<String>,
+    user: AuthenticatedUser,
+    body: Result<Json<ImportRequest>, JsonRejection>,
+) -> Result<Response, AppError> {
+    let Json(request) = body?;
+    let actor = user.fingerprint();
+    if project_id == DEFAULTS_SOURCE {
+        return Err(AppError::BadRequest(format!("Bundles cannot be imported into {}", DEFAULTS_SOURCE)));
+    }
+    if request.bundle.version > BUNDLE_VERSION {
+        return Err(AppError::Unprocessable(format!(
+            "Bundle version {} is newer than the supported version {}",
+            request.bundle.version, BUNDLE_VERSION
+        )));
+    }
+
+    let api = app_state.management_client(user.access_token);
+    let mut configs = import_diffs(&api, &project_id, &request.bundle).await?;
+    // Stands in for the source project in the confirmation token
+    let bundle_ref = format!("bundle:{}", bundle_digest(&request.bundle)?);
+
+    let Some(token) = &request.confirmation_token else {
+        let confirmation_expires_at =
+            OffsetDateTime::now_utc() + Duration::minutes(app_state.config.confirmation_ttl_minutes);
+        let confirmation_token = issue_token(
+            &app_state.config.confirmation_secret,
+            &ConfirmationClaims {
+                source_id: bundle_ref,
+                dest_id: project_id,
+                diff_hashes: configs
+                    .iter()
+                    .map(|config| (config.name.clone(), diff_hash(&config.diffs)))
+                    .collect(),
+                ignore: Vec::new(),
+                expires_at: confirmation_expires_at.unix_timestamp(),
+            },
+        )?;
+
+        for config in &mut configs {
+            truncate_diffs(config, app_state.config.diff_max_value_len);
+        }
+        return Ok(Json(ImportPreview {
+            summary: PreviewSummary::of(&configs),
+            configs,
+            confirmation_token,
+            confirmation_expires_at,
+        })
+        .into_response());
+    };
+
+    let claims = verify_token(
+        &app_state.config.confirmation_secret,
+        token,
+        OffsetDateTime::now_utc().unix_timestamp(),
+    )?;
+    if claims.source_id != bundle_ref || claims.dest_id != project_id {
+        return Err(AppError::Forbidden(
+            "Confirmation token was issued for a different bundle or project".to_string(),
+        ));
+    }
+
+    let mut selections = request.services.clone();
+    selections.retain(|selection| !selection.keys.is_empty());
+    if selections.is_empty() {
+        return Err(AppError::BadRequest("No services selected to import".to_string()));
+    }
+
+    for selection in &selections {
+        let expected_hash = claims.diff_hashes.get(&selection.service).ok_or_else(|| {
+            AppError::Forbidden(format!("Confirmation token does not cover service {}", selection.service))
+        })?;
+        let diffs = configs
+            .iter()
+            .find(|config| config.name == selection.service)
+            .map_or(&[][..], |config| config.diffs.as_slice());
+        if &diff_hash(diffs) != expected_hash {
+            return Err(AppError::Conflict(format!(
+                "{} config changed since the preview, run the preview again",
+                selection.service
+            )));
+        }
+        let options = DiffOptions::for_service(&selection.service).with_ignore(&claims.ignore);
+        check_confirmed_keys(&selection.service, &selection.keys, diffs, &options)?;
+    }
+
+    let apply_id = Uuid::new_v4().to_string();
+    let imported =
+        import_bundle(&api, &app_state.apply_snapshots, &project_id, &request.bundle, &selections, &apply_id).await;
+    app_state.audit.record(audit_entry(
+        actor,
+        AuditAction::Import,
+        &apply_id,
+        Some(&request.bundle.project_id),
+        &project_id,
+        selections.iter().map(|selection| (selection.service.as_str(), selection.keys.len())),
+        imported.as_ref().err().map(ToString::to_string),
+    ));
+    Ok(Json(imported?).into_response())
+}
+
+// Diffs the bundle, as the source, against the live configs. Live values are masked like the
+// bundle's, so sensitive fields only differ where one side has none.
+async fn import_diffs(
+    api: &impl ManagementApi,
+    project_id: &str,
+    bundle: &ConfigBundle,
+) -> Result<Vec<ProjectConfig>, AppError> {
+    let specs = bundle
+        .configs
+        .keys()
+        .map(|service| {
+            find_service(service)
+                .ok_or_else(|| AppError::Unprocessable(format!("Bundle has unknown service {}", service)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut live = fetch_configs(api, project_id, specs.into_iter()).await?;
+    live.values_mut().for_each(mask_sensitive_fields);
+    diff_snapshot(&live, &bundle.configs, 0).await
+}
+
+fn bundle_digest(bundle: &ConfigBundle) -> Result<String, AppError> {
+    let contents = serde_json::to_vec(&(&bundle.configs, &bundle.function_bodies))?;
+    Ok(URL_SAFE_NO_PAD.encode(Sha256::digest(contents)))
+}
+
+// Applies the selected keys from the bundle onto the project, after capturing its current
+// config so the import can be rolled back like any other apply
+pub async fn import_bundle(
+    api: &impl ManagementApi,
+    snapshots: &ApplySnapshots,
+    project_id: &str,
+    bundle: &ConfigBundle,
+    selections: &[ServiceApply],
+    apply_id: &str,
+) -> Result<ApplyResponse, AppError> {
+    for selection in selections {
+        let spec = applyable_service(&selection.service)?;
+        let source = bundle.configs.get(&selection.service).ok_or_else(|| {
+            AppError::BadRequest(format!("Bundle has no {} config", selection.service))
+        })?;
+        check_unmasked(spec, source, &selection.keys)?;
+    }
+
+    let plan = build_plan(&bundle.project_id, project_id, selections)?;
+    let ordered: Vec<ServiceApply> = plan
+        .steps
+        .into_iter()
+        .map(|step| ServiceApply {
+            delete_missing: selections
+                .iter()
+                .any(|selection| selection.service == step.service && selection.delete_missing),
+            service: step.service,
+            keys: step.keys,
+        })
+        .collect();
+
+    let snapshot = capture_snapshot(api, project_id, &ordered, apply_id).await?;
+    snapshots.insert(snapshot).await;
+
+    let mut results = Vec::new();
+    for selection in &ordered {
+        let spec = applyable_service(&selection.service)?;
+        let source = &bundle.configs[&selection.service];
+        apply_config(api, FunctionSource::Bundle(&bundle.function_bodies), project_id, spec, source, selection)
+            .await
+            .map_err(|e| {
+                e.context(format!(
+                    "Failed to import {} config (apply_id {} can be rolled back)",
+                    selection.service, apply_id
+                ))
+            })?;
+        results.push(ApplyResult {
+            service: selection.service.clone(),
+            applied_keys: selection.keys.clone(),
+        });
+    }
+
+    Ok(ApplyResponse {
+        apply_id: apply_id.to_string(),
+        results,
+    })
+}
+
+// Masked values would overwrite the real ones with the mask. Patched services can still import
+// their other keys; item-based services are rejected if any of their values is masked.
+fn check_unmasked(spec: &ServiceSpec, source: &Value, keys: &[String]) -> Result<(), AppError> {
+    let masked: Vec<&str> = match spec.apply {
+        ApplyStrategy::Patch(_) => keys
+            .iter()
+            .filter(|key| {
+                key.split('.')
+                    .try_fold(source, |current, segment| current.get(segment))
+                    .is_some_and(has_mask)
+            })
+            .map(String::as_str)
+            .collect(),
+        _ if has_mask(source) => vec![spec.name],
+        _ => Vec::new(),
+    };
+
+    if masked.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::BadRequest(format!(
+            "{} masked in the bundle and cannot be imported: {}",
+            spec.name,
+            masked.join(", ")
+        )))
+    }
+}
+
+fn has_mask(value: &Value) -> bool {
+    match value {
+        Value::String(text) => text == MASK,
+        Value::Object(map) => map.values().any(has_mask),
+        Value::Array(items) => items.iter().any(has_mask),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::supabase_client::mock::MockApi;
+    use reqwest::Method;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_export_masks_secrets_and_encodes_function_bodies() {
+        let api = MockApi::default()
+            .with_response(Method::GET, "/projects/proj/config/auth", json!({"smtp_host": "mail.dev", "smtp_pass": "hunter2"}))
+            .with_response(Method::GET, "/projects/proj/functions?page=1&limit=100", json!([{"slug": "hello", "name": "hello"}]))
+            .with_response(Method::GET, "/projects/proj/functions/hello/body", json!("eszip"));
+        let specs = selected_specs(Some("Smtp,EdgeFunctions")).unwrap();
+
+        let bundle = export_bundle(&api, "proj", specs, true).await.unwrap();
+        assert_eq!(bundle.configs["Smtp"]["smtp_host"], "mail.dev");
+        assert_ne!(bundle.configs["Smtp"]["smtp_pass"], "hunter2");
+        assert_eq!(STANDARD.decode(&bundle.function_bodies["hello"]).unwrap(), b"\"eszip\"");
+        assert!(selected_specs(Some("Nope")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_import_skips_masked_values_and_applies_selected_keys() {
+        let api = MockApi::default().with_response(
+            Method::GET,
+            "/projects/proj/config/auth",
+            json!({"security_captcha_provider": "hcaptcha", "security_captcha_secret": "live-secret", "security_captcha_enabled": false}),
+        );
+        let bundle = ConfigBundle {
+            version: BUNDLE_VERSION,
+            project_id: "exported".to_string(),
+            exported_at: OffsetDateTime::now_utc(),
+            configs: [(
+                "Captcha".to_string(),
+                json!({"security_captcha_provider": "turnstile", "security_captcha_secret": MASK, "security_captcha_enabled": true}),
+            )]
+            .into_iter()
+            .collect(),
+            function_bodies: BTreeMap::new(),
+        };
+
+        let configs = import_diffs(&api, "proj", &bundle).await.unwrap();
+        let keys: Vec<&str> = configs[0].diffs.iter().map(|diff| diff.key.as_str()).collect();
+        assert_eq!(keys.len(), 2);
+        assert!(!keys.contains(&"security_captcha_secret"));
+
+        let selection = |keys: &[&str]| ServiceApply {
+            service: "Captcha".to_string(),
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+            delete_missing: false,
+        };
+        let snapshots = ApplySnapshots::default();
+        let masked = import_bundle(&api, &snapshots, "proj", &bundle, &[selection(&["security_captcha_secret"])], "apply").await;
+        assert!(matches!(masked, Err(AppError::BadRequest(_))));
+
+        let response = import_bundle(&api, &snapshots, "proj", &bundle, &[selection(&["security_captcha_provider"])], "apply")
+            .await
+            .unwrap();
+        assert!(snapshots.contains(&response.apply_id));
+        let (method, path, body) = api.calls().pop().unwrap();
+        assert_eq!((method, path.as_str()), (Method::PATCH, "/projects/proj/config/auth"));
+        assert_eq!(body, Some(json!({"security_captcha_provider": "turnstile"})));
+    }
+}
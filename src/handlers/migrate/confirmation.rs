@@ -0,0 +1,136 @@
+use crate::error::AppError;
+use crate::models::migrate::DiffEntry;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// What a preview showed: apply is only allowed against the same projects and unchanged diffs
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ConfirmationClaims {
+    pub source_id: String,
+    pub dest_id: String,
+    pub diff_hashes: BTreeMap<String, String>,
+    // Extra fields the preview left out, so apply compares the same diff
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    pub expires_at: i64,
+}
+
+// Hashes a service's diff independent of the order entries were produced in
+pub fn diff_hash(diffs: &[DiffEntry]) -> String {
+    let mut entries: Vec<(&str, Option<&str>, Option<&str>)> = diffs
+        .iter()
+        .map(|d| (d.key.as_str(), d.source_value.as_deref(), d.dest_value.as_deref()))
+        .collect();
+    entries.sort();
+
+    let mut hasher = Sha256::new();
+    for (key, source_value, dest_value) in entries {
+        for part in [Some(key), source_value, dest_value] {
+            // A missing value hashes differently from any string, including "null"
+            hasher.update([part.is_some() as u8]);
+            let part = part.unwrap_or_default();
+            hasher.update((part.len() as u64).to_be_bytes());
+            hasher.update(part.as_bytes());
+        }
+    }
+    URL_SAFE_NO_PAD.encode(hasher.finalize())
+}
+
+pub fn issue_token(secret: &str, claims: &ConfirmationClaims) -> Result<String, AppError> {
+    let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims)?);
+    let signature = URL_SAFE_NO_PAD.encode(sign(secret, &payload).finalize().into_bytes());
+    Ok(format!("{}.{}", payload, signature))
+}
+
+pub fn verify_token(secret: &str, token: &str, now: i64) -> Result<ConfirmationClaims, AppError> {
+    let invalid = || AppError::Forbidden("Invalid confirmation token".to_string());
+
+    let (payload, signature) = token.split_once('.').ok_or_else(invalid)?;
+    let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| invalid())?;
+    sign(secret, payload)
+        .verify_slice(&signature)
+        .map_err(|_| invalid())?;
+
+    let payload = URL_SAFE_NO_PAD.decode(payload).map_err(|_| invalid())?;
+    let claims: ConfirmationClaims = serde_json::from_slice(&payload).map_err(|_| invalid())?;
+
+    if claims.expires_at < now {
+        return Err(AppError::Forbidden(
+            "Confirmation token has expired, run the preview again".to_string(),
+        ));
+    }
+
+    Ok(claims)
+}
+
+fn sign(secret: &str, payload: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::migrate::{ChangeType, RiskLevel};
+
+    fn claims() -> ConfirmationClaims {
+        ConfirmationClaims {
+            source_id: "src".to_string(),
+            dest_id: "dst".to_string(),
+            diff_hashes: BTreeMap::from([("Auth".to_string(), "hash".to_string())]),
+            ignore: Vec::new(),
+            expires_at: 1_000,
+        }
+    }
+
+    #[test]
+    fn test_token_round_trip_and_tampering() {
+        let token = issue_token("secret", &claims()).unwrap();
+
+        assert_eq!(verify_token("secret", &token, 999).unwrap(), claims());
+        assert!(verify_token("other", &token, 999).is_err());
+        assert!(verify_token("secret", &token, 1_001).is_err());
+
+        let mut forged = claims();
+        forged.dest_id = "prod".to_string();
+        let forged_payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&forged).unwrap());
+        let signature = token.split_once('.').unwrap().1;
+        assert!(verify_token("secret", &format!("{}.{}", forged_payload, signature), 999).is_err());
+    }
+
+    #[test]
+    fn test_diff_hash_ignores_entry_order() {
+        let a = DiffEntry {
+            key: "a".to_string(),
+            change_type: ChangeType::Modified,
+            source_value: Some("1".to_string()),
+            dest_value: Some("2".to_string()),
+            risk: RiskLevel::Medium,
+            truncated: false,
+        };
+        let b = DiffEntry {
+            key: "b".to_string(),
+            change_type: ChangeType::Added,
+            source_value: Some("x".to_string()),
+            dest_value: None,
+            risk: RiskLevel::Medium,
+            truncated: false,
+        };
+        let null_b = DiffEntry {
+            change_type: ChangeType::Modified,
+            dest_value: Some("null".to_string()),
+            ..b.clone()
+        };
+
+        assert_eq!(diff_hash(&[a.clone(), b.clone()]), diff_hash(&[b.clone(), a.clone()]));
+        assert_ne!(diff_hash(&[a]), diff_hash(&[]));
+        assert_ne!(diff_hash(&[b]), diff_hash(&[null_b]));
+    }
+}
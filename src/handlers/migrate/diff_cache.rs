@@ -0,0 +1,99 @@
+use crate::handlers::migrate::preview_handler::DiffOptions;
+use crate::models::migrate::ProjectConfig;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+// Computed diffs keyed by a hash of what went into them, so rendering the same preview again,
+// e.g. in another format, skips the diff. Entries hold the full diff, before it is filtered,
+// masked or truncated for display.
+#[derive(Clone)]
+pub struct DiffCache {
+    ttl: Duration,
+    entries: Arc<RwLock<HashMap<String, CachedDiff>>>,
+}
+
+struct CachedDiff {
+    // None when the configs matched
+    config: Option<ProjectConfig>,
+    stored_at: Instant,
+}
+
+impl DiffCache {
+    // A zero TTL turns caching off
+    pub fn new(ttl: Duration) -> Self {
+        DiffCache {
+            ttl,
+            entries: Default::default(),
+        }
+    }
+
+    // Hash of the service, the options that change its diff and both fetched payloads
+    pub fn key(service: &str, source: &Value, dest: &Value, options: &DiffOptions) -> String {
+        let mut hasher = Sha256::new();
+        for part in [service, &options.ignore.join(","), &options.max_depth.to_string()] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        hasher.update(source.to_string().as_bytes());
+        hasher.update([0]);
+        hasher.update(dest.to_string().as_bytes());
+        URL_SAFE_NO_PAD.encode(hasher.finalize())
+    }
+
+    pub fn get(&self, key: &str) -> Option<Option<ProjectConfig>> {
+        let entries = self.entries.read().expect("diff cache lock poisoned");
+        entries
+            .get(key)
+            .filter(|cached| cached.stored_at.elapsed() < self.ttl)
+            .map(|cached| cached.config.clone())
+    }
+
+    pub fn insert(&self, key: String, config: Option<ProjectConfig>) {
+        if self.ttl.is_zero() {
+            return;
+        }
+
+        let mut entries = self.entries.write().expect("diff cache lock poisoned");
+        entries.retain(|_, cached| cached.stored_at.elapsed() < self.ttl);
+        entries.insert(
+            key,
+            CachedDiff {
+                config,
+                stored_at: Instant::now(),
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_cache_keyed_by_content_and_options() {
+        let cache = DiffCache::new(Duration::from_secs(60));
+        let options = DiffOptions::default();
+        let key = DiffCache::key("Auth", &json!({"a": 1}), &json!({"a": 2}), &options);
+
+        assert_eq!(key, DiffCache::key("Auth", &json!({"a": 1}), &json!({"a": 2}), &options));
+        assert_ne!(key, DiffCache::key("Auth", &json!({"a": 1}), &json!({"a": 3}), &options));
+        assert_ne!(
+            key,
+            DiffCache::key("Auth", &json!({"a": 1}), &json!({"a": 2}), &options.clone().with_ignore(&["a".to_string()]))
+        );
+
+        cache.insert(key.clone(), None);
+        assert!(matches!(cache.get(&key), Some(None)));
+        assert!(cache.get("other").is_none());
+
+        let disabled = DiffCache::new(Duration::ZERO);
+        disabled.insert(key.clone(), None);
+        assert!(disabled.get(&key).is_none());
+    }
+}
@@ -0,0 +1,318 @@
+use crate::handlers::auth::AuthenticatedUser;
+use crate::error::AppError;
+use crate::handlers::migrate::services::{find_service, SERVICES};
+use crate::handlers::migrate::snapshots::{diff_snapshot, fetch_configs, fetch_masked_configs, ConfigSnapshots};
+use crate::models::migrate::{DriftStatus, Notification, ProjectConfig};
+use crate::models::AppState;
+use crate::supabase_client::ManagementApi;
+
+use axum::{extract::State, response::Json};
+use futures::try_join;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use time::OffsetDateTime;
+
+// Something the scheduler re-diffs, from DRIFT_WATCH: `<source>:<dest>` compares two projects,
+// `snapshot:<id>` a snapshot with its live project
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DriftWatch {
+    Projects { source_id: String, dest_id: String },
+    Snapshot { snapshot_id: String },
+}
+
+impl DriftWatch {
+    pub fn parse(entry: &str) -> Result<Self, String> {
+        match entry.split_once(':') {
+            Some(("snapshot", snapshot_id)) if !snapshot_id.is_empty() => Ok(DriftWatch::Snapshot {
+                snapshot_id: snapshot_id.to_string(),
+            }),
+            Some((source_id, dest_id)) if !source_id.is_empty() && !dest_id.is_empty() => Ok(DriftWatch::Projects {
+                source_id: source_id.to_string(),
+                dest_id: dest_id.to_string(),
+            }),
+            _ => Err(format!("DRIFT_WATCH entry {} is neither <source>:<dest> nor snapshot:<id>", entry)),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            DriftWatch::Projects { source_id, dest_id } => format!("{}:{}", source_id, dest_id),
+            DriftWatch::Snapshot { snapshot_id } => format!("snapshot:{}", snapshot_id),
+        }
+    }
+}
+
+// When the scheduler runs, from DRIFT_SCHEDULE: a five field cron expression
+// (`minute hour day-of-month month day-of-week`, in UTC) taking `*`, values, `a-b` ranges,
+// `/step` and comma separated lists. As in cron, a day matches if either day field does when
+// both are restricted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DriftSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl DriftSchedule {
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!("DRIFT_SCHEDULE {} does not have five fields", expression));
+        };
+        let field = |value: &str, min: u32, max: u32| {
+            parse_cron_field(value, min, max)
+                .ok_or_else(|| format!("DRIFT_SCHEDULE field {} is not valid between {} and {}", value, min, max))
+        };
+        let mut weekday_bits = field(weekdays, 0, 7)?;
+        // 7 is Sunday as well
+        if weekday_bits & (1 << 7) != 0 {
+            weekday_bits |= 1;
+        }
+        Ok(DriftSchedule {
+            minutes: field(minutes, 0, 59)?,
+            hours: field(hours, 0, 23)?,
+            days: field(days, 1, 31)?,
+            months: field(months, 1, 12)?,
+            weekdays: weekday_bits,
+            any_day: days == "*",
+            any_weekday: weekdays == "*",
+        })
+    }
+
+    // First matching minute after `after`, None if the schedule never matches (e.g. `0 0 31 2 *`)
+    pub fn next_after(&self, after: OffsetDateTime) -> Option<OffsetDateTime> {
+        let start = after.replace_second(0).ok()?.replace_nanosecond(0).ok()? + time::Duration::minutes(1);
+        // Every day/month combination comes round within a leap year cycle
+        for offset in 0..(366 * 4 + 1) {
+            let date = start.date() + time::Duration::days(offset);
+            if !self.matches_date(date) {
+                continue;
+            }
+            let first_minute = if offset == 0 { start.hour() as u32 * 60 + start.minute() as u32 } else { 0 };
+            for minute_of_day in first_minute..24 * 60 {
+                let (hour, minute) = (minute_of_day / 60, minute_of_day % 60);
+                if self.hours & (1 << hour) != 0 && self.minutes & (1 << minute) != 0 {
+                    let time = time::Time::from_hms(hour as u8, minute as u8, 0).ok()?;
+                    return Some(date.with_time(time).assume_utc());
+                }
+            }
+        }
+        None
+    }
+
+    fn matches_date(&self, date: time::Date) -> bool {
+        if self.months & (1 << u8::from(date.month())) == 0 {
+            return false;
+        }
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().number_days_from_sunday()) != 0;
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+}
+
+// Bit set of the values a cron field allows, None if it does not parse or leaves min..=max
+fn parse_cron_field(field: &str, min: u32, max: u32) -> Option<u64> {
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|step| *step > 0)?),
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (start.parse().ok()?, end.parse().ok()?),
+                // `5/15` runs from 5 to the end of the range
+                None if part.contains('/') => (range.parse().ok()?, max),
+                None => (range.parse().ok()?, range.parse().ok()?),
+            },
+        };
+        if start < min || end > max || start > end {
+            return None;
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Some(bits)
+}
+
+// Latest drift check per watch
+#[derive(Clone, Default)]
+pub struct DriftMonitor {
+    statuses: Arc<RwLock<BTreeMap<String, DriftStatus>>>,
+}
+
+impl DriftMonitor {
+    // Returns the watch's previous status
+    pub fn record(&self, status: DriftStatus) -> Option<DriftStatus> {
+        self.statuses
+            .write()
+            .expect("drift status lock poisoned")
+            .insert(status.watch.clone(), status)
+    }
+
+    pub fn statuses(&self) -> Vec<DriftStatus> {
+        self.statuses
+            .read()
+            .expect("drift status lock poisoned")
+            .values()
+            .cloned()
+            .collect()
+    }
+}
+
+// Re-diffs every watch on the configured schedule. Runs with the personal access token, since
+// there is no user session to borrow one from.
+pub fn spawn_drift_checks(app_state: AppState) {
+    let watches = app_state.config.drift_watches.clone();
+    let Some(schedule) = app_state.config.drift_schedule.clone() else {
+        return;
+    };
+    if watches.is_empty() {
+        return;
+    }
+    let Some(token) = app_state.config.personal_access_token.clone() else {
+        tracing::warn!("DRIFT_WATCH is set but drift checks need SUPABASE_ACCESS_TOKEN, not scheduling them");
+        return;
+    };
+
+    tokio::spawn(async move {
+        loop {
+            let now = OffsetDateTime::now_utc();
+            let Some(next) = schedule.next_after(now) else {
+                tracing::warn!("DRIFT_SCHEDULE never matches, stopping drift checks");
+                return;
+            };
+            tokio::time::sleep(Duration::try_from(next - now).unwrap_or_default()).await;
+            let api = app_state.management_client(token.clone());
+            for watch in &watches {
+                let status = check_drift(&api, &app_state.config_snapshots, watch).await;
+                let previous = app_state.drift.record(status.clone());
+                // Only news is sent, not the same drift on every check
+                if status.drift && previous.is_none_or(|previous| previous.changes != status.changes) {
+                    app_state.notifier.notify(Notification::DriftDetected(status));
+                }
+            }
+        }
+    });
+}
+
+#[tracing::instrument(skip_all, fields(watch = %watch.label()))]
+pub async fn check_drift(api: &impl ManagementApi, snapshots: &ConfigSnapshots, watch: &DriftWatch) -> DriftStatus {
+    let checked = drifted_configs(api, snapshots, watch).await;
+    let (configs, error) = match checked {
+        Ok(configs) => (configs, None),
+        Err(e) => {
+            tracing::warn!(error = %e, "drift check failed");
+            (Vec::new(), Some(e.to_string()))
+        }
+    };
+
+    DriftStatus {
+        watch: watch.label(),
+        checked_at: OffsetDateTime::now_utc(),
+        drift: !configs.is_empty(),
+        changes: configs.iter().map(|config| config.summary.total).sum(),
+        services: configs.into_iter().map(|config| config.name).collect(),
+        error,
+    }
+}
+
+async fn drifted_configs(
+    api: &impl ManagementApi,
+    snapshots: &ConfigSnapshots,
+    watch: &DriftWatch,
+) -> Result<Vec<ProjectConfig>, AppError> {
+    match watch {
+        DriftWatch::Projects { source_id, dest_id } => {
+            let (source, dest) = try_join!(
+                fetch_configs(api, source_id, SERVICES.iter()),
+                fetch_configs(api, dest_id, SERVICES.iter())
+            )?;
+            diff_snapshot(&dest, &source, 0).await
+        }
+        DriftWatch::Snapshot { snapshot_id } => {
+            let snapshot = snapshots.get(snapshot_id)?;
+            let specs = snapshot.configs.keys().filter_map(|service| find_service(service));
+            let live = fetch_masked_configs(api, &snapshot.project_id, specs).await?;
+            diff_snapshot(&snapshot.configs, &live, 0).await
+        }
+    }
+}
+
+pub async fn drift_status_handler(
+    State(app_state): State<AppState>,
+    _user: AuthenticatedUser,
+) -> Json<Vec<DriftStatus>> {
+    Json(app_state.drift.statuses())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::migrate::ConfigSnapshot;
+    use crate::supabase_client::mock::MockApi;
+    use serde_json::json;
+    use time::format_description::well_known::Rfc3339;
+
+    fn utc(timestamp: &str) -> OffsetDateTime {
+        OffsetDateTime::parse(timestamp, &Rfc3339).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_drift_checked_against_snapshot() {
+        assert_eq!(
+            DriftWatch::parse("abc:def").unwrap(),
+            DriftWatch::Projects { source_id: "abc".to_string(), dest_id: "def".to_string() }
+        );
+        assert!(DriftWatch::parse("abc").is_err());
+        assert!(DriftWatch::parse("snapshot:").is_err());
+
+        let hourly = DriftSchedule::parse("0 * * * *").unwrap();
+        let at = utc("2024-02-28T10:30:00Z");
+        assert_eq!(hourly.next_after(at), Some(utc("2024-02-28T11:00:00Z")));
+        let weekdays = DriftSchedule::parse("*/15 9-17 * * 1-5").unwrap();
+        // Friday evening rolls over to Monday morning
+        let friday = utc("2024-03-01T17:50:00Z");
+        assert_eq!(weekdays.next_after(friday), Some(utc("2024-03-04T09:00:00Z")));
+        let leap_day = DriftSchedule::parse("0 0 29 2 *").unwrap();
+        assert_eq!(leap_day.next_after(at), Some(utc("2024-02-29T00:00:00Z")));
+        assert_eq!(DriftSchedule::parse("0 0 31 2 *").unwrap().next_after(at), None);
+        assert!(DriftSchedule::parse("0 * * *").is_err());
+        assert!(DriftSchedule::parse("60 * * * *").is_err());
+        assert!(DriftSchedule::parse("*/0 * * * *").is_err());
+
+        let snapshots = ConfigSnapshots::default();
+        snapshots.insert(ConfigSnapshot {
+            id: "snap".to_string(),
+            project_id: "proj".to_string(),
+            name: "baseline".to_string(),
+            created_at: OffsetDateTime::now_utc(),
+            configs: [("Postgrest".to_string(), json!({"max_rows": 1000}))].into_iter().collect(),
+        });
+        let api = MockApi::default().with_response(
+            reqwest::Method::GET,
+            "/projects/proj/postgrest",
+            json!({"max_rows": 500}),
+        );
+
+        let watch = DriftWatch::parse("snapshot:snap").unwrap();
+        let status = check_drift(&api, &snapshots, &watch).await;
+        assert!(status.drift);
+        assert_eq!(status.services, vec!["Postgrest"]);
+        assert_eq!(status.error, None);
+
+        let missing = check_drift(&api, &snapshots, &DriftWatch::parse("snapshot:gone").unwrap()).await;
+        assert!(!missing.drift);
+        assert!(missing.error.is_some());
+    }
+}
@@ -0,0 +1,138 @@
+use crate::error::AppError;
+use crate::handlers::migrate::services::{find_item, selected_ids};
+use crate::supabase_client::ManagementApi;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+// Settings sent alongside the bundle when deploying, taken from the source function
+const DEPLOY_SETTINGS: [&str; 5] = ["name", "verify_jwt", "import_map", "entrypoint_path", "import_map_path"];
+
+// Where the deployed bundles come from: another project, or the base64 bodies of an imported
+// config bundle keyed by slug
+pub enum FunctionSource<'a> {
+    Project(&'a str),
+    Bundle(&'a BTreeMap<String, String>),
+}
+
+impl FunctionSource<'_> {
+    async fn body(&self, api: &impl ManagementApi, slug: &str) -> Result<Vec<u8>, AppError> {
+        match self {
+            FunctionSource::Project(source_id) => api
+                .get_function_body(source_id, slug)
+                .await
+                .map_err(|e| e.context(format!("Failed to download function {}", slug))),
+            FunctionSource::Bundle(bodies) => {
+                let body = bodies.get(slug).ok_or_else(|| {
+                    AppError::BadRequest(format!(
+                        "Bundle has no body for function {}, export it with function_bodies=true",
+                        slug
+                    ))
+                })?;
+                STANDARD
+                    .decode(body)
+                    .map_err(|e| AppError::BadRequest(format!("Body of function {} is not base64: {}", slug, e)))
+            }
+        }
+    }
+}
+
+// Deploys the selected functions from source to destination, creating the ones the
+// destination does not have yet. Destination-only functions are left alone.
+pub async fn apply_functions(
+    api: &impl ManagementApi,
+    bodies: FunctionSource<'_>,
+    dest_id: &str,
+    source: &Value,
+    dest: &Value,
+    keys: &[String],
+) -> Result<(), AppError> {
+    for slug in selected_ids(keys, &[source, dest], &["slug"]) {
+        let Some(function) = find_item(source, &["slug"], &slug) else {
+            continue;
+        };
+
+        let bundle = bodies.body(api, &slug).await?;
+
+        let exists = find_item(dest, &["slug"], &slug).is_some();
+        api.deploy_function(dest_id, &slug, exists, &deploy_query(&slug, function), bundle)
+            .await
+            .map_err(|e| e.context(format!("Failed to deploy function {}", slug)))?;
+    }
+
+    Ok(())
+}
+
+// Deletes the selected functions the apply created. The API cannot redeploy an earlier
+// bundle, so functions that existed before are reported instead of restored.
+pub async fn restore_functions(
+    api: &impl ManagementApi,
+    dest_id: &str,
+    snapshot: &Value,
+    current: &Value,
+    keys: &[String],
+) -> Result<(), AppError> {
+    let mut unrestorable = Vec::new();
+
+    for slug in selected_ids(keys, &[snapshot, current], &["slug"]) {
+        match (find_item(snapshot, &["slug"], &slug), find_item(current, &["slug"], &slug)) {
+            (None, Some(_)) => api.delete_function(dest_id, &slug).await?,
+            (Some(previous), Some(deployed)) if previous != deployed => unrestorable.push(slug),
+            _ => {}
+        }
+    }
+
+    if unrestorable.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::ApiError(format!(
+            "Previous versions of functions {} cannot be redeployed",
+            unrestorable.join(", ")
+        )))
+    }
+}
+
+fn deploy_query(slug: &str, function: &Value) -> Vec<(&'static str, String)> {
+    let mut query = vec![("slug", slug.to_string())];
+
+    for setting in DEPLOY_SETTINGS {
+        match function.get(setting) {
+            Some(Value::String(value)) => query.push((setting, value.clone())),
+            Some(Value::Bool(value)) => query.push((setting, value.to_string())),
+            _ => {}
+        }
+    }
+
+    query
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_deploy_query_uses_source_settings() {
+        let function = json!({
+            "slug": "hello",
+            "name": "hello",
+            "verify_jwt": false,
+            "import_map": true,
+            "entrypoint_path": "file:///src/index.ts",
+            "import_map_path": null,
+            "ezbr_sha256": "abc"
+        });
+
+        assert_eq!(
+            deploy_query("hello", &function),
+            vec![
+                ("slug", "hello".to_string()),
+                ("name", "hello".to_string()),
+                ("verify_jwt", "false".to_string()),
+                ("import_map", "true".to_string()),
+                ("entrypoint_path", "file:///src/index.ts".to_string()),
+            ]
+        );
+    }
+}
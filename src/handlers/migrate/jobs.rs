@@ -0,0 +1,448 @@
+use crate::handlers::migrate::apply_handler::{check_confirmation, run_apply_job};
+use crate::handlers::auth::AuthenticatedUser;
+use crate::error::AppError;
+use crate::models::migrate::{JobAccepted, JobEvent, JobStatus, JobStep, MigrationJob, ResumeJobRequest, StepStatus};
+use crate::models::AppState;
+use crate::storage::Records;
+
+use axum::{
+    extract::{rejection::JsonRejection, Path, State},
+    http::StatusCode,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json,
+    },
+};
+use futures::stream::{self, Stream, StreamExt};
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+use time::OffsetDateTime;
+use tokio::sync::{broadcast, Semaphore};
+
+const EVENT_BUFFER: usize = 64;
+
+// Tracks migration jobs and runs them on a bounded pool of background tasks. With storage
+// configured every change is saved there, so jobs outlive a restart.
+#[derive(Clone)]
+pub struct JobManager {
+    jobs: Arc<RwLock<HashMap<String, MigrationJob>>>,
+    events: Arc<RwLock<HashMap<String, broadcast::Sender<JobEvent>>>>,
+    pool: Arc<Semaphore>,
+    records: Option<Records>,
+}
+
+impl JobManager {
+    pub fn new(max_concurrent_jobs: usize) -> Self {
+        Self {
+            jobs: Default::default(),
+            events: Default::default(),
+            pool: Arc::new(Semaphore::new(max_concurrent_jobs.max(1))),
+            records: None,
+        }
+    }
+
+    // Restores persisted jobs. Anything that was queued or running when the server stopped
+    // is marked interrupted so it can be resumed.
+    pub async fn load(max_concurrent_jobs: usize, records: Option<Records>) -> Result<Self, String> {
+        let mut manager = Self::new(max_concurrent_jobs);
+        let Some(records) = records else {
+            return Ok(manager);
+        };
+
+        let loaded = records.load::<MigrationJob>().await?;
+        {
+            let mut jobs = manager.jobs.write().expect("job store lock poisoned");
+            for mut job in loaded {
+                if matches!(job.status, JobStatus::Queued | JobStatus::Running) {
+                    job.status = JobStatus::Interrupted;
+                    for step in &mut job.steps {
+                        if step.status == StepStatus::Running {
+                            step.status = StepStatus::Pending;
+                        }
+                    }
+                }
+                jobs.insert(job.id.clone(), job);
+            }
+        }
+
+        manager.records = Some(records);
+        for job_id in manager.job_ids() {
+            manager.persist(&job_id);
+        }
+        Ok(manager)
+    }
+
+    pub fn insert(&self, job: MigrationJob) {
+        let job_id = job.id.clone();
+        self.open_events(&job_id);
+        self.jobs
+            .write()
+            .expect("job store lock poisoned")
+            .insert(job.id.clone(), job);
+        self.persist(&job_id);
+    }
+
+    // Forgets finished jobs last updated before the cutoff, returning how many there were
+    pub fn prune_finished(&self, cutoff: OffsetDateTime) -> usize {
+        let pruned: Vec<String> = self
+            .jobs
+            .write()
+            .expect("job store lock poisoned")
+            .extract_if(|_, job| job.status.is_finished() && job.updated_at < cutoff)
+            .map(|(job_id, _)| job_id)
+            .collect();
+
+        if let Some(records) = &self.records {
+            for job_id in &pruned {
+                records.delete(job_id);
+            }
+        }
+        pruned.len()
+    }
+
+    // Applies that a kept job belongs to
+    pub fn apply_ids(&self) -> HashSet<String> {
+        self.jobs
+            .read()
+            .expect("job store lock poisoned")
+            .values()
+            .map(|job| job.apply_id.clone())
+            .collect()
+    }
+
+    fn job_ids(&self) -> Vec<String> {
+        self.jobs
+            .read()
+            .expect("job store lock poisoned")
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    fn open_events(&self, job_id: &str) {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER);
+        self.events
+            .write()
+            .expect("job events lock poisoned")
+            .insert(job_id.to_string(), sender);
+    }
+
+    fn persist(&self, job_id: &str) {
+        let Some(records) = &self.records else {
+            return;
+        };
+        if let Some(job) = self.get(job_id) {
+            records.save(job_id, &job);
+        }
+    }
+
+    // Puts a failed or interrupted job back in the queue; its succeeded steps are kept
+    pub fn prepare_resume(&self, job_id: &str) -> Result<MigrationJob, AppError> {
+        let job = {
+            let mut jobs = self.jobs.write().expect("job store lock poisoned");
+            let job = jobs
+                .get_mut(job_id)
+                .ok_or_else(|| AppError::NotFound(format!("No job found with id {}", job_id)))?;
+            if !job.status.is_resumable() {
+                return Err(AppError::Conflict(format!(
+                    "Job {} cannot be resumed while it is {:?}",
+                    job_id, job.status
+                )));
+            }
+
+            job.status = JobStatus::Queued;
+            job.error = None;
+            job.residual_diffs = None;
+            for step in &mut job.steps {
+                if step.status != StepStatus::Succeeded {
+                    step.status = StepStatus::Pending;
+                    step.error = None;
+                }
+            }
+            job.updated_at = OffsetDateTime::now_utc();
+            job.clone()
+        };
+
+        self.open_events(job_id);
+        self.persist(job_id);
+        Ok(job)
+    }
+
+    // The job, if the token with this fingerprint started it. Anyone else gets the same 404 as
+    // for a job that does not exist.
+    pub fn get_owned(&self, job_id: &str, requested_by: &str) -> Result<MigrationJob, AppError> {
+        self.get(job_id)
+            .filter(|job| job.requested_by.as_deref() == Some(requested_by))
+            .ok_or_else(|| AppError::NotFound(format!("No job found with id {}", job_id)))
+    }
+
+    pub fn get(&self, job_id: &str) -> Option<MigrationJob> {
+        self.jobs
+            .read()
+            .expect("job store lock poisoned")
+            .get(job_id)
+            .cloned()
+    }
+
+    pub fn update(&self, job_id: &str, f: impl FnOnce(&mut MigrationJob)) {
+        if let Some(job) = self
+            .jobs
+            .write()
+            .expect("job store lock poisoned")
+            .get_mut(job_id)
+        {
+            f(job);
+            job.updated_at = OffsetDateTime::now_utc();
+        }
+        self.persist(job_id);
+    }
+
+    pub fn set_status(&self, job_id: &str, status: JobStatus, error: Option<String>) {
+        self.update(job_id, |job| {
+            job.status = status;
+            job.error = error.clone();
+        });
+
+        if status.is_finished() {
+            self.publish(job_id, JobEvent::Done { status, error });
+            // Dropping the sender ends every open event stream once it has drained
+            self.events
+                .write()
+                .expect("job events lock poisoned")
+                .remove(job_id);
+        }
+    }
+
+    pub fn set_step_status(&self, job_id: &str, step: usize, status: StepStatus, error: Option<String>) {
+        let mut service = String::new();
+        self.update(job_id, |job| {
+            if let Some(job_step) = job.steps.get_mut(step) {
+                job_step.status = status;
+                job_step.error = error.clone();
+                service = job_step.service.clone();
+            }
+        });
+
+        let event = match status {
+            StepStatus::Pending => return,
+            StepStatus::Running => JobEvent::StepStarted { step, service },
+            StepStatus::Succeeded => JobEvent::StepSucceeded { step, service },
+            StepStatus::Failed => JobEvent::StepFailed { step, service, error },
+        };
+        self.publish(job_id, event);
+    }
+
+    pub fn subscribe(&self, job_id: &str) -> Option<broadcast::Receiver<JobEvent>> {
+        self.events
+            .read()
+            .expect("job events lock poisoned")
+            .get(job_id)
+            .map(|sender| sender.subscribe())
+    }
+
+    fn publish(&self, job_id: &str, event: JobEvent) {
+        if let Some(sender) = self
+            .events
+            .read()
+            .expect("job events lock poisoned")
+            .get(job_id)
+        {
+            // No subscribers is fine; the job state is still queryable
+            let _ = sender.send(event);
+        }
+    }
+
+    // Queues the job's work; it starts once a slot in the pool is free
+    pub fn spawn(&self, work: impl Future<Output = ()> + Send + 'static) {
+        let pool = self.pool.clone();
+        tokio::spawn(async move {
+            let _permit = pool.acquire_owned().await.expect("job pool closed");
+            work.await;
+        });
+    }
+}
+
+pub async fn job_status_handler(
+    State(app_state): State<AppState>,
+    Path(job_id): Path<String>,
+    user: AuthenticatedUser,
+) -> Result<Json<MigrationJob>, AppError> {
+    app_state.jobs.get_owned(&job_id, &user.fingerprint()).map(Json)
+}
+
+// Resumes a failed or interrupted job of the caller's. Like an apply, it needs a confirmation
+// token from a fresh preview, since the configs may have changed since the job started.
+pub async fn resume_job_handler(
+    State(app_state): State<AppState>,
+    Path(job_id): Path<String>,
+    user: AuthenticatedUser,
+    body: Result<Json<ResumeJobRequest>, JsonRejection>,
+) -> Result<impl IntoResponse, AppError> {
+    let Json(request) = body?;
+    let job = app_state.jobs.get_owned(&job_id, &user.fingerprint())?;
+    let api = app_state.management_client(user.access_token);
+
+    let pending: Vec<&JobStep> = job.steps.iter().filter(|step| step.status != StepStatus::Succeeded).collect();
+    if pending.len() < job.steps.len() && !app_state.apply_snapshots.contains(&job.apply_id) {
+        return Err(AppError::Conflict(format!(
+            "Rollback snapshot of apply {} is missing, so its applied steps could not be rolled back; start a new apply instead",
+            job.apply_id
+        )));
+    }
+    check_confirmation(
+        &app_state.config.confirmation_secret,
+        &api,
+        &request.confirmation_token,
+        &job.source_id,
+        &job.dest_id,
+        pending.iter().map(|step| (step.service.as_str(), step.keys.as_slice())),
+    )
+    .await?;
+
+    let job = app_state.jobs.prepare_resume(&job_id)?;
+
+    app_state
+        .jobs
+        .spawn(run_apply_job(app_state.clone(), api, job_id.clone()));
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(JobAccepted {
+            job_id,
+            apply_id: job.apply_id,
+        }),
+    ))
+}
+
+pub async fn job_events_handler(
+    State(app_state): State<AppState>,
+    Path(job_id): Path<String>,
+    user: AuthenticatedUser,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    // Subscribe before reading the job so no event falls between the two
+    let receiver = app_state.jobs.subscribe(&job_id);
+    let job = app_state.jobs.get_owned(&job_id, &user.fingerprint())?;
+
+    let initial = Event::default()
+        .event("status")
+        .json_data(&job)
+        .map_err(|e| AppError::ApiError(format!("Failed to encode job status: {}", e)))?;
+
+    let updates = stream::unfold((receiver, false), |(receiver, done)| async move {
+        let mut receiver = match receiver {
+            Some(receiver) if !done => receiver,
+            _ => return None,
+        };
+
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    let is_done = matches!(event, JobEvent::Done { .. });
+                    let sse_event = Event::default()
+                        .event(event.name())
+                        .json_data(&event)
+                        .unwrap_or_else(|_| Event::default().event(event.name()));
+                    return Some((Ok(sse_event), (Some(receiver), is_done)));
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    let stream = stream::once(async move { Ok(initial) }).chain(updates);
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::migrate::JobStep;
+    use crate::storage::{Collection, FileStorage};
+
+    fn test_job(id: &str) -> MigrationJob {
+        let now = OffsetDateTime::now_utc();
+        MigrationJob {
+            id: id.to_string(),
+            apply_id: "apply".to_string(),
+            source_id: "src".to_string(),
+            dest_id: "dst".to_string(),
+            status: JobStatus::Queued,
+            steps: vec![JobStep {
+                service: "Auth".to_string(),
+                keys: vec!["site_url".to_string()],
+                delete_missing: false,
+                status: StepStatus::Pending,
+                error: None,
+            }],
+            error: None,
+            residual_diffs: None,
+            requested_by: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_step_and_done_events_are_published() {
+        let jobs = JobManager::new(1);
+        jobs.insert(test_job("job1"));
+        let mut receiver = jobs.subscribe("job1").unwrap();
+
+        jobs.set_step_status("job1", 0, StepStatus::Running, None);
+        jobs.set_step_status("job1", 0, StepStatus::Succeeded, None);
+        jobs.set_status("job1", JobStatus::Succeeded, None);
+
+        assert!(matches!(receiver.recv().await.unwrap(), JobEvent::StepStarted { step: 0, .. }));
+        assert!(matches!(receiver.recv().await.unwrap(), JobEvent::StepSucceeded { step: 0, .. }));
+        assert!(matches!(
+            receiver.recv().await.unwrap(),
+            JobEvent::Done { status: JobStatus::Succeeded, .. }
+        ));
+        assert!(receiver.recv().await.is_err());
+        assert!(jobs.subscribe("job1").is_none());
+        assert_eq!(jobs.get("job1").unwrap().steps[0].status, StepStatus::Succeeded);
+    }
+
+    #[test]
+    fn test_jobs_are_only_visible_to_their_owner() {
+        let jobs = JobManager::new(1);
+        jobs.insert(MigrationJob { requested_by: Some("token:owner".to_string()), ..test_job("job1") });
+        jobs.insert(test_job("job2"));
+
+        assert!(jobs.get_owned("job1", "token:owner").is_ok());
+        assert!(matches!(jobs.get_owned("job1", "token:other"), Err(AppError::NotFound(_))));
+        assert!(matches!(jobs.get_owned("job2", "token:owner"), Err(AppError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_load_marks_unfinished_jobs_interrupted() {
+        let dir = std::env::temp_dir().join(format!("supabasemm-jobs-{}", uuid::Uuid::new_v4()));
+        let storage = Arc::new(FileStorage {
+            job_state_dir: Some(dir.clone()),
+            snapshot_dir: None,
+            audit_log_path: None,
+            profile_dir: None,
+        });
+        let records = Records::new(storage.clone(), Collection::Jobs);
+        let jobs = JobManager::load(1, Some(records.clone())).await.unwrap();
+        jobs.insert(test_job("job1"));
+        jobs.set_status("job1", JobStatus::Running, None);
+        jobs.set_step_status("job1", 0, StepStatus::Running, None);
+        records.flush().await;
+
+        let reloaded = JobManager::load(1, Some(Records::new(storage, Collection::Jobs))).await.unwrap();
+        let job = reloaded.get("job1").unwrap();
+        assert_eq!(job.status, JobStatus::Interrupted);
+        assert_eq!(job.steps[0].status, StepStatus::Pending);
+
+        let resumed = reloaded.prepare_resume("job1").unwrap();
+        assert_eq!(resumed.status, JobStatus::Queued);
+        assert!(reloaded.prepare_resume("job1").is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
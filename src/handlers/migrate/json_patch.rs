@@ -0,0 +1,163 @@
+use crate::handlers::migrate::preview_handler::{comparable_value, strategy_for, DiffOptions};
+use crate::supabase_client::redact::{is_sensitive_field, mask_sensitive_fields, MASK};
+
+use serde_json::{json, Map, Value};
+
+// RFC 6902 operations that turn the destination config into the source one, i.e. what a
+// migration would change. Which paths change is decided on the configs as the diff compares
+// them, so fields the diff leaves out and values that only differ in form produce no
+// operation. The values are the source's as fetched, not normalized, so the operations can
+// be applied as they are. With redact on, an operation whose value holds a secret carries
+// `"redacted": true` instead of a value; it is a marker only and cannot be applied until
+// the preview is run with redact off.
+pub fn service_patch(config_type: &str, source: &Value, dest: &Value, options: &DiffOptions, redact: bool) -> Vec<Value> {
+    let mut ops = Vec::new();
+    // Array indices refer to the list without platform-managed secrets, like the diff keys
+    let raw_source = strategy_for(config_type).prepare(source.clone());
+    let walk = PatchWalk { unordered: options.unordered };
+    walk.patch_values(
+        "",
+        "",
+        &comparable_value(config_type, source, options),
+        &comparable_value(config_type, dest, options),
+        &raw_source,
+        &mut ops,
+    );
+
+    if redact {
+        ops.iter_mut().for_each(redact_op);
+    }
+    ops
+}
+
+// Swaps a value holding a secret for the redacted marker
+fn redact_op(op: &mut Value) {
+    let field = op["path"].as_str().and_then(|path| path.rsplit('/').next()).unwrap_or_default().to_string();
+    let Some(value) = op.get("value") else {
+        return;
+    };
+
+    let mut masked = value.clone();
+    match &mut masked {
+        Value::String(text) if is_sensitive_field(&field) && !text.is_empty() => *text = MASK.to_string(),
+        other => mask_sensitive_fields(other),
+    }
+    if &masked != value
+        && let Some(op) = op.as_object_mut()
+    {
+        op.remove("value");
+        op.insert("redacted".to_string(), Value::Bool(true));
+    }
+}
+
+struct PatchWalk {
+    // Paths of lists whose order the diff ignores; they are compared sorted, so their items
+    // do not line up with the source's
+    unordered: &'static [&'static str],
+}
+
+impl PatchWalk {
+    // `path` is the JSON Pointer of the operation, `field_path` the dotted path the diff
+    // options use. `raw` is the source value as fetched at the same place.
+    fn patch_values(&self, path: &str, field_path: &str, source: &Value, dest: &Value, raw: &Value, ops: &mut Vec<Value>) {
+        match (source, dest) {
+            (Value::Object(src), Value::Object(dst)) => self.patch_objects(path, field_path, src, dst, raw, ops),
+            // Same-length arrays are patched item by item, anything else is swapped out whole
+            // so the indices of later operations stay valid
+            (Value::Array(src), Value::Array(dst)) if src.len() == dst.len() && !self.unordered.contains(&field_path) => {
+                for (i, (s, d)) in src.iter().zip(dst).enumerate() {
+                    let raw_item = raw.get(i).unwrap_or(s);
+                    self.patch_values(&format!("{}/{}", path, i), field_path, s, d, raw_item, ops);
+                }
+            }
+            _ if source != dest => ops.push(json!({ "op": "replace", "path": path, "value": raw })),
+            _ => {}
+        }
+    }
+
+    fn patch_objects(
+        &self,
+        path: &str,
+        field_path: &str,
+        src: &Map<String, Value>,
+        dst: &Map<String, Value>,
+        raw: &Value,
+        ops: &mut Vec<Value>,
+    ) {
+        let child_path = |key: &str| {
+            if field_path.is_empty() {
+                key.to_string()
+            } else {
+                format!("{}.{}", field_path, key)
+            }
+        };
+
+        for (key, dst_val) in dst {
+            let pointer = format!("{}/{}", path, escape(key));
+            match src.get(key) {
+                Some(src_val) => {
+                    let raw_val = raw.get(key).unwrap_or(src_val);
+                    self.patch_values(&pointer, &child_path(key), src_val, dst_val, raw_val, ops)
+                }
+                None => ops.push(json!({ "op": "remove", "path": pointer })),
+            }
+        }
+
+        for (key, src_val) in src {
+            if !dst.contains_key(key) {
+                let pointer = format!("{}/{}", path, escape(key));
+                ops.push(json!({ "op": "add", "path": pointer, "value": raw.get(key).unwrap_or(src_val) }));
+            }
+        }
+    }
+}
+
+// JSON Pointer escaping (RFC 6901)
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patch_turns_dest_into_source() {
+        let source = json!({"site_url": "https://a.dev", "mfa": true, "list": [1, 2], "a/b": 1});
+        let dest = json!({"site_url": "https://b.dev", "legacy": 1, "list": [1], "a/b": 1});
+
+        let ops = service_patch("Auth", &source, &dest, &DiffOptions::default(), true);
+        assert_eq!(
+            ops,
+            vec![
+                json!({"op": "remove", "path": "/legacy"}),
+                json!({"op": "replace", "path": "/list", "value": [1, 2]}),
+                json!({"op": "replace", "path": "/site_url", "value": "https://a.dev"}),
+                json!({"op": "add", "path": "/mfa", "value": true}),
+            ]
+        );
+        assert!(service_patch("Auth", &source, &source, &DiffOptions::default(), true).is_empty());
+
+        let ops = service_patch("Smtp", &json!({"smtp_pass": "hunter2"}), &json!({}), &DiffOptions::default(), true);
+        assert_eq!(ops, vec![json!({"op": "add", "path": "/smtp_pass", "redacted": true})]);
+        let ops = service_patch("Smtp", &json!({"smtp_pass": "hunter2"}), &json!({}), &DiffOptions::default(), false);
+        assert_eq!(ops, vec![json!({"op": "add", "path": "/smtp_pass", "value": "hunter2"})]);
+    }
+
+    #[test]
+    fn test_patch_values_are_not_normalized() {
+        let options = DiffOptions { unordered: &["schemas"], ..DiffOptions::default() };
+        let source = json!({"site_url": "https://a.dev/", "enabled": "TRUE", "schemas": ["public", "api"], "hooks": ["x"]});
+        let dest = json!({"site_url": "https://b.dev", "enabled": "true", "schemas": ["api"], "hooks": ["y"]});
+
+        let ops = service_patch("Auth", &source, &dest, &options, true);
+        assert_eq!(
+            ops,
+            vec![
+                json!({"op": "replace", "path": "/hooks/0", "value": "x"}),
+                json!({"op": "replace", "path": "/schemas", "value": ["public", "api"]}),
+                json!({"op": "replace", "path": "/site_url", "value": "https://a.dev/"}),
+            ]
+        );
+    }
+}
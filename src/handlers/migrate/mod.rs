@@ -1,3 +1,41 @@
+pub mod apply_handler;
+pub mod apply_snapshots;
+pub mod audit;
+pub mod branches;
+pub mod bundle;
+pub mod confirmation;
+pub mod diff_cache;
+pub mod drift;
+pub mod functions;
+pub mod jobs;
+pub mod json_patch;
+pub mod normalize;
+pub mod notify;
+pub mod plan;
+pub mod preferences;
+pub mod render;
 pub mod preview_handler;
+pub mod profiles;
+pub mod rollback_handler;
+pub mod secrets;
+pub mod services;
+pub mod snapshots;
+pub mod strategy;
+pub mod tree;
+pub mod webhooks;
 
-pub use preview_handler::preview_handler;
\ No newline at end of file
+pub use apply_handler::apply_handler;
+pub use audit::audit_handler;
+pub use bundle::{export_handler, import_handler};
+pub use branches::{branches_handler, organizations_handler, projects_handler};
+pub use drift::drift_status_handler;
+pub use jobs::{job_events_handler, job_status_handler, resume_job_handler};
+pub use notify::{create_webhook_handler, delete_webhook_handler, list_webhooks_handler};
+pub use plan::plan_handler;
+pub use preferences::{get_preferences_handler, update_preferences_handler};
+pub use preview_handler::{preview_handler, preview_post_handler, preview_report_handler, preview_value_handler};
+pub use profiles::{
+    delete_profile_handler, get_profile_handler, list_profiles_handler, preview_profile_handler, save_profile_handler,
+};
+pub use rollback_handler::rollback_handler;
+pub use snapshots::{create_snapshot_handler, drift_handler, list_snapshots_handler, snapshot_diff_handler};
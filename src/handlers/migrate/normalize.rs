@@ -0,0 +1,97 @@
+use serde_json::Value;
+use time::format_description::well_known::Rfc3339;
+use time::{OffsetDateTime, UtcOffset};
+
+// Rewrites values that mean the same thing into one form, so they compare equal: lists of
+// plain values at the `unordered` paths are sorted, timestamps moved to UTC, trailing slashes
+// dropped from URLs and "TRUE"/"False" style flags lowercased. Paths are dotted from the
+// config root, without array positions; every other list keeps its order.
+pub fn normalize(value: Value, unordered: &[&str]) -> Value {
+    normalize_at("", value, unordered)
+}
+
+fn normalize_at(path: &str, value: Value, unordered: &[&str]) -> Value {
+    match value {
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .map(|(key, field)| {
+                    let field_path = if path.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{}.{}", path, key)
+                    };
+                    let field = normalize_at(&field_path, field, unordered);
+                    (key, field)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => {
+            let mut items: Vec<Value> = items.into_iter().map(|item| normalize_at(path, item, unordered)).collect();
+            if unordered.contains(&path) && items.iter().all(|item| !item.is_object() && !item.is_array()) {
+                items.sort_by_key(|item| item.to_string());
+            }
+            Value::Array(items)
+        }
+        Value::String(text) => Value::String(normalize_string(text)),
+        other => other,
+    }
+}
+
+fn normalize_string(text: String) -> String {
+    if text.eq_ignore_ascii_case("true") || text.eq_ignore_ascii_case("false") {
+        return text.to_ascii_lowercase();
+    }
+
+    if let Ok(timestamp) = OffsetDateTime::parse(&text, &Rfc3339)
+        && let Ok(utc) = timestamp.to_offset(UtcOffset::UTC).format(&Rfc3339)
+    {
+        return utc;
+    }
+
+    if let Some(rest) = text.strip_prefix("https://").or_else(|| text.strip_prefix("http://"))
+        && rest.ends_with('/')
+        && !rest.trim_end_matches('/').is_empty()
+    {
+        return text.trim_end_matches('/').to_string();
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_equivalent_values_normalize_alike() {
+        let source = json!({
+            "schemas": ["public", "api"],
+            "site_url": "https://example.com/",
+            "enabled": "TRUE",
+            "updated": "2025-01-01T02:00:00+02:00",
+            "rules": [{"b": 1}, {"a": 1}]
+        });
+        let dest = json!({
+            "schemas": ["api", "public"],
+            "site_url": "https://example.com",
+            "enabled": "true",
+            "updated": "2025-01-01T00:00:00Z",
+            "rules": [{"b": 1}, {"a": 1}]
+        });
+
+        assert_eq!(normalize(source, &["schemas"]), normalize(dest, &["schemas"]));
+        assert_eq!(normalize(json!("https://"), &[]), json!("https://"));
+        assert_ne!(normalize(json!([{"a": 1}, {"b": 1}]), &[""]), normalize(json!([{"b": 1}, {"a": 1}]), &[""]));
+    }
+
+    #[test]
+    fn test_only_unordered_paths_are_sorted() {
+        let config = json!([{"name": "avatars", "allowed_mime_types": ["image/png", "image/jpeg"], "order": ["b", "a"]}]);
+
+        let normalized = normalize(config, &["allowed_mime_types"]);
+        assert_eq!(normalized[0]["allowed_mime_types"], json!(["image/jpeg", "image/png"]));
+        assert_eq!(normalized[0]["order"], json!(["b", "a"]));
+    }
+}
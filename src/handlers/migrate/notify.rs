@@ -0,0 +1,171 @@
+use crate::handlers::admin::require_admin;
+use crate::error::AppError;
+use crate::models::migrate::{CreateWebhookRequest, Notification, Webhook};
+use crate::models::AppState;
+
+use axum::{
+    extract::{rejection::JsonRejection, Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use reqwest::Url;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use uuid::Uuid;
+
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Sends drift and apply events to the registered webhook URLs. URLs from NOTIFY_WEBHOOK_URLS
+// are always there; the rest are added and removed through the admin API and only live until
+// restart.
+#[derive(Clone)]
+pub struct Notifier {
+    http: reqwest::Client,
+    webhooks: Arc<RwLock<Vec<Webhook>>>,
+}
+
+impl Notifier {
+    pub fn new(http: reqwest::Client, urls: &[String]) -> Result<Self, String> {
+        let webhooks = urls
+            .iter()
+            .enumerate()
+            .map(|(i, url)| {
+                check_url(url)
+                    .map(|url| Webhook {
+                        id: format!("env-{}", i + 1),
+                        url,
+                        from_env: true,
+                    })
+                    .map_err(|e| format!("NOTIFY_WEBHOOK_URLS: {}", e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Notifier {
+            http,
+            webhooks: Arc::new(RwLock::new(webhooks)),
+        })
+    }
+
+    pub fn register(&self, url: &str) -> Result<Webhook, AppError> {
+        let webhook = Webhook {
+            id: Uuid::new_v4().to_string(),
+            url: check_url(url).map_err(AppError::BadRequest)?,
+            from_env: false,
+        };
+        self.webhooks
+            .write()
+            .expect("webhook lock poisoned")
+            .push(webhook.clone());
+        Ok(webhook)
+    }
+
+    pub fn list(&self) -> Vec<Webhook> {
+        self.webhooks.read().expect("webhook lock poisoned").clone()
+    }
+
+    pub fn remove(&self, webhook_id: &str) -> Result<(), AppError> {
+        let mut webhooks = self.webhooks.write().expect("webhook lock poisoned");
+        match webhooks.iter().position(|webhook| webhook.id == webhook_id) {
+            Some(index) if webhooks[index].from_env => Err(AppError::Conflict(format!(
+                "Webhook {} comes from NOTIFY_WEBHOOK_URLS and cannot be removed",
+                webhook_id
+            ))),
+            Some(index) => {
+                webhooks.remove(index);
+                Ok(())
+            }
+            None => Err(AppError::NotFound(format!("No webhook found with id {}", webhook_id))),
+        }
+    }
+
+    // Posts the event to every webhook in the background. Failed deliveries are logged, not retried.
+    pub fn notify(&self, notification: Notification) {
+        for webhook in self.list() {
+            let http = self.http.clone();
+            let notification = notification.clone();
+            tokio::spawn(async move {
+                let result = http
+                    .post(&webhook.url)
+                    .timeout(DELIVERY_TIMEOUT)
+                    .json(&notification)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status());
+                if let Err(e) = result {
+                    tracing::warn!(webhook_id = %webhook.id, error = %e, "failed to notify webhook");
+                }
+            });
+        }
+    }
+}
+
+fn check_url(url: &str) -> Result<String, String> {
+    let parsed = Url::parse(url).map_err(|e| format!("{} is not a valid URL: {}", url, e))?;
+    if !["http", "https"].contains(&parsed.scheme()) {
+        return Err(format!("{} is not an http(s) URL", url));
+    }
+    Ok(parsed.to_string())
+}
+
+pub async fn list_webhooks_handler(
+    State(app_state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<Webhook>>, AppError> {
+    require_admin(&app_state, &headers)?;
+    Ok(Json(app_state.notifier.list()))
+}
+
+pub async fn create_webhook_handler(
+    State(app_state): State<AppState>,
+    headers: HeaderMap,
+    body: Result<Json<CreateWebhookRequest>, JsonRejection>,
+) -> Result<(StatusCode, Json<Webhook>), AppError> {
+    let Json(request) = body?;
+    require_admin(&app_state, &headers)?;
+    let webhook = app_state.notifier.register(&request.url)?;
+    Ok((StatusCode::CREATED, Json(webhook)))
+}
+
+pub async fn delete_webhook_handler(
+    State(app_state): State<AppState>,
+    Path(webhook_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<StatusCode, AppError> {
+    require_admin(&app_state, &headers)?;
+    app_state.notifier.remove(&webhook_id)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::migrate::JobStatus;
+    use serde_json::json;
+
+    #[test]
+    fn test_webhooks_registered_and_removed() {
+        let notifier = Notifier::new(reqwest::Client::new(), &["https://hooks.example.com/env".to_string()]).unwrap();
+        assert!(Notifier::new(reqwest::Client::new(), &["ftp://example.com".to_string()]).is_err());
+
+        let webhook = notifier.register("https://hooks.example.com/drift").unwrap();
+        assert!(matches!(notifier.register("not a url"), Err(AppError::BadRequest(_))));
+        assert_eq!(notifier.list().len(), 2);
+
+        assert!(matches!(notifier.remove("env-1"), Err(AppError::Conflict(_))));
+        notifier.remove(&webhook.id).unwrap();
+        assert!(matches!(notifier.remove(&webhook.id), Err(AppError::NotFound(_))));
+        assert_eq!(notifier.list().len(), 1);
+
+        let payload = serde_json::to_value(Notification::ApplyFinished {
+            job_id: "job".to_string(),
+            apply_id: "apply".to_string(),
+            source_id: "src".to_string(),
+            dest_id: "dst".to_string(),
+            status: JobStatus::Failed,
+            error: Some("boom".to_string()),
+        })
+        .unwrap();
+        assert_eq!(payload["event"], json!("apply_finished"));
+        assert_eq!(payload["status"], json!("failed"));
+    }
+}
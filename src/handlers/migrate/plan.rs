@@ -0,0 +1,108 @@
+use crate::error::AppError;
+use crate::handlers::migrate::preview_handler::PreviewQuery;
+use crate::handlers::migrate::services::find_service;
+use crate::models::migrate::{MigrationPlan, PlanStep, ServiceApply};
+
+use axum::{extract::Query, response::Json};
+
+// Services that must be migrated before the given one
+pub fn service_dependencies(service: &str) -> &'static [&'static str] {
+    find_service(service)
+        .map(|spec| spec.depends_on)
+        .unwrap_or_default()
+}
+
+// Orders the selected services so each runs after the selected services it depends on.
+// Services without a dependency between them keep their requested order.
+pub fn build_plan(
+    source_id: &str,
+    dest_id: &str,
+    services: &[ServiceApply],
+) -> Result<MigrationPlan, AppError> {
+    let selected: Vec<&str> = services.iter().map(|s| s.service.as_str()).collect();
+    let mut remaining: Vec<&ServiceApply> = services.iter().collect();
+    let mut steps: Vec<PlanStep> = Vec::new();
+
+    while !remaining.is_empty() {
+        let ready = remaining.iter().position(|candidate| {
+            service_dependencies(&candidate.service)
+                .iter()
+                .filter(|dependency| selected.contains(dependency))
+                .all(|dependency| steps.iter().any(|step| step.service == *dependency))
+        });
+
+        let Some(index) = ready else {
+            return Err(AppError::BadRequest(
+                "Selected services have a circular dependency".to_string(),
+            ));
+        };
+
+        let service = remaining.remove(index);
+        steps.push(PlanStep {
+            order: steps.len(),
+            service: service.service.clone(),
+            keys: service.keys.clone(),
+            depends_on: service_dependencies(&service.service)
+                .iter()
+                .filter(|dependency| selected.contains(dependency))
+                .map(|dependency| dependency.to_string())
+                .collect(),
+        });
+    }
+
+    Ok(MigrationPlan {
+        source_id: source_id.to_string(),
+        dest_id: dest_id.to_string(),
+        steps,
+    })
+}
+
+pub async fn plan_handler(Query(params): Query<PreviewQuery>) -> Result<Json<MigrationPlan>, AppError> {
+    let services: Vec<ServiceApply> = params
+        .selected_services()
+        .into_iter()
+        .map(|service| ServiceApply {
+            service: service.to_string(),
+            keys: Vec::new(),
+            delete_missing: false,
+        })
+        .collect();
+
+    build_plan(&params.source_id, &params.dest_id, &services).map(Json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(name: &str) -> ServiceApply {
+        ServiceApply {
+            service: name.to_string(),
+            keys: Vec::new(),
+            delete_missing: false,
+        }
+    }
+
+    #[test]
+    fn test_plan_orders_dependencies_first() {
+        let plan = build_plan(
+            "src",
+            "dst",
+            &[service("EdgeFunctions"), service("Auth"), service("Secrets")],
+        )
+        .unwrap();
+
+        let order: Vec<&str> = plan.steps.iter().map(|s| s.service.as_str()).collect();
+        assert_eq!(order, vec!["Auth", "Secrets", "EdgeFunctions"]);
+        assert_eq!(plan.steps[2].depends_on, vec!["Secrets".to_string()]);
+        assert_eq!(plan.steps[2].order, 2);
+    }
+
+    #[test]
+    fn test_plan_ignores_unselected_dependencies() {
+        let plan = build_plan("src", "dst", &[service("EdgeFunctions")]).unwrap();
+
+        assert_eq!(plan.steps.len(), 1);
+        assert!(plan.steps[0].depends_on.is_empty());
+    }
+}
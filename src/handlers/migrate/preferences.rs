@@ -0,0 +1,73 @@
+use crate::error::AppError;
+use crate::handlers::migrate::preview_handler::{requested_services, FORMATS, OUTPUTS};
+use crate::models::migrate::Preferences;
+
+use axum::{extract::rejection::JsonRejection, response::Json};
+use tower_sessions::Session;
+
+const PREFERENCES_KEY: &str = "preview_preferences";
+
+// The session's preferences, all unset until some are saved
+pub async fn stored_preferences(session: &Session) -> Result<Preferences, AppError> {
+    Ok(session.get(PREFERENCES_KEY).await?.unwrap_or_default())
+}
+
+pub async fn get_preferences_handler(session: Session) -> Result<Json<Preferences>, AppError> {
+    Ok(Json(stored_preferences(&session).await?))
+}
+
+// Replaces the preferences; options left out of the body fall back to the built-in defaults
+pub async fn update_preferences_handler(
+    session: Session,
+    body: Result<Json<Preferences>, JsonRejection>,
+) -> Result<Json<Preferences>, AppError> {
+    let Json(mut preferences) = body?;
+    if !preferences.services.is_empty() {
+        preferences.services = requested_services(&preferences.services)?
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+    }
+    if let Some(output) = preferences.output.as_deref()
+        && !OUTPUTS.contains(&output)
+    {
+        return Err(AppError::Unprocessable(format!("Unknown output format {}", output)));
+    }
+    if let Some(format) = preferences.format.as_deref()
+        && !FORMATS.contains(&format)
+    {
+        return Err(AppError::Unprocessable(format!("Unknown format {}", format)));
+    }
+
+    session.insert(PREFERENCES_KEY, &preferences).await?;
+    Ok(Json(preferences))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Arc;
+    use tower_sessions::MemoryStore;
+
+    #[tokio::test]
+    async fn test_preferences_validated_and_kept_in_session() {
+        let session = Session::new(None, Arc::new(MemoryStore::default()), None);
+        assert_eq!(stored_preferences(&session).await.unwrap(), Preferences::default());
+
+        let update = |body| update_preferences_handler(session.clone(), Ok(Json(serde_json::from_value(body).unwrap())));
+        assert!(matches!(update(json!({"services": ["Auht"]})).await, Err(AppError::Unprocessable(_))));
+        assert!(matches!(update(json!({"format": "pdf"})).await, Err(AppError::Unprocessable(_))));
+        assert!(serde_json::from_value::<Preferences>(json!({"redcat": false})).is_err());
+
+        let Json(saved) = update(json!({"services": ["Auth", "Buckets"], "format": "markdown", "redact": false}))
+            .await
+            .unwrap();
+        let Json(preferences) = get_preferences_handler(session).await.unwrap();
+        assert_eq!(preferences, saved);
+        assert_eq!(preferences.services, vec!["Auth", "Buckets"]);
+        assert_eq!(preferences.format.as_deref(), Some("markdown"));
+        assert_eq!(preferences.output, None);
+        assert_eq!(preferences.redact, Some(false));
+    }
+}
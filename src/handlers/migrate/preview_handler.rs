@@ -1,18 +1,36 @@
-use crate::models::migrate::{ProjectConfig, DiffEntry};
+use crate::error::AppError;
+use crate::handlers::auth::AuthenticatedUser;
+use crate::handlers::migrate::branches::resolve_project_ref;
+use crate::handlers::migrate::confirmation::{diff_hash, issue_token, ConfirmationClaims};
+use crate::handlers::migrate::diff_cache::DiffCache;
+use crate::handlers::migrate::json_patch::service_patch;
+use crate::handlers::migrate::normalize::normalize;
+use crate::handlers::migrate::plan::build_plan;
+use crate::handlers::migrate::preferences::stored_preferences;
+use crate::handlers::migrate::render::{html_report, markdown_report, unified_diff};
+use crate::handlers::migrate::strategy::{DiffStrategy, Recursive};
+use crate::handlers::migrate::tree::diff_tree;
+use crate::handlers::migrate::services::{assess_risk, fetch_service_config, find_service, item_id, DEFAULTS_SOURCE};
+use crate::models::migrate::{ChangeType, DiffNode, MigrationPlan, ProjectConfig, DiffEntry, PreviewSummary, PreviewWarning, RiskLevel, ServiceApply};
 use crate::models::AppState;
+use crate::supabase_client::redact::redact_diff_value;
 
 use axum::{
-    extract::{Query, State},
-    http::StatusCode,
-    response::{IntoResponse, Json},
+    extract::{rejection::JsonRejection, Query, State},
+    http::header::CONTENT_TYPE,
+    response::{Html, IntoResponse, Json, Response},
 };
+use futures::future::try_join_all;
+use futures::try_join;
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
-use std::collections::HashMap;
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+use std::collections::{BTreeMap, HashMap};
+use time::{Duration, OffsetDateTime};
 use tower_sessions::Session;
 
 // Define the query parameters for the endpoint
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 pub struct PreviewQuery {
     pub source_id: String,
     pub dest_id: String,
@@ -21,198 +39,659 @@ pub struct PreviewQuery {
     pub edge_functions: Option<bool>,
     pub secrets: Option<bool>,
     pub postgres: Option<bool>,
+    pub storage: Option<bool>,
+    pub buckets: Option<bool>,
+    pub realtime: Option<bool>,
+    pub network_restrictions: Option<bool>,
+    pub network_bans: Option<bool>,
+    pub ssl_enforcement: Option<bool>,
+    pub webhooks: Option<bool>,
+    pub pooler: Option<bool>,
+    pub backups: Option<bool>,
+    pub read_replicas: Option<bool>,
+    pub addons: Option<bool>,
+    pub postgres_version: Option<bool>,
+    pub email_templates: Option<bool>,
+    pub sms: Option<bool>,
+    pub sso: Option<bool>,
+    pub oauth_providers: Option<bool>,
+    pub auth_hooks: Option<bool>,
+    pub mfa: Option<bool>,
+    pub captcha: Option<bool>,
+    pub smtp: Option<bool>,
+    // Skip cached Management API responses and fetch everything fresh
+    pub refresh: Option<bool>,
+    // "json_patch" adds an RFC 6902 patch per service next to the diff, "tree" the diff
+    // nested by key sections
+    pub output: Option<String>,
+    // Comma-separated fields to leave out of the diff on top of each service's defaults
+    pub ignore: Option<String>,
+    // "text" returns a unified diff of each service's config as plain text instead of JSON,
+    // "markdown" a report with a table per service and "html" a side-by-side page
+    pub format: Option<String>,
+    // Comma-separated key prefixes; only diff entries under one of them are returned
+    pub filter: Option<String>,
+    // Longest diff value returned, overriding DIFF_MAX_VALUE_LEN; 0 returns values whole
+    pub max_value_len: Option<usize>,
+    // false returns sensitive values unmasked
+    pub redact: Option<bool>,
+}
+
+pub const OUTPUTS: [&str; 3] = ["diff", "json_patch", "tree"];
+pub const FORMATS: [&str; 4] = ["json", "text", "markdown", "html"];
+
+impl PreviewQuery {
+    pub fn selected_services(&self) -> Vec<&'static str> {
+        [
+            ("Auth", self.auth),
+            ("Postgrest", self.postgrest),
+            ("EdgeFunctions", self.edge_functions),
+            ("Secrets", self.secrets),
+            ("Postgres", self.postgres),
+            ("Storage", self.storage),
+            ("Buckets", self.buckets),
+            ("Realtime", self.realtime),
+            ("NetworkRestrictions", self.network_restrictions),
+            ("NetworkBans", self.network_bans),
+            ("SslEnforcement", self.ssl_enforcement),
+            ("Webhooks", self.webhooks),
+            ("Pooler", self.pooler),
+            ("Backups", self.backups),
+            ("ReadReplicas", self.read_replicas),
+            ("Addons", self.addons),
+            // Compatibility pre-check, on unless turned off
+            ("PostgresVersion", self.postgres_version.or(Some(true))),
+            // These used to be part of the Auth diff, so they follow it unless set explicitly
+            ("EmailTemplates", self.email_templates.or(self.auth)),
+            ("Sms", self.sms.or(self.auth)),
+            ("OAuthProviders", self.oauth_providers.or(self.auth)),
+            ("AuthHooks", self.auth_hooks.or(self.auth)),
+            ("Mfa", self.mfa.or(self.auth)),
+            ("Captcha", self.captcha.or(self.auth)),
+            ("Smtp", self.smtp.or(self.auth)),
+            ("Sso", self.sso),
+        ]
+        .into_iter()
+        .filter(|(_, selected)| selected.unwrap_or(false))
+        .map(|(service, _)| service)
+        .collect()
+    }
+
+    // Whether the query picks services itself rather than leaving them to the preferences
+    pub fn names_services(&self) -> bool {
+        [
+            self.auth,
+            self.postgrest,
+            self.edge_functions,
+            self.secrets,
+            self.postgres,
+            self.storage,
+            self.buckets,
+            self.realtime,
+            self.network_restrictions,
+            self.network_bans,
+            self.ssl_enforcement,
+            self.webhooks,
+            self.pooler,
+            self.backups,
+            self.read_replicas,
+            self.addons,
+            self.postgres_version,
+            self.email_templates,
+            self.sms,
+            self.oauth_providers,
+            self.auth_hooks,
+            self.mfa,
+            self.captcha,
+            self.smtp,
+            self.sso,
+        ]
+        .iter()
+        .any(Option::is_some)
+    }
+}
+
+// Body of POST /preview: the services by name plus the options GET takes as query parameters
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PreviewRequest {
+    pub source_id: String,
+    pub dest_id: String,
+    // Left out, the preferred services are diffed
+    #[serde(default)]
+    pub services: Vec<String>,
+    #[serde(default)]
+    pub options: PreviewOptions,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PreviewOptions {
+    pub refresh: Option<bool>,
+    pub output: Option<String>,
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    pub format: Option<String>,
+    #[serde(default)]
+    pub filter: Vec<String>,
+    pub max_value_len: Option<usize>,
+    pub redact: Option<bool>,
 }
 
 // Define the response structure
 #[derive(Debug, Serialize)]
 pub struct PreviewResponse {
     pub configs: Vec<ProjectConfig>,
+    pub summary: PreviewSummary,
+    pub plan: MigrationPlan,
+    pub warnings: Vec<PreviewWarning>,
+    pub confirmation_token: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub confirmation_expires_at: OffsetDateTime,
+    // Per service, the JSON Patch that turns the destination config into the source one.
+    // Operations marked redacted carry no value and cannot be applied as they are.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub json_patch: Option<BTreeMap<String, Vec<Value>>>,
+    // Per service, the diff nested by key sections
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tree: Option<BTreeMap<String, DiffNode>>,
 }
 
-// Define error response
-#[derive(Debug, Serialize)]
-pub struct ErrorResponse {
-    pub error: String,
-}
-
-// Custom error type for this endpoint
-#[derive(Debug)]
-pub enum PreviewError {
-    Unauthorized,
-    ApiError(String),
-    JsonError(serde_json::Error),
-    SessionError(String),
-}
-
-impl IntoResponse for PreviewError {
-    fn into_response(self) -> axum::response::Response {
-        let (status, error_message) = match self {
-            PreviewError::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized".to_string()),
-            PreviewError::ApiError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
-            PreviewError::JsonError(err) => (StatusCode::BAD_REQUEST, format!("JSON error: {}", err)),
-            PreviewError::SessionError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, format!("Session error: {}", msg)),
-        };
+// The preview as a standalone HTML page, for sharing with reviewers
+pub async fn preview_report_handler(
+    state: State<AppState>,
+    Query(mut params): Query<PreviewQuery>,
+    user: AuthenticatedUser,
+    session: Session,
+) -> Result<Response, AppError> {
+    params.format = Some("html".to_string());
+    preview_handler(state, Query(params), user, session).await
+}
 
-        let body = Json(ErrorResponse {
-            error: error_message,
-        });
+#[derive(Debug, Deserialize)]
+pub struct PreviewValueQuery {
+    pub source_id: String,
+    pub dest_id: String,
+    pub service: String,
+    pub key: String,
+    // Same as the preview's, so the key refers to the same entry
+    pub ignore: Option<String>,
+}
 
-        (status, body).into_response()
+// One diff entry with its values whole, for entries a preview returned truncated. Sensitive
+// values stay masked.
+pub async fn preview_value_handler(
+    State(app_state): State<AppState>,
+    Query(mut params): Query<PreviewValueQuery>,
+    user: AuthenticatedUser,
+) -> Result<Json<DiffEntry>, AppError> {
+    let api = app_state.management_client(user.access_token).read_cache(true);
+
+    if params.dest_id == DEFAULTS_SOURCE {
+        return Err(AppError::BadRequest(format!("{} can only be used as the source", DEFAULTS_SOURCE)));
     }
+    params.source_id = resolve_project_ref(&api, &params.source_id).await?;
+    params.dest_id = resolve_project_ref(&api, &params.dest_id).await?;
+
+    let spec = find_service(&params.service)
+        .ok_or_else(|| AppError::BadRequest(format!("Unknown service {}", params.service)))?;
+    let (source, dest) = try_join!(
+        fetch_service_config(&api, &params.source_id, spec),
+        fetch_service_config(&api, &params.dest_id, spec)
+    )?;
+
+    let options = DiffOptions::for_service(spec.name).with_ignore(&parse_list(params.ignore.as_deref()));
+    let diffs = calculate_diff(spec.name, &source, &dest, &options)?
+        .into_iter()
+        .filter(|diff| diff.key == params.key)
+        .collect();
+
+    let mut config = ProjectConfig::new(spec.name.to_string(), diffs);
+    redact_diffs(&mut config);
+    config
+        .diffs
+        .pop()
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("No {} difference at {}", spec.name, params.key)))
 }
 
-impl From<serde_json::Error> for PreviewError {
-    fn from(err: serde_json::Error) -> Self {
-        PreviewError::JsonError(err)
+// The preview with its services and options in a JSON body, for clients with more options than
+// fit a query string comfortably
+pub async fn preview_post_handler(
+    state: State<AppState>,
+    user: AuthenticatedUser,
+    session: Session,
+    body: Result<Json<PreviewRequest>, JsonRejection>,
+) -> Result<Response, AppError> {
+    let Json(request) = body?;
+    let services = if request.services.is_empty() {
+        stored_preferences(&session).await?.services
+    } else {
+        request.services
+    };
+    let services = requested_services(&services)?;
+
+    let options = request.options;
+    let params = PreviewQuery {
+        source_id: request.source_id,
+        dest_id: request.dest_id,
+        refresh: options.refresh,
+        output: options.output,
+        ignore: Some(options.ignore.join(",")),
+        format: options.format,
+        filter: Some(options.filter.join(",")),
+        max_value_len: options.max_value_len,
+        redact: options.redact,
+        ..PreviewQuery::default()
+    };
+    preview(state, params, services, user, session).await
+}
+
+// Services named in a request body, all of them known
+pub fn requested_services(names: &[String]) -> Result<Vec<&'static str>, AppError> {
+    let unknown: Vec<&str> = names
+        .iter()
+        .filter(|name| find_service(name).is_none())
+        .map(String::as_str)
+        .collect();
+    if !unknown.is_empty() {
+        return Err(AppError::Unprocessable(format!("Unknown services: {}", unknown.join(", "))));
+    }
+    if names.is_empty() {
+        return Err(AppError::Unprocessable("services must name at least one service".to_string()));
     }
+
+    Ok(names.iter().filter_map(|name| find_service(name)).map(|spec| spec.name).collect())
 }
 
 pub async fn preview_handler(
-    State(app_state): State<AppState>,
+    state: State<AppState>,
     Query(params): Query<PreviewQuery>,
+    user: AuthenticatedUser,
+    session: Session,
+) -> Result<Response, AppError> {
+    let preferred = stored_preferences(&session).await?.services;
+    let services = if params.names_services() || preferred.is_empty() {
+        params.selected_services()
+    } else {
+        requested_services(&preferred)?
+    };
+    preview(state, params, services, user, session).await
+}
+
+#[tracing::instrument(skip_all, fields(source_id = %params.source_id, dest_id = %params.dest_id))]
+pub async fn preview(
+    State(app_state): State<AppState>,
+    mut params: PreviewQuery,
+    services: Vec<&'static str>,
+    user: AuthenticatedUser,
     session: Session,
-) -> Result<impl IntoResponse, PreviewError> {
+) -> Result<Response, AppError> {
+    let api = app_state
+        .management_client(user.access_token)
+        .read_cache(!params.refresh.unwrap_or(false));
 
-    // TODO: Check authentication
+    // Either side can be a preview branch given as <project_ref>/<branch name>
+    params.source_id = resolve_project_ref(&api, &params.source_id).await?;
+    params.dest_id = resolve_project_ref(&api, &params.dest_id).await?;
 
     let mut project_config: Vec<ProjectConfig> = Vec::new();
-    let mut config_json: Vec<(String, String, String)> = Vec::new();
+    let mut diff_hashes = BTreeMap::new();
+    let mut warnings: Vec<PreviewWarning> = Vec::new();
 
-    // Check Auth config
-    if params.auth.unwrap_or(false) {
-        let source_config = mgmt_api_get(&session, format!("/projects/{}/config/auth", params.source_id))
-            .await
-            .map_err(|e| PreviewError::ApiError(format!("Failed to get auth config: {:?}", e)))?;
-        let dest_config = mgmt_api_get(&session,format!("/projects/{}/config/auth", params.dest_id))
-            .await
-            .map_err(|e| PreviewError::ApiError(format!("Failed to get auth config: {:?}", e)))?;
-        config_json.push(("Auth".to_string(), source_config, dest_config));
-    }
+    // Options the request leaves out come from the session's preferences
+    let preferences = stored_preferences(&session).await?;
+    let redact = params.redact.or(preferences.redact).unwrap_or(true);
 
-    // Check Postgrest config
-    if params.postgrest.unwrap_or(false) {
-        let source_config = mgmt_api_get(&session,format!("/projects/{}/postgrest", params.source_id))
-            .await
-            .map_err(|e| PreviewError::ApiError(format!("Failed to get postgrest config: {:?}", e)))?;
-        let dest_config = mgmt_api_get(&session,format!("/projects/{}/postgrest", params.dest_id))
-            .await
-            .map_err(|e| PreviewError::ApiError(format!("Failed to get postgrest config: {:?}", e)))?;
-        config_json.push(("Postgrest".to_string(), source_config, dest_config));
+    let output = params.output.as_deref().or(preferences.output.as_deref()).unwrap_or("diff");
+    if !OUTPUTS.contains(&output) {
+        return Err(AppError::BadRequest(format!("Unknown output format {}", output)));
     }
+    let mut json_patch = (output == "json_patch").then(BTreeMap::new);
 
-    // Check Edge Functions config
-    if params.edge_functions.unwrap_or(false) {
-        let source_config = mgmt_api_get(&session,format!("/projects/{}/functions", params.source_id))
-            .await
-            .map_err(|e| PreviewError::ApiError(format!("Failed to get functions config: {:?}", e)))?;
-        let dest_config = mgmt_api_get(&session,format!("/projects/{}/functions", params.dest_id))
-            .await
-            .map_err(|e| PreviewError::ApiError(format!("Failed to get functions config: {:?}", e)))?;
-        config_json.push(("EdgeFunctions".to_string(), source_config, dest_config));
+    let format = params.format.as_deref().or(preferences.format.as_deref()).unwrap_or("json");
+    if !FORMATS.contains(&format) {
+        return Err(AppError::BadRequest(format!("Unknown format {}", format)));
     }
-
-    // Check Secrets config
-    if params.secrets.unwrap_or(false) {
-        let source_config = mgmt_api_get(&session,format!("/projects/{}/secrets", params.source_id))
-            .await
-            .map_err(|e| PreviewError::ApiError(format!("Failed to get secrets config: {:?}", e)))?;
-        let dest_config = mgmt_api_get(&session,format!("/projects/{}/secrets", params.dest_id))
-            .await
-            .map_err(|e| PreviewError::ApiError(format!("Failed to get secrets config: {:?}", e)))?;
-        config_json.push(("Secrets".to_string(), source_config, dest_config));
+    let mut text_diff = String::new();
+
+    let ignore = parse_list(params.ignore.as_deref());
+    let filter = parse_list(params.filter.as_deref());
+    let max_value_len = params.max_value_len.unwrap_or(app_state.config.diff_max_value_len);
+
+    let mut specs = services
+        .into_iter()
+        .map(|service| {
+            find_service(service)
+                .ok_or_else(|| AppError::BadRequest(format!("Unknown service {}", service)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if params.dest_id == DEFAULTS_SOURCE {
+        return Err(AppError::BadRequest(format!("{} can only be used as the source", DEFAULTS_SOURCE)));
     }
-
-    // Check Postgres config
-    if params.postgres.unwrap_or(false) {
-        let url = "/config/database/postgres".to_string();
-        let source_config = mgmt_api_get(&session,format!("/projects/{}{}", params.source_id, url))
-            .await
-            .map_err(|e| PreviewError::ApiError(format!("Failed to get postgres config: {:?}", e)))?;
-        let dest_config = mgmt_api_get(&session,format!("/projects/{}{}", params.dest_id, url))
-            .await
-            .map_err(|e| PreviewError::ApiError(format!("Failed to get postgres config: {:?}", e)))?;
-        config_json.push(("Postgres".to_string(), source_config, dest_config));
+    // Against the defaults, services without documented ones are skipped rather than failing
+    if params.source_id == DEFAULTS_SOURCE {
+        specs.retain(|spec| {
+            if spec.defaults.is_none() {
+                warnings.push(PreviewWarning {
+                    service: spec.name.to_string(),
+                    message: "No documented defaults to compare against, skipped".to_string(),
+                });
+            }
+            spec.defaults.is_some()
+        });
     }
 
-    // Process each config and generate diffs
-    for (service, source_json, dest_json) in config_json {
-        let source: Value = serde_json::from_str(&source_json)?;
-        let dest: Value = serde_json::from_str(&dest_json)?;
+    // Fetch every selected config from both projects at once, keeping the selection order
+    let fetched = try_join_all(specs.into_iter().map(|spec| {
+        let api = &api;
+        let (source_id, dest_id) = (&params.source_id, &params.dest_id);
+        async move {
+            let (source, dest) = try_join!(
+                fetch_service_config(api, source_id, spec),
+                fetch_service_config(api, dest_id, spec)
+            )?;
+            Ok::<_, AppError>((spec, source, dest))
+        }
+    }))
+    .await?;
 
-        let project_config_entry = json_diff(service.clone(), source.clone(), dest).await?;
+    // Generate diffs for each fetched config
+    for (spec, source, dest) in fetched {
+        let service = spec.name;
 
-        if let Some(config_entry) = project_config_entry {
-            project_config.push(config_entry);
+        if let Some(message) = spec.check.and_then(|check| check(&source, &dest)) {
+            warnings.push(PreviewWarning {
+                service: service.to_string(),
+                message,
+            });
+        }
+
+        let options = DiffOptions::for_service(service).with_ignore(&ignore);
+
+        if let Some(patches) = json_patch.as_mut() {
+            let ops = service_patch(service, &source, &dest, &options, redact);
+            if !ops.is_empty() {
+                patches.insert(service.to_string(), ops);
+            }
         }
 
-        // Store in session (optional - you might want to remove this if not needed)
-        if let Err(e) = session.insert(&service, source_json).await {
-            eprintln!("Failed to insert preview results into session: {:?}", e);
-            // Don't fail the request for session errors, just log
+        if format == "text" {
+            text_diff.push_str(&unified_diff(
+                service,
+                &params.source_id,
+                &params.dest_id,
+                &source,
+                &dest,
+                &options,
+                redact,
+            ));
+        }
+
+        let cache_key = DiffCache::key(service, &source, &dest, &options);
+        let project_config_entry = match app_state.diff_cache.get(&cache_key) {
+            Some(cached) => cached,
+            None => {
+                let config_entry = json_diff(service.to_string(), source, dest, &options).await?;
+                app_state.diff_cache.insert(cache_key, config_entry.clone());
+                config_entry
+            }
+        };
+
+        let diffs = project_config_entry
+            .as_ref()
+            .map(|config_entry| config_entry.diffs.as_slice())
+            .unwrap_or_default();
+        diff_hashes.insert(service.to_string(), diff_hash(diffs));
+
+        // Masked and filtered only after hashing, so apply still checks the whole diff and
+        // notices a changed secret
+        if let Some(config_entry) = project_config_entry
+            && let Some(mut config_entry) = filter_diffs(config_entry, &filter)
+        {
+            if redact {
+                redact_diffs(&mut config_entry);
+            }
+            truncate_diffs(&mut config_entry, max_value_len);
+            project_config.push(config_entry);
         }
     }
 
+    if format == "text" {
+        return Ok(([(CONTENT_TYPE, "text/plain; charset=utf-8")], text_diff).into_response());
+    }
+    if format == "markdown" {
+        let report = markdown_report(&params.source_id, &params.dest_id, &project_config, &warnings);
+        return Ok(([(CONTENT_TYPE, "text/markdown; charset=utf-8")], report).into_response());
+    }
+    if format == "html" {
+        let page = html_report(&params.source_id, &params.dest_id, &project_config, &warnings);
+        return Ok(Html(page).into_response());
+    }
+
+    let plan = build_plan(
+        &params.source_id,
+        &params.dest_id,
+        &project_config
+            .iter()
+            .map(|config| ServiceApply {
+                service: config.name.clone(),
+                keys: config.diffs.iter().map(|diff| diff.key.clone()).collect(),
+                delete_missing: false,
+            })
+            .collect::<Vec<_>>(),
+    )?;
+
+    let confirmation_expires_at =
+        OffsetDateTime::now_utc() + Duration::minutes(app_state.config.confirmation_ttl_minutes);
+    let confirmation_token = issue_token(
+        &app_state.config.confirmation_secret,
+        &ConfirmationClaims {
+            source_id: params.source_id.clone(),
+            dest_id: params.dest_id.clone(),
+            diff_hashes,
+            ignore,
+            expires_at: confirmation_expires_at.unix_timestamp(),
+        },
+    )?;
+
+    let tree = (output == "tree").then(|| {
+        project_config
+            .iter()
+            .map(|config| (config.name.clone(), diff_tree(&config.diffs)))
+            .collect()
+    });
+
     Ok(Json(PreviewResponse {
+        summary: PreviewSummary::of(&project_config),
         configs: project_config,
-    }))
+        plan,
+        warnings,
+        confirmation_token,
+        confirmation_expires_at,
+        json_patch,
+        tree,
+    })
+    .into_response())
 }
 
-pub async fn mgmt_api_get(session: &Session, url: String) -> Result<String, PreviewError> {
-    use reqwest::header::{ACCEPT, AUTHORIZATION};
-    
-    let constructed_url = format!("https://api.supabase.com/v1{}", url);
-    
-    let token_option: Option<String> = session
-        .get("supabase_access_token")
-        .await
-        .map_err(|e| PreviewError::SessionError(format!("Failed to get token from session: {:?}", e)))?;
-    
-    let token = token_option.ok_or_else(|| {
-        PreviewError::Unauthorized
-    })?;
-
-    let client = reqwest::Client::new();
-    let api_response = client
-        .get(&constructed_url)
-        .header(AUTHORIZATION, format!("Bearer {}", token))
-        .header(ACCEPT, "application/json")
-        .send()
-        .await
-        .map_err(|e| PreviewError::ApiError(format!("Request failed: {:?}", e)))?;
-
-    if api_response.status().is_success() {
-        api_response
-            .text()
-            .await
-            .map_err(|e| PreviewError::ApiError(format!("Error reading response body as text: {:?}", e)))
-    } else {
-        let status_code = api_response.status().as_u16();
-        let error_text = api_response
-            .text()
-            .await
-            .unwrap_or_else(|e| format!("Error reading response body: {}", e));
-        Err(PreviewError::ApiError(format!(
-            "HTTP request failed with status {}: {}",
-            status_code, error_text
-        )))
+// Timestamps that differ between any two projects, whatever the config type
+const ALWAYS_IGNORED: [&str; 2] = ["created_at", "updated_at"];
+
+// Nesting below which values are compared whole rather than field by field
+const MAX_DIFF_DEPTH: usize = 32;
+
+// How values of one config type are compared
+#[derive(Debug, Clone)]
+pub struct DiffOptions {
+    // Field that identifies array items, so they are matched by it rather than by position
+    pub id_key: &'static str,
+    // Arrays at these paths whose items are only identified by several fields together
+    pub identity: &'static [(&'static str, &'static [&'static str])],
+    // Lists of plain values at these paths are compared regardless of order
+    pub unordered: &'static [&'static str],
+    // Fields left out before diffing. A plain name matches the field at any depth, a dotted
+    // path like `smtp.port` only that field; array positions are not part of the path.
+    pub ignore: Vec<String>,
+    // Objects and arrays nested deeper than this are compared whole and show up as one entry
+    pub max_depth: usize,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions {
+            id_key: "id",
+            identity: &[],
+            unordered: &[],
+            ignore: ALWAYS_IGNORED.iter().map(|field| field.to_string()).collect(),
+            max_depth: MAX_DIFF_DEPTH,
+        }
     }
 }
 
+impl DiffOptions {
+    pub fn for_service(config_type: &str) -> Self {
+        let mut options = DiffOptions::default();
+        if let Some(spec) = find_service(config_type) {
+            options.id_key = spec.id_key;
+            options.identity = spec.identity;
+            options.unordered = spec.unordered;
+            options.ignore.extend(spec.ignore.iter().map(|field| field.to_string()));
+        }
+        options
+    }
+
+    // Fields that together identify the items of the array at this path
+    fn identity_at(&self, path: &str) -> &[&'static str] {
+        self.identity
+            .iter()
+            .find(|(identity_path, _)| *identity_path == path)
+            .map(|(_, fields)| *fields)
+            .unwrap_or(std::slice::from_ref(&self.id_key))
+    }
+
+    pub fn with_ignore(mut self, fields: &[String]) -> Self {
+        self.ignore.extend(fields.iter().cloned());
+        self
+    }
+
+    // The value as it is compared: without the ignored fields, and normalized
+    pub fn prepare(&self, value: &Value) -> Value {
+        normalize(self.strip_ignored(value), self.unordered)
+    }
+
+    // Whether a diff key points at or into a field the diff leaves out. Item IDs and array
+    // positions are skipped, as they are not part of ignore paths.
+    pub fn ignores_key(&self, key: &str) -> bool {
+        let mut path = String::new();
+        for segment in key.split('.').filter(|segment| !segment.starts_with("id:")) {
+            let field = segment.split('[').next().unwrap_or_default();
+            if !path.is_empty() {
+                path.push('.');
+            }
+            path.push_str(field);
+            if self.ignore.iter().any(|entry| *entry == path || entry == field) {
+                return true;
+            }
+        }
+        false
+    }
+
+    // Copy of the value without the ignored fields
+    pub fn strip_ignored(&self, value: &Value) -> Value {
+        self.strip_at("", value)
+    }
+
+    fn strip_at(&self, path: &str, value: &Value) -> Value {
+        match value {
+            Value::Object(fields) => Value::Object(
+                fields
+                    .iter()
+                    .filter_map(|(key, field)| {
+                        let field_path = if path.is_empty() {
+                            key.clone()
+                        } else {
+                            format!("{}.{}", path, key)
+                        };
+                        let ignored = self.ignore.iter().any(|entry| *entry == field_path || entry == key);
+                        (!ignored).then(|| (key.clone(), self.strip_at(&field_path, field)))
+                    })
+                    .collect(),
+            ),
+            Value::Array(items) => Value::Array(items.iter().map(|item| self.strip_at(path, item)).collect()),
+            other => other.clone(),
+        }
+    }
+}
+
+// Keeps the entries whose key starts with one of the prefixes; None when nothing is left.
+// No prefixes keeps everything.
+pub fn filter_diffs(config: ProjectConfig, prefixes: &[String]) -> Option<ProjectConfig> {
+    if prefixes.is_empty() {
+        return Some(config);
+    }
+
+    let diffs: Vec<DiffEntry> = config
+        .diffs
+        .into_iter()
+        .filter(|diff| prefixes.iter().any(|prefix| diff.key.starts_with(prefix.as_str())))
+        .collect();
+    (!diffs.is_empty()).then(|| ProjectConfig::new(config.name, diffs))
+}
+
+// Comma-separated values of a query parameter, e.g. ignored fields or key prefixes
+pub fn parse_list(param: Option<&str>) -> Vec<String> {
+    param
+        .into_iter()
+        .flat_map(|fields| fields.split(','))
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(str::to_string)
+        .collect()
+}
 
 pub async fn json_diff(
     config_type: String,
     source_value: Value,
     dest_value: Value,
-) -> Result<Option<ProjectConfig>, PreviewError> {
-    let diff_entries = calculate_diff(&config_type, &source_value, &dest_value)?;
+    options: &DiffOptions,
+) -> Result<Option<ProjectConfig>, AppError> {
+    let diff_entries = calculate_diff(&config_type, &source_value, &dest_value, options)?;
 
     if diff_entries.is_empty() {
         Ok(None)
     } else {
-        Ok(Some(ProjectConfig {
-            name: config_type,
-            diffs: diff_entries,
-        }))
+        Ok(Some(ProjectConfig::new(config_type, diff_entries)))
+    }
+}
+
+// Masks sensitive values in a diff meant for display. Entries stay, so a changed secret
+// still shows up as changed.
+pub fn redact_diffs(config: &mut ProjectConfig) {
+    for diff in &mut config.diffs {
+        let field = diff.key.rsplit('.').next().unwrap_or_default();
+        let field = field.split('[').next().unwrap_or_default();
+        for value in [&mut diff.source_value, &mut diff.dest_value].into_iter().flatten() {
+            *value = redact_diff_value(field, value);
+        }
+    }
+}
+
+// Cuts values longer than max_len characters and marks their entries as truncated. 0 keeps
+// values whole.
+pub fn truncate_diffs(config: &mut ProjectConfig, max_len: usize) {
+    if max_len == 0 {
+        return;
+    }
+
+    for diff in &mut config.diffs {
+        for value in [&mut diff.source_value, &mut diff.dest_value].into_iter().flatten() {
+            if let Some((end, _)) = value.char_indices().nth(max_len) {
+                value.truncate(end);
+                diff.truncated = true;
+            }
+        }
     }
 }
 
@@ -220,75 +699,75 @@ fn calculate_diff(
     config_type: &str,
     source: &Value,
     dest: &Value,
-) -> Result<Vec<DiffEntry>, PreviewError> {
-    let mut diff_entries = Vec::new();
-
-    // Pre-filter arrays if this is Secrets config
-    if config_type == "Secrets" {
-        if let (Value::Array(src_arr), Value::Array(dst_arr)) = (source, dest) {
-            // Filter out SUPABASE_ secrets before diffing
-            let filtered_src: Vec<Value> = src_arr
-                .iter()
-                .filter(|v| !is_supabase_secret(v))
-                .cloned()
-                .collect();
-            let filtered_dst: Vec<Value> = dst_arr
-                .iter()
-                .filter(|v| !is_supabase_secret(v))
-                .cloned()
-                .collect();
-
-            let filtered_src_value = Value::Array(filtered_src);
-            let filtered_dst_value = Value::Array(filtered_dst);
-            diff_values(
-                "",
-                &filtered_src_value,
-                &filtered_dst_value,
-                &mut diff_entries,
-            );
-        } else {
-            diff_values("", source, dest, &mut diff_entries);
+    options: &DiffOptions,
+) -> Result<Vec<DiffEntry>, AppError> {
+    let mut diff_entries = strategy_for(config_type).diff(
+        &comparable_value(config_type, source, options),
+        &comparable_value(config_type, dest, options),
+        options,
+    );
+
+    if let Some(spec) = find_service(config_type) {
+        for entry in &mut diff_entries {
+            entry.risk = assess_risk(spec, entry);
         }
-    } else {
-        diff_values("", source, dest, &mut diff_entries);
     }
 
     Ok(diff_entries)
 }
 
-fn is_supabase_secret(value: &Value) -> bool {
-    if let Value::Object(obj) = value {
-        if let Some(Value::String(name)) = obj.get("name") {
-            return name.starts_with("SUPABASE_");
-        }
-    }
-    false
+pub fn strategy_for(config_type: &str) -> &'static dyn DiffStrategy {
+    find_service(config_type).map_or(&Recursive, |spec| spec.diff)
+}
+
+// A config as the diff sees it: ignored fields left out, the rest normalized and reshaped by
+// the service's diff strategy
+pub fn comparable_value(config_type: &str, value: &Value, options: &DiffOptions) -> Value {
+    strategy_for(config_type).prepare(options.prepare(value))
 }
 
-fn diff_values(path: &str, source: &Value, dest: &Value, diffs: &mut Vec<DiffEntry>) {
+pub fn diff_values(path: &str, source: &Value, dest: &Value, options: &DiffOptions, diffs: &mut Vec<DiffEntry>) {
+    diff_at(path, source, dest, 0, options, diffs);
+}
+
+fn diff_at(path: &str, source: &Value, dest: &Value, depth: usize, options: &DiffOptions, diffs: &mut Vec<DiffEntry>) {
     use Value::*;
 
+    // Past the max depth, e.g. inside a deeply nested JSON blob, values are compared whole
+    let nested = depth < options.max_depth;
+
     match (source, dest) {
-        (Array(src), Array(dst)) => diff_arrays(path, src, dst, diffs),
-        (Object(src), Object(dst)) => diff_objects(path, src, dst, diffs),
+        (Array(src), Array(dst)) if nested => diff_arrays(path, src, dst, depth + 1, options, diffs),
+        (Object(src), Object(dst)) if nested => diff_objects(path, src, dst, depth + 1, options, diffs),
         _ if source != dest => {
             diffs.push(DiffEntry {
                 key: if path.is_empty() { "root" } else { path }.to_string(),
-                source_value: format_value(source),
-                dest_value: format_value(dest),
+                change_type: ChangeType::Modified,
+                source_value: Some(format_value(source)),
+                dest_value: Some(format_value(dest)),
+                risk: RiskLevel::default(),
+                truncated: false,
             });
         }
         _ => {} // Values are equal
     }
 }
 
-fn diff_arrays(path: &str, src: &[Value], dst: &[Value], diffs: &mut Vec<DiffEntry>) {
-    let src_map = to_id_map(src);
-    let dst_map = to_id_map(dst);
+fn diff_arrays(
+    path: &str,
+    src: &[Value],
+    dst: &[Value],
+    depth: usize,
+    options: &DiffOptions,
+    diffs: &mut Vec<DiffEntry>,
+) {
+    let identity = options.identity_at(path);
+    let src_map = to_id_map(src, identity);
+    let dst_map = to_id_map(dst, identity);
 
     match (src_map, dst_map) {
         (Some(src_ids), Some(mut dst_ids)) => {
-            diff_by_id(path, &src_ids, &mut dst_ids, diffs);
+            diff_by_id(path, &src_ids, &mut dst_ids, depth, options, diffs);
         }
         (Some(src_ids), None) => {
             for (id, val) in src_ids {
@@ -299,8 +778,11 @@ fn diff_arrays(path: &str, src: &[Value], dst: &[Value], diffs: &mut Vec<DiffEnt
                         if path.is_empty() { "" } else { "." },
                         id
                     ),
-                    source_value: format_value(val),
-                    dest_value: "null".to_string(),
+                    change_type: ChangeType::Added,
+                    source_value: Some(format_value(val)),
+                    dest_value: None,
+                    risk: RiskLevel::default(),
+                    truncated: false,
                 });
             }
         }
@@ -313,27 +795,28 @@ fn diff_arrays(path: &str, src: &[Value], dst: &[Value], diffs: &mut Vec<DiffEnt
                         if path.is_empty() { "" } else { "." },
                         id
                     ),
-                    source_value: "null".to_string(),
-                    dest_value: format_value(val),
+                    change_type: ChangeType::Removed,
+                    source_value: None,
+                    dest_value: Some(format_value(val)),
+                    risk: RiskLevel::default(),
+                    truncated: false,
                 });
             }
         }
         (None, None) => {
-            diff_by_index(path, src, dst, diffs);
+            diff_by_index(path, src, dst, depth, options, diffs);
         }
     }
 }
 
-fn to_id_map(arr: &[Value]) -> Option<HashMap<String, &Value>> {
+fn to_id_map<'a>(arr: &'a [Value], identity: &[&str]) -> Option<HashMap<String, &'a Value>> {
     let mut map = HashMap::new();
     let mut has_ids = false;
 
     for item in arr {
-        if let Value::Object(obj) = item {
-            if let Some(Value::String(id)) = obj.get("id") {
-                map.insert(id.clone(), item);
-                has_ids = true;
-            }
+        if let Some(id) = item_id(item, identity) {
+            map.insert(id, item);
+            has_ids = true;
         }
     }
 
@@ -348,6 +831,8 @@ fn diff_by_id(
     path: &str,
     src_map: &HashMap<String, &Value>,
     dst_map: &mut HashMap<String, &Value>,
+    depth: usize,
+    options: &DiffOptions,
     diffs: &mut Vec<DiffEntry>,
 ) {
     for (id, src_val) in src_map {
@@ -359,12 +844,15 @@ fn diff_by_id(
         );
 
         if let Some(dst_val) = dst_map.remove(id) {
-            diff_values(&item_path, src_val, &dst_val, diffs);
+            diff_at(&item_path, src_val, dst_val, depth, options, diffs);
         } else {
             diffs.push(DiffEntry {
                 key: item_path,
-                source_value: format_value(src_val),
-                dest_value: "null".to_string(),
+                change_type: ChangeType::Added,
+                source_value: Some(format_value(src_val)),
+                dest_value: None,
+                risk: RiskLevel::default(),
+                truncated: false,
             });
         }
     }
@@ -377,49 +865,109 @@ fn diff_by_id(
                 if path.is_empty() { "" } else { "." },
                 id
             ),
-            source_value: "null".to_string(),
-            dest_value: format_value(dst_val),
+            change_type: ChangeType::Removed,
+            source_value: None,
+            dest_value: Some(format_value(dst_val)),
+            risk: RiskLevel::default(),
+            truncated: false,
         });
     }
 }
 
-fn diff_by_index(path: &str, src: &[Value], dst: &[Value], diffs: &mut Vec<DiffEntry>) {
+fn diff_by_index(
+    path: &str,
+    src: &[Value],
+    dst: &[Value],
+    depth: usize,
+    options: &DiffOptions,
+    diffs: &mut Vec<DiffEntry>,
+) {
+    let is_scalar = |item: &Value| !item.is_object() && !item.is_array();
+    if src.iter().all(is_scalar) && dst.iter().all(is_scalar) {
+        return diff_scalars(path, src, dst, diffs);
+    }
+
     let max_len = src.len().max(dst.len());
 
     for i in 0..max_len {
         let item_path = format!("{}[{}]", path, i);
 
         match (src.get(i), dst.get(i)) {
-            (Some(s), Some(d)) => {
-                if s.is_object() && d.is_object() && s != d {
-                    diffs.push(DiffEntry {
-                        key: item_path,
-                        source_value: format_value(s),
-                        dest_value: format_value(d),
-                    });
-                } else if !s.is_object() || !d.is_object() {
-                    diff_values(&item_path, s, d, diffs);
-                }
-            }
+            // Objects at the same position are compared field by field
+            (Some(s), Some(d)) => diff_at(&item_path, s, d, depth, options, diffs),
             (Some(s), None) => diffs.push(DiffEntry {
                 key: item_path,
-                source_value: format_value(s),
-                dest_value: "null".to_string(),
+                change_type: ChangeType::Added,
+                source_value: Some(format_value(s)),
+                dest_value: None,
+                risk: RiskLevel::default(),
+                truncated: false,
             }),
             (None, Some(d)) => diffs.push(DiffEntry {
                 key: item_path,
-                source_value: "null".to_string(),
-                dest_value: format_value(d),
+                change_type: ChangeType::Removed,
+                source_value: None,
+                dest_value: Some(format_value(d)),
+                risk: RiskLevel::default(),
+                truncated: false,
             }),
             _ => {}
         }
     }
 }
 
+// Aligns lists of plain values on their longest common subsequence, so a value inserted at
+// the front shows up as one addition rather than every later position changing. Added and
+// modified entries are keyed by their source position, removed ones by their destination one.
+fn diff_scalars(path: &str, src: &[Value], dst: &[Value], diffs: &mut Vec<DiffEntry>) {
+    let src_items: Vec<String> = src.iter().map(Value::to_string).collect();
+    let dst_items: Vec<String> = dst.iter().map(Value::to_string).collect();
+    let entry = |change_type, src_index: Option<usize>, dst_index: Option<usize>| DiffEntry {
+        key: format!("{}[{}]", path, src_index.or(dst_index).unwrap_or_default()),
+        change_type,
+        source_value: src_index.map(|i| format_value(&src[i])),
+        dest_value: dst_index.map(|i| format_value(&dst[i])),
+        risk: RiskLevel::default(),
+        truncated: false,
+    };
+
+    // The destination is the old side: the ops describe what migrating would change
+    for op in capture_diff_slices(Algorithm::Lcs, &dst_items, &src_items) {
+        match op {
+            DiffOp::Equal { .. } => {}
+            DiffOp::Delete { old_index, old_len, .. } => {
+                for i in old_index..old_index + old_len {
+                    diffs.push(entry(ChangeType::Removed, None, Some(i)));
+                }
+            }
+            DiffOp::Insert { new_index, new_len, .. } => {
+                for i in new_index..new_index + new_len {
+                    diffs.push(entry(ChangeType::Added, Some(i), None));
+                }
+            }
+            // Values replaced in place pair up as modifications, the rest are added or removed
+            DiffOp::Replace { old_index, old_len, new_index, new_len } => {
+                for offset in 0..old_len.max(new_len) {
+                    let dst_index = (offset < old_len).then_some(old_index + offset);
+                    let src_index = (offset < new_len).then_some(new_index + offset);
+                    let change_type = match (src_index, dst_index) {
+                        (Some(_), Some(_)) => ChangeType::Modified,
+                        (Some(_), None) => ChangeType::Added,
+                        _ => ChangeType::Removed,
+                    };
+                    diffs.push(entry(change_type, src_index, dst_index));
+                }
+            }
+        }
+    }
+}
+
 fn diff_objects(
     path: &str,
     src: &Map<String, Value>,
     dst: &Map<String, Value>,
+    depth: usize,
+    options: &DiffOptions,
     diffs: &mut Vec<DiffEntry>,
 ) {
     for (key, src_val) in src {
@@ -430,11 +978,14 @@ fn diff_objects(
         };
 
         match dst.get(key) {
-            Some(dst_val) => diff_values(&field_path, src_val, dst_val, diffs),
+            Some(dst_val) => diff_at(&field_path, src_val, dst_val, depth, options, diffs),
             None => diffs.push(DiffEntry {
                 key: field_path,
-                source_value: format_value(src_val),
-                dest_value: "null".to_string(),
+                change_type: ChangeType::Added,
+                source_value: Some(format_value(src_val)),
+                dest_value: None,
+                risk: RiskLevel::default(),
+                truncated: false,
             }),
         }
     }
@@ -448,8 +999,11 @@ fn diff_objects(
             };
             diffs.push(DiffEntry {
                 key: field_path,
-                source_value: "null".to_string(),
-                dest_value: format_value(dst_val),
+                change_type: ChangeType::Removed,
+                source_value: None,
+                dest_value: Some(format_value(dst_val)),
+                risk: RiskLevel::default(),
+                truncated: false,
             });
         }
     }
@@ -468,24 +1022,40 @@ fn format_value(value: &Value) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_preview_body_validates_services_and_options() {
+        let services = vec!["Auth".to_string(), "Secrets".to_string()];
+        assert_eq!(requested_services(&services).unwrap(), vec!["Auth", "Secrets"]);
+
+        let services = vec!["Auth".to_string(), "Auht".to_string(), "Buckts".to_string()];
+        match requested_services(&services) {
+            Err(AppError::Unprocessable(msg)) => assert_eq!(msg, "Unknown services: Auht, Buckts"),
+            other => panic!("expected unknown services, got {:?}", other),
+        }
+
+        let body = json!({"source_id": "a", "dest_id": "b", "services": ["Auth"], "options": {"ignroe": ["x"]}});
+        assert!(serde_json::from_value::<PreviewRequest>(body).is_err());
+    }
 
     #[tokio::test]
     async fn test_object_diff() {
         let source: Value = serde_json::from_str(r#"{"a": 1, "b": 2}"#).unwrap();
         let dest: Value = serde_json::from_str(r#"{"a": 1, "b": 3, "c": 4}"#).unwrap();
 
-        let result = json_diff("test".to_string(), source, dest).await.unwrap();
+        let result = json_diff("test".to_string(), source, dest, &DiffOptions::default()).await.unwrap();
         let config = result.unwrap();
 
         assert_eq!(config.diffs.len(), 2); // b changed, c added
         assert!(config
             .diffs
             .iter()
-            .any(|d| d.key == "b" && d.dest_value == "3"));
+            .any(|d| d.key == "b" && d.dest_value.as_deref() == Some("3")));
         assert!(config
             .diffs
             .iter()
-            .any(|d| d.key == "c" && d.source_value == "null"));
+            .any(|d| d.key == "c" && d.change_type == ChangeType::Removed));
     }
 
     #[tokio::test]
@@ -499,7 +1069,7 @@ mod tests {
         let source_value: Value = serde_json::from_str(source).unwrap();
         let dest_value: Value = serde_json::from_str(dest).unwrap();
 
-        let result = json_diff("test".to_string(), source_value, dest_value)
+        let result = json_diff("test".to_string(), source_value, dest_value, &DiffOptions::default())
             .await
             .unwrap();
         let config = result.unwrap();
@@ -517,7 +1087,7 @@ mod tests {
         let source_value: Value = serde_json::from_str(source).unwrap();
         let dest_value: Value = serde_json::from_str(dest).unwrap();
 
-        let result = json_diff("test".to_string(), source_value, dest_value)
+        let result = json_diff("test".to_string(), source_value, dest_value, &DiffOptions::default())
             .await
             .unwrap();
         assert!(result.is_none());
@@ -550,7 +1120,7 @@ mod tests {
         let source_value: Value = serde_json::from_str(source).unwrap();
         let dest_value: Value = serde_json::from_str(dest).unwrap();
 
-        let result = json_diff("test".to_string(), source_value, dest_value)
+        let result = json_diff("test".to_string(), source_value, dest_value, &DiffOptions::default())
             .await
             .unwrap();
         let config = result.unwrap();
@@ -559,15 +1129,75 @@ mod tests {
         assert!(config
             .diffs
             .iter()
-            .any(|d| d.key == "user.age" && d.dest_value == "31"));
+            .any(|d| d.key == "user.age" && d.dest_value.as_deref() == Some("31")));
         assert!(config
             .diffs
             .iter()
-            .any(|d| d.key == "user.address.city" && d.dest_value == "New York"));
+            .any(|d| d.key == "user.address.city" && d.dest_value.as_deref() == Some("New York")));
         assert!(config
             .diffs
             .iter()
-            .any(|d| d.key == "user.address.zip" && d.source_value == "null"));
+            .any(|d| d.key == "user.address.zip" && d.change_type == ChangeType::Removed));
+    }
+
+    #[tokio::test]
+    async fn test_scalar_insertion_is_one_addition() {
+        let source = json!({"schemas": ["graphql_public", "private", "public"]});
+        let dest = json!({"schemas": ["graphql_public", "public"]});
+
+        let config = json_diff("test".to_string(), source, dest, &DiffOptions::default())
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(config.diffs.len(), 1);
+        assert_eq!(config.diffs[0].key, "schemas[1]");
+        assert_eq!(config.diffs[0].change_type, ChangeType::Added);
+        assert_eq!(config.diffs[0].source_value.as_deref(), Some("private"));
+    }
+
+    #[tokio::test]
+    async fn test_scalar_alignment_keeps_list_order() {
+        let source = json!({"hooks": ["c", "a", "b"]});
+        let dest = json!({"hooks": ["c", "b"]});
+
+        let config = json_diff("test".to_string(), source, dest, &DiffOptions::default())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(config.diffs.len(), 1);
+        assert_eq!(config.diffs[0].key, "hooks[1]");
+        assert_eq!(config.diffs[0].change_type, ChangeType::Added);
+        assert_eq!(config.diffs[0].source_value.as_deref(), Some("a"));
+
+        // Reordering is a change unless the path is order-insensitive
+        let reordered = json_diff("test".to_string(), json!(["b", "a"]), json!(["a", "b"]), &DiffOptions::default())
+            .await
+            .unwrap();
+        assert!(reordered.is_some());
+        let options = DiffOptions { unordered: &[""], ..DiffOptions::default() };
+        let unordered = json_diff("test".to_string(), json!(["b", "a"]), json!(["a", "b"]), &options)
+            .await
+            .unwrap();
+        assert!(unordered.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_deep_values_compared_whole_and_long_ones_truncated() {
+        let source = json!({"a": {"b": {"c": 1, "body": "x".repeat(20)}}});
+        let dest = json!({"a": {"b": {"c": 2, "body": "y".repeat(20)}}});
+        let options = DiffOptions { max_depth: 2, ..DiffOptions::default() };
+
+        let mut config = json_diff("test".to_string(), source, dest, &options)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(config.diffs.len(), 1);
+        assert_eq!(config.diffs[0].key, "a.b");
+
+        truncate_diffs(&mut config, 10);
+        assert!(config.diffs[0].truncated);
+        assert_eq!(config.diffs[0].source_value.as_ref().map(|value| value.chars().count()), Some(10));
     }
 
     #[tokio::test]
@@ -578,7 +1208,7 @@ mod tests {
         let source_value: Value = serde_json::from_str(source).unwrap();
         let dest_value: Value = serde_json::from_str(dest).unwrap();
 
-        let result = json_diff("test".to_string(), source_value, dest_value)
+        let result = json_diff("test".to_string(), source_value, dest_value, &DiffOptions::default())
             .await
             .unwrap();
         let config = result.unwrap();
@@ -588,11 +1218,11 @@ mod tests {
         assert!(config
             .diffs
             .iter()
-            .any(|d| d.key == "[2]" && d.source_value == "3" && d.dest_value == "5"));
+            .any(|d| d.key == "[2]" && d.source_value.as_deref() == Some("3") && d.dest_value.as_deref() == Some("5")));
         assert!(config
             .diffs
             .iter()
-            .any(|d| d.key == "[3]" && d.source_value == "4" && d.dest_value == "null"));
+            .any(|d| d.key == "[3]" && d.source_value.as_deref() == Some("4") && d.change_type == ChangeType::Added));
     }
 
     #[tokio::test]
@@ -611,7 +1241,7 @@ mod tests {
         let source_value: Value = serde_json::from_str(source).unwrap();
         let dest_value: Value = serde_json::from_str(dest).unwrap();
 
-        let result = json_diff("Secrets".to_string(), source_value, dest_value)
+        let result = json_diff("Secrets".to_string(), source_value, dest_value, &DiffOptions::for_service("Secrets"))
             .await
             .unwrap();
         let config = result.unwrap();
@@ -619,25 +1249,26 @@ mod tests {
         // After filtering SUPABASE_ secrets:
         // Source has: MY_SECRET, ANOTHER_SECRET
         // Dest has: MY_SECRET
-        // So we should see:
-        // - [0] changed (MY_SECRET value changed)
-        // - [1] removed (ANOTHER_SECRET)
+        // Secrets are matched by name, so we should see:
+        // - MY_SECRET's value changed; its timestamp is ignored
+        // - ANOTHER_SECRET missing on dest
         assert_eq!(config.diffs.len(), 2);
-        assert!(config.diffs.iter().any(|d| d.key == "[0]")); // MY_SECRET changed
+        assert!(config.diffs.iter().any(|d| d.key == "id:MY_SECRET.value"));
+        assert!(!config.diffs.iter().any(|d| d.key == "id:MY_SECRET.updated_at"));
         assert!(config
             .diffs
             .iter()
-            .any(|d| d.key == "[1]" && d.source_value.contains("ANOTHER_SECRET"))); // ANOTHER_SECRET removed
+            .any(|d| d.key == "id:ANOTHER_SECRET" && d.source_value.as_deref().is_some_and(|v| v.contains("ANOTHER_SECRET"))));
 
         // Should not have any SUPABASE_ related diffs
         for diff in &config.diffs {
-            assert!(!diff.source_value.contains("SUPABASE_"));
-            assert!(!diff.dest_value.contains("SUPABASE_"));
+            assert!(!diff.source_value.as_deref().unwrap_or_default().contains("SUPABASE_"));
+            assert!(!diff.dest_value.as_deref().unwrap_or_default().contains("SUPABASE_"));
         }
     }
 
     #[tokio::test]
-    async fn test_array_object_diff_whole_object() {
+    async fn test_array_object_diff_per_field() {
         let source = r#"[
             {"name": "item1", "value": 100, "active": true}
         ]"#;
@@ -648,15 +1279,91 @@ mod tests {
         let source_value: Value = serde_json::from_str(source).unwrap();
         let dest_value: Value = serde_json::from_str(dest).unwrap();
 
-        let result = json_diff("test".to_string(), source_value, dest_value)
+        let result = json_diff("test".to_string(), source_value, dest_value, &DiffOptions::default())
             .await
             .unwrap();
         let config = result.unwrap();
 
-        // Should report the whole object as changed
+        // Only the changed field is reported
+        assert_eq!(config.diffs.len(), 1);
+        assert_eq!(config.diffs[0].key, "[0].value");
+        assert_eq!(config.diffs[0].source_value.as_deref(), Some("100"));
+        assert_eq!(config.diffs[0].dest_value.as_deref(), Some("200"));
+    }
+
+    #[tokio::test]
+    async fn test_custom_id_key_matches_items() {
+        let source = json!([{"slug": "hello", "verify_jwt": true}]);
+        let dest = json!([{"slug": "hello", "verify_jwt": false}]);
+
+        let config = json_diff("test".to_string(), source, dest, &DiffOptions { id_key: "slug", ..DiffOptions::default() })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(config.diffs.len(), 1);
+        assert_eq!(config.diffs[0].key, "id:hello.verify_jwt");
+    }
+
+    #[tokio::test]
+    async fn test_filter_keeps_matching_key_prefixes() {
+        let source = json!({"google": {"enabled": true}, "github": {"enabled": true}, "site_url": "a"});
+        let dest = json!({"google": {"enabled": false}, "github": {"enabled": false}, "site_url": "b"});
+        let config = json_diff("test".to_string(), source, dest, &DiffOptions::default())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let filtered = filter_diffs(config.clone(), &parse_list(Some("google,site"))).unwrap();
+        let keys: Vec<&str> = filtered.diffs.iter().map(|d| d.key.as_str()).collect();
+        assert_eq!(keys, vec!["google.enabled", "site_url"]);
+        assert_eq!(filtered.summary.total, 2);
+
+        assert!(filter_diffs(config.clone(), &parse_list(Some("smtp"))).is_none());
+        assert_eq!(filter_diffs(config, &[]).unwrap().diffs.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_ignored_fields_are_left_out() {
+        let source = json!({"updated_at": "2024-01-01", "smtp": {"host": "a", "port": 25}, "items": [{"version": 1}]});
+        let dest = json!({"updated_at": "2024-02-01", "smtp": {"host": "b", "port": 587}, "items": [{"version": 2}]});
+
+        let ignore = parse_list(Some("smtp.port, version"));
+        assert_eq!(ignore, vec!["smtp.port", "version"]);
+
+        let options = DiffOptions::default().with_ignore(&ignore);
+        let config = json_diff("test".to_string(), source, dest, &options).await.unwrap().unwrap();
+
         assert_eq!(config.diffs.len(), 1);
-        assert!(config.diffs.iter().any(|d| d.key == "[0]"));
-        assert!(config.diffs[0].source_value.contains("\"value\":100"));
-        assert!(config.diffs[0].dest_value.contains("\"value\":200"));
+        assert_eq!(config.diffs[0].key, "smtp.host");
+    }
+
+    #[tokio::test]
+    async fn test_buckets_matched_by_name() {
+        let source = r#"[
+            {"name": "avatars", "public": true, "file_size_limit": 1024},
+            {"name": "docs", "public": false, "file_size_limit": null}
+        ]"#;
+        let dest = r#"[
+            {"name": "avatars", "public": false, "file_size_limit": 1024}
+        ]"#;
+
+        let source_value: Value = serde_json::from_str(source).unwrap();
+        let dest_value: Value = serde_json::from_str(dest).unwrap();
+
+        let result = json_diff("Buckets".to_string(), source_value, dest_value, &DiffOptions::for_service("Buckets"))
+            .await
+            .unwrap();
+        let config = result.unwrap();
+
+        assert_eq!(config.diffs.len(), 2);
+        assert!(config
+            .diffs
+            .iter()
+            .any(|d| d.key == "id:avatars.public" && d.dest_value.as_deref() == Some("false")));
+        assert!(config
+            .diffs
+            .iter()
+            .any(|d| d.key == "id:docs" && d.change_type == ChangeType::Added));
     }
-}
\ No newline at end of file
+}
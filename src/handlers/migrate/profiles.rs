@@ -0,0 +1,251 @@
+use crate::error::AppError;
+use crate::handlers::auth::AuthenticatedUser;
+use crate::handlers::migrate::preview_handler::{preview, requested_services, PreviewQuery};
+use crate::models::migrate::{MigrationProfile, SaveProfileRequest};
+use crate::models::AppState;
+use crate::storage::Records;
+
+use axum::{
+    extract::{rejection::JsonRejection, Path, State},
+    http::StatusCode,
+    response::{Json, Response},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use time::OffsetDateTime;
+use tower_sessions::Session;
+
+// Saved profiles by owner and name, so each user has their own. With storage configured
+// they are saved there as well, so they outlive a restart.
+#[derive(Clone, Default)]
+pub struct Profiles {
+    profiles: Arc<RwLock<BTreeMap<(String, String), MigrationProfile>>>,
+    records: Option<Records>,
+}
+
+impl Profiles {
+    pub async fn load(records: Option<Records>) -> Result<Self, String> {
+        let Some(records) = records else {
+            return Ok(Self::default());
+        };
+
+        let mut profiles = BTreeMap::new();
+        for profile in records.load::<MigrationProfile>().await? {
+            // Profiles saved before they had owners cannot be given back to anyone
+            if profile.owner.is_empty() {
+                tracing::warn!(name = %profile.name, "dropping a saved profile that has no owner");
+                records.delete(&profile.name);
+                continue;
+            }
+            profiles.insert((profile.owner.clone(), profile.name.clone()), profile);
+        }
+        Ok(Self {
+            profiles: Arc::new(RwLock::new(profiles)),
+            records: Some(records),
+        })
+    }
+
+    // Returns the saved profile and whether it replaced one of the owner's with the same name
+    pub fn save(&self, owner: &str, request: SaveProfileRequest) -> Result<(MigrationProfile, bool), AppError> {
+        check_name(&request.name)?;
+        if request.source_id == request.dest_id {
+            return Err(AppError::BadRequest("source_id and dest_id must differ".to_string()));
+        }
+        let services = requested_services(&request.services)?;
+
+        let mut profiles = self.profiles.write().expect("profile store lock poisoned");
+        let now = OffsetDateTime::now_utc();
+        let key = (owner.to_string(), request.name);
+        let previous = profiles.get(&key);
+        let profile = MigrationProfile {
+            created_at: previous.map_or(now, |previous| previous.created_at),
+            updated_at: now,
+            name: key.1.clone(),
+            owner: key.0.clone(),
+            source_id: request.source_id,
+            dest_id: request.dest_id,
+            services: services.into_iter().map(str::to_string).collect(),
+            ignore: request.ignore,
+        };
+        let replaced = previous.is_some();
+
+        if let Some(records) = &self.records {
+            records.save(&record_id(owner, &profile.name), &profile);
+        }
+        profiles.insert(key, profile.clone());
+        Ok((profile, replaced))
+    }
+
+    pub fn get(&self, owner: &str, name: &str) -> Result<MigrationProfile, AppError> {
+        self.profiles
+            .read()
+            .expect("profile store lock poisoned")
+            .get(&(owner.to_string(), name.to_string()))
+            .cloned()
+            .ok_or_else(|| AppError::NotFound(format!("No profile named {}", name)))
+    }
+
+    // The owner's profiles by name
+    pub fn list(&self, owner: &str) -> Vec<MigrationProfile> {
+        self.profiles
+            .read()
+            .expect("profile store lock poisoned")
+            .values()
+            .filter(|profile| profile.owner == owner)
+            .cloned()
+            .collect()
+    }
+
+    pub fn remove(&self, owner: &str, name: &str) -> Result<(), AppError> {
+        self.profiles
+            .write()
+            .expect("profile store lock poisoned")
+            .remove(&(owner.to_string(), name.to_string()))
+            .ok_or_else(|| AppError::NotFound(format!("No profile named {}", name)))?;
+        if let Some(records) = &self.records {
+            records.delete(&record_id(owner, name));
+        }
+        Ok(())
+    }
+}
+
+// Owners are hashed so the id is safe in a file name; names never hold a dot, so it is unique
+fn record_id(owner: &str, name: &str) -> String {
+    format!("{}.{}", &URL_SAFE_NO_PAD.encode(Sha256::digest(owner.as_bytes()))[..16], name)
+}
+
+// Names end up in URLs and file names, so they are kept to a safe set of characters
+fn check_name(name: &str) -> Result<(), AppError> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::BadRequest(
+            "Profile names are 1 to 64 letters, digits, dashes or underscores".to_string(),
+        ))
+    }
+}
+
+pub async fn save_profile_handler(
+    State(app_state): State<AppState>,
+    user: AuthenticatedUser,
+    body: Result<Json<SaveProfileRequest>, JsonRejection>,
+) -> Result<(StatusCode, Json<MigrationProfile>), AppError> {
+    let Json(request) = body?;
+    let (profile, replaced) = app_state.profiles.save(&user.owner(), request)?;
+    let status = if replaced { StatusCode::OK } else { StatusCode::CREATED };
+    Ok((status, Json(profile)))
+}
+
+pub async fn list_profiles_handler(
+    State(app_state): State<AppState>,
+    user: AuthenticatedUser,
+) -> Json<Vec<MigrationProfile>> {
+    Json(app_state.profiles.list(&user.owner()))
+}
+
+pub async fn get_profile_handler(
+    State(app_state): State<AppState>,
+    Path(name): Path<String>,
+    user: AuthenticatedUser,
+) -> Result<Json<MigrationProfile>, AppError> {
+    Ok(Json(app_state.profiles.get(&user.owner(), &name)?))
+}
+
+pub async fn delete_profile_handler(
+    State(app_state): State<AppState>,
+    Path(name): Path<String>,
+    user: AuthenticatedUser,
+) -> Result<StatusCode, AppError> {
+    app_state.profiles.remove(&user.owner(), &name)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Runs the preview the profile describes, confirmation token included, so it can be applied
+// like any other
+pub async fn preview_profile_handler(
+    state: State<AppState>,
+    Path(name): Path<String>,
+    user: AuthenticatedUser,
+    session: Session,
+) -> Result<Response, AppError> {
+    let profile = state.profiles.get(&user.owner(), &name)?;
+    let services = requested_services(&profile.services)?;
+    let params = PreviewQuery {
+        source_id: profile.source_id,
+        dest_id: profile.dest_id,
+        ignore: (!profile.ignore.is_empty()).then(|| profile.ignore.join(",")),
+        ..PreviewQuery::default()
+    };
+    preview(state, params, services, user, session).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{Collection, FileStorage};
+
+    fn request(name: &str, services: &[&str]) -> SaveProfileRequest {
+        SaveProfileRequest {
+            name: name.to_string(),
+            source_id: "staging".to_string(),
+            dest_id: "prod".to_string(),
+            services: services.iter().map(|service| service.to_string()).collect(),
+            ignore: vec!["site_url".to_string()],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_profiles_saved_replaced_and_reloaded() {
+        let dir = std::env::temp_dir().join(format!("supabasemm-profiles-{}", uuid::Uuid::new_v4()));
+        let storage = Arc::new(FileStorage {
+            job_state_dir: None,
+            snapshot_dir: None,
+            audit_log_path: None,
+            profile_dir: Some(dir.clone()),
+        });
+        let records = Records::new(storage.clone(), Collection::Profiles);
+        let profiles = Profiles::load(Some(records.clone())).await.unwrap();
+
+        let (first, replaced) = profiles.save("account:alice", request("weekly-sync", &["Auth"])).unwrap();
+        assert!(!replaced);
+        let (second, replaced) = profiles.save("account:alice", request("weekly-sync", &["Auth", "Postgrest"])).unwrap();
+        assert!(replaced);
+        assert_eq!(second.created_at, first.created_at);
+        profiles.save("account:alice", request("nightly", &["Storage"])).unwrap();
+
+        assert!(matches!(profiles.save("account:alice", request("bad name", &["Auth"])), Err(AppError::BadRequest(_))));
+        assert!(matches!(profiles.save("account:alice", request("unknown", &["Nope"])), Err(AppError::Unprocessable(_))));
+
+        // Another user's profile of the same name neither replaces nor reveals the first
+        let (_, replaced) = profiles.save("token:bob", request("weekly-sync", &["Storage"])).unwrap();
+        assert!(!replaced);
+        assert!(matches!(profiles.get("token:bob", "nightly"), Err(AppError::NotFound(_))));
+        assert!(matches!(profiles.remove("token:bob", "nightly"), Err(AppError::NotFound(_))));
+
+        profiles.remove("account:alice", "nightly").unwrap();
+        assert!(matches!(profiles.remove("account:alice", "nightly"), Err(AppError::NotFound(_))));
+        // A profile from before profiles had owners is dropped on load
+        let mut legacy = profiles.get("account:alice", "weekly-sync").unwrap();
+        legacy.name = "legacy".to_string();
+        legacy.owner = String::new();
+        records.save("legacy", &legacy);
+        records.flush().await;
+
+        let reloaded_records = Records::new(storage, Collection::Profiles);
+        let reloaded = Profiles::load(Some(reloaded_records.clone())).await.unwrap();
+        let names: Vec<String> = reloaded.list("account:alice").into_iter().map(|profile| profile.name).collect();
+        assert_eq!(names, vec!["weekly-sync"]);
+        assert_eq!(reloaded.get("account:alice", "weekly-sync").unwrap().services, vec!["Auth", "Postgrest"]);
+        assert_eq!(reloaded.get("token:bob", "weekly-sync").unwrap().services, vec!["Storage"]);
+
+        reloaded_records.flush().await;
+        assert!(!dir.join("legacy.json").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
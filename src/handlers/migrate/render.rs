@@ -0,0 +1,255 @@
+use crate::handlers::migrate::preview_handler::{comparable_value, DiffOptions};
+use crate::models::migrate::{ChangeType, PreviewSummary, PreviewWarning, ProjectConfig, RiskLevel};
+use crate::supabase_client::redact::mask_sensitive_fields;
+
+use serde_json::Value;
+use similar::TextDiff;
+use std::fmt::Write;
+
+// Unified diff of one service's pretty-printed config, from the destination to the source,
+// i.e. what a migration would change. Empty when the configs match.
+pub fn unified_diff(
+    service: &str,
+    source_id: &str,
+    dest_id: &str,
+    source: &Value,
+    dest: &Value,
+    options: &DiffOptions,
+    redact: bool,
+) -> String {
+    let (source, dest) = (pretty(service, source, options, redact), pretty(service, dest, options, redact));
+    if source == dest {
+        return String::new();
+    }
+
+    TextDiff::from_lines(&dest, &source)
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("{}/{}", dest_id, service), &format!("{}/{}", source_id, service))
+        .to_string()
+}
+
+fn pretty(service: &str, value: &Value, options: &DiffOptions, redact: bool) -> String {
+    let mut value = comparable_value(service, value, options);
+    if redact {
+        mask_sensitive_fields(&mut value);
+    }
+    // Pretty-printing a Value cannot fail
+    let mut text = serde_json::to_string_pretty(&value).unwrap_or_default();
+    text.push('\n');
+    text
+}
+
+// Report of an already redacted preview, one section and table per service, for PR
+// descriptions and change tickets
+pub fn markdown_report(
+    source_id: &str,
+    dest_id: &str,
+    configs: &[ProjectConfig],
+    warnings: &[PreviewWarning],
+) -> String {
+    let summary = PreviewSummary::of(configs);
+    let mut report = String::new();
+
+    // Writing to a String cannot fail
+    let _ = writeln!(report, "# Migration preview: `{}` → `{}`\n", source_id, dest_id);
+    let _ = writeln!(
+        report,
+        "{} changes: {} added, {} removed, {} modified.\n",
+        summary.totals.total, summary.totals.added, summary.totals.removed, summary.totals.modified
+    );
+
+    if !warnings.is_empty() {
+        let _ = writeln!(report, "## Warnings\n");
+        for warning in warnings {
+            let _ = writeln!(report, "- **{}**: {}", warning.service, cell(&warning.message));
+        }
+        report.push('\n');
+    }
+
+    if configs.is_empty() {
+        report.push_str("The selected configs already match.\n");
+    }
+
+    for config in configs {
+        let _ = writeln!(report, "## {} ({} changes)\n", config.name, config.summary.total);
+        let _ = writeln!(report, "| Key | Change | Risk | Source | Destination |");
+        let _ = writeln!(report, "| --- | --- | --- | --- | --- |");
+        for diff in &config.diffs {
+            let change = match diff.change_type {
+                ChangeType::Added => "added",
+                ChangeType::Removed => "removed",
+                ChangeType::Modified => "modified",
+            };
+            let risk = match diff.risk {
+                RiskLevel::Low => "low",
+                RiskLevel::Medium => "medium",
+                RiskLevel::High => "**high**",
+            };
+            let _ = writeln!(
+                report,
+                "| `{}` | {} | {} | {} | {} |",
+                diff.key,
+                change,
+                risk,
+                diff.source_value.as_deref().map(cell).unwrap_or_default(),
+                diff.dest_value.as_deref().map(cell).unwrap_or_default(),
+            );
+        }
+        report.push('\n');
+    }
+
+    report
+}
+
+const HTML_STYLE: &str = "body{font-family:system-ui,sans-serif;margin:2rem;color:#1f2328}\
+table{border-collapse:collapse;width:100%;margin-bottom:2rem;table-layout:fixed}\
+th,td{border:1px solid #d0d7de;padding:.4rem .6rem;text-align:left;vertical-align:top;word-break:break-word}\
+th{background:#f6f8fa}td.key{font-family:monospace}\
+td.source{background:#e6ffec}td.dest{background:#ffebe9}td.missing{background:#f6f8fa;color:#8c959f}\
+.high{color:#cf222e;font-weight:bold}.warning{background:#fff8c5;padding:.5rem;border-radius:4px}";
+
+// Standalone page with the source and destination values side by side per service, for
+// reviewers who do not read JSON
+pub fn html_report(
+    source_id: &str,
+    dest_id: &str,
+    configs: &[ProjectConfig],
+    warnings: &[PreviewWarning],
+) -> String {
+    let summary = PreviewSummary::of(configs);
+    let (source_id, dest_id) = (escape_html(source_id), escape_html(dest_id));
+    let mut page = String::new();
+
+    // Writing to a String cannot fail
+    let _ = write!(
+        page,
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Migration preview</title>\
+<style>{}</style></head><body><h1>Migration preview: {} → {}</h1>\
+<p>{} changes: {} added, {} removed, {} modified.</p>",
+        HTML_STYLE,
+        source_id,
+        dest_id,
+        summary.totals.total,
+        summary.totals.added,
+        summary.totals.removed,
+        summary.totals.modified
+    );
+
+    for warning in warnings {
+        let _ = write!(
+            page,
+            "<p class=\"warning\"><strong>{}</strong>: {}</p>",
+            escape_html(&warning.service),
+            escape_html(&warning.message)
+        );
+    }
+    if configs.is_empty() {
+        page.push_str("<p>The selected configs already match.</p>");
+    }
+
+    for config in configs {
+        let _ = write!(
+            page,
+            "<h2>{}</h2><table><tr><th>Key</th><th>Source ({})</th><th>Destination ({})</th></tr>",
+            escape_html(&config.name),
+            source_id,
+            dest_id
+        );
+        for diff in &config.diffs {
+            let _ = write!(
+                page,
+                "<tr><td class=\"key{}\">{}</td>{}{}</tr>",
+                if diff.risk == RiskLevel::High { " high" } else { "" },
+                escape_html(&diff.key),
+                value_cell("source", diff.source_value.as_deref()),
+                value_cell("dest", diff.dest_value.as_deref())
+            );
+        }
+        page.push_str("</table>");
+    }
+
+    page.push_str("</body></html>");
+    page
+}
+
+fn value_cell(class: &str, value: Option<&str>) -> String {
+    match value {
+        Some(value) => format!("<td class=\"{}\">{}</td>", class, escape_html(value)),
+        None => "<td class=\"missing\">not set</td>".to_string(),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+// Keeps a value on one table row
+fn cell(value: &str) -> String {
+    value.replace('|', "\\|").replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::migrate::DiffEntry;
+    use serde_json::json;
+
+    #[test]
+    fn test_unified_diff_goes_from_dest_to_source() {
+        let source = json!({"site_url": "https://a.dev", "smtp_pass": "hunter2"});
+        let dest = json!({"site_url": "https://b.dev", "smtp_pass": "other"});
+
+        let diff = unified_diff("Auth", "src", "dst", &source, &dest, &DiffOptions::default(), true);
+        assert!(diff.starts_with("--- dst/Auth\n+++ src/Auth\n"));
+        assert!(diff.contains("-  \"site_url\": \"https://b.dev\",\n"));
+        assert!(diff.contains("+  \"site_url\": \"https://a.dev\",\n"));
+        assert!(!diff.contains("hunter2"));
+
+        assert_eq!(unified_diff("Auth", "src", "dst", &source, &source, &DiffOptions::default(), true), "");
+    }
+
+    #[test]
+    fn test_markdown_report_has_a_table_per_service() {
+        let config = ProjectConfig::new(
+            "Auth".to_string(),
+            vec![DiffEntry {
+                key: "uri_allow_list".to_string(),
+                change_type: ChangeType::Modified,
+                source_value: Some("a|b".to_string()),
+                dest_value: Some("c".to_string()),
+                risk: RiskLevel::High,
+                truncated: false,
+            }],
+        );
+
+        let report = markdown_report("src", "dst", &[config], &[]);
+        assert!(report.starts_with("# Migration preview: `src` → `dst`\n"));
+        assert!(report.contains("## Auth (1 changes)\n"));
+        assert!(report.contains("| `uri_allow_list` | modified | **high** | a\\|b | c |\n"));
+    }
+
+    #[test]
+    fn test_html_report_escapes_values() {
+        let config = ProjectConfig::new(
+            "Auth".to_string(),
+            vec![DiffEntry {
+                key: "site_url".to_string(),
+                change_type: ChangeType::Added,
+                source_value: Some("<script>".to_string()),
+                dest_value: None,
+                risk: RiskLevel::High,
+                truncated: false,
+            }],
+        );
+
+        let page = html_report("src", "dst", &[config], &[]);
+        assert!(page.contains("<td class=\"key high\">site_url</td><td class=\"source\">&lt;script&gt;</td>"));
+        assert!(page.contains("<td class=\"missing\">not set</td>"));
+        assert!(!page.contains("<script>"));
+    }
+}
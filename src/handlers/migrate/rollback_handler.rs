@@ -0,0 +1,59 @@
+use crate::handlers::migrate::apply_handler::restore_service;
+use crate::handlers::auth::AuthenticatedUser;
+use crate::handlers::migrate::audit::audit_entry;
+use crate::error::AppError;
+use crate::models::migrate::{ApplyResponse, ApplyResult, ApplySnapshot, AuditAction};
+use crate::supabase_client::ManagementApi;
+use crate::models::AppState;
+
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Json},
+};
+
+pub async fn rollback_handler(
+    State(app_state): State<AppState>,
+    Path(apply_id): Path<String>,
+    user: AuthenticatedUser,
+) -> Result<impl IntoResponse, AppError> {
+    let actor = user.fingerprint();
+    let api = app_state.management_client(user.access_token);
+
+    let snapshot = app_state
+        .apply_snapshots
+        .get(&apply_id)
+        .ok_or_else(|| AppError::NotFound(format!("No snapshot found for apply {}", apply_id)))?;
+
+    let restored = restore_snapshot(&api, &snapshot).await;
+    app_state.audit.record(audit_entry(
+        actor,
+        AuditAction::Rollback,
+        &apply_id,
+        None,
+        &snapshot.dest_id,
+        snapshot.services.iter().map(|service| (service.service.as_str(), service.keys.len())),
+        restored.as_ref().err().map(ToString::to_string),
+    ));
+    let results = restored?;
+
+    app_state.apply_snapshots.remove(&apply_id);
+
+    Ok(Json(ApplyResponse { apply_id, results }))
+}
+
+async fn restore_snapshot(api: &impl ManagementApi, snapshot: &ApplySnapshot) -> Result<Vec<ApplyResult>, AppError> {
+    let mut results: Vec<ApplyResult> = Vec::new();
+
+    for service in &snapshot.services {
+        restore_service(api, &snapshot.dest_id, service)
+            .await
+            .map_err(|e| e.context(format!("Failed to roll back {} config", service.service)))?;
+
+        results.push(ApplyResult {
+            service: service.service.clone(),
+            applied_keys: service.keys.clone(),
+        });
+    }
+
+    Ok(results)
+}
@@ -0,0 +1,155 @@
+use crate::error::AppError;
+use crate::handlers::migrate::services::{find_item, selected_ids};
+use crate::supabase_client::ManagementApi;
+
+use serde_json::{json, Value};
+
+// Creates or updates the selected secrets from the source. Destination-only secrets are
+// deleted only when asked to.
+pub async fn apply_secrets(
+    api: &impl ManagementApi,
+    dest_id: &str,
+    source: &Value,
+    dest: &Value,
+    keys: &[String],
+    delete_missing: bool,
+) -> Result<(), AppError> {
+    sync_secrets(api, dest_id, source, dest, keys, delete_missing).await
+}
+
+// Puts back the values the snapshot recorded and deletes secrets the apply created
+pub async fn restore_secrets(
+    api: &impl ManagementApi,
+    dest_id: &str,
+    snapshot: &Value,
+    current: &Value,
+    keys: &[String],
+) -> Result<(), AppError> {
+    sync_secrets(api, dest_id, snapshot, current, keys, true).await
+}
+
+// Makes the selected secrets on the destination match `wanted`
+async fn sync_secrets(
+    api: &impl ManagementApi,
+    dest_id: &str,
+    wanted: &Value,
+    current: &Value,
+    keys: &[String],
+    delete_missing: bool,
+) -> Result<(), AppError> {
+    let (upserts, deletes) = secret_changes(wanted, current, keys, delete_missing);
+
+    if !upserts.is_empty() {
+        api.upsert_secrets(dest_id, &upserts)
+            .await
+        .map_err(|e| e.context("Failed to write secrets"))?;
+    }
+
+    if !deletes.is_empty() {
+        api.delete_secrets(dest_id, &deletes)
+            .await
+        .map_err(|e| e.context("Failed to delete secrets"))?;
+    }
+
+    Ok(())
+}
+
+// Secrets to create or update, and names to delete. SUPABASE_ secrets are managed by the
+// platform and never touched, and secrets without a value are skipped rather than written
+// as null.
+fn secret_changes(
+    wanted: &Value,
+    current: &Value,
+    keys: &[String],
+    delete_missing: bool,
+) -> (Vec<Value>, Vec<String>) {
+    let mut upserts = Vec::new();
+    let mut deletes = Vec::new();
+
+    for name in selected_ids(keys, &[wanted, current], &["name"]) {
+        if name.starts_with("SUPABASE_") {
+            continue;
+        }
+        match (find_item(wanted, &["name"], &name), find_item(current, &["name"], &name)) {
+            (Some(secret), existing) => {
+                let Some(value) = secret.get("value").filter(|value| value.is_string()) else {
+                    tracing::warn!(name = %name, "skipping a secret without a value");
+                    continue;
+                };
+                if existing.and_then(|existing| existing.get("value")) != Some(value) {
+                    upserts.push(json!({ "name": name, "value": value }));
+                }
+            }
+            (None, Some(_)) if delete_missing => deletes.push(name),
+            _ => {}
+        }
+    }
+
+    (upserts, deletes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::supabase_client::mock::MockApi;
+    use reqwest::Method;
+
+    #[tokio::test]
+    async fn test_apply_secrets_upserts_in_one_request() {
+        let api = MockApi::default();
+        let source = json!([{"name": "API_KEY", "value": "new"}, {"name": "OTHER", "value": "same"}]);
+        let dest = json!([{"name": "API_KEY", "value": "old"}, {"name": "OTHER", "value": "same"}]);
+        let keys = vec!["id:API_KEY.value".to_string()];
+
+        apply_secrets(&api, "dst", &source, &dest, &keys, true).await.unwrap();
+
+        assert_eq!(
+            api.calls(),
+            vec![(
+                Method::POST,
+                "/projects/dst/secrets".to_string(),
+                Some(json!([{"name": "API_KEY", "value": "new"}]))
+            )]
+        );
+    }
+
+    #[test]
+    fn test_secret_changes_skip_platform_secrets() {
+        let source = json!([
+            {"name": "API_KEY", "value": "new"},
+            {"name": "WEBHOOK_URL", "value": "https://example.com"},
+            {"name": "SUPABASE_URL", "value": "https://src.supabase.co"},
+            {"name": "NO_VALUE"},
+            {"name": "NULL_VALUE", "value": null}
+        ]);
+        let dest = json!([
+            {"name": "API_KEY", "value": "old"},
+            {"name": "LEGACY_TOKEN", "value": "stale"},
+            {"name": "SUPABASE_URL", "value": "https://dst.supabase.co"}
+        ]);
+        let keys: Vec<String> = [
+            "id:API_KEY.value",
+            "id:WEBHOOK_URL",
+            "id:LEGACY_TOKEN",
+            "id:SUPABASE_URL.value",
+            "id:NO_VALUE",
+            "id:NULL_VALUE",
+        ]
+            .iter()
+            .map(|key| key.to_string())
+            .collect();
+
+        let (upserts, deletes) = secret_changes(&source, &dest, &keys, false);
+        assert_eq!(
+            upserts,
+            vec![
+                json!({"name": "API_KEY", "value": "new"}),
+                json!({"name": "WEBHOOK_URL", "value": "https://example.com"})
+            ]
+        );
+        assert!(deletes.is_empty());
+
+        let (_, deletes) = secret_changes(&source, &dest, &keys, true);
+        assert_eq!(deletes, vec!["LEGACY_TOKEN".to_string()]);
+    }
+}
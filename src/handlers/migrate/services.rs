@@ -0,0 +1,1406 @@
+use crate::error::AppError;
+use crate::handlers::migrate::strategy::{DiffStrategy, FunctionsBySlug, Recursive, SecretsByName};
+use crate::handlers::migrate::webhooks::{WEBHOOKS_QUERY, WEBHOOK_IDENTITY};
+use crate::models::migrate::{AuthConfig, DiffEntry, PostgresConfig, PostgrestConfig, RiskLevel, Secret};
+use crate::supabase_client::ManagementApi;
+
+use reqwest::Method;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+// How apply writes a service's selected keys to the destination
+pub enum ApplyStrategy {
+    // Preview only
+    Unsupported,
+    // Send the selected fields back to the service path with this method
+    Patch(Method),
+    // Recreate database webhooks from the source project's trigger definitions
+    Webhooks,
+    // Deploy the source project's edge function bundles
+    Functions,
+    // Upsert secrets by name, optionally deleting destination-only ones
+    Secrets,
+}
+
+// Services the client has typed reads for. The typed model validates the payload before the
+// service's transform sees it, and list reads follow pages.
+#[derive(Clone, Copy)]
+pub enum TypedRead {
+    AuthConfig,
+    Functions,
+    Secrets,
+}
+
+impl TypedRead {
+    async fn fetch(self, api: &impl ManagementApi, project_id: &str) -> Result<Value, AppError> {
+        Ok(match self {
+            TypedRead::AuthConfig => serde_json::to_value(api.get_auth_config(project_id).await?)?,
+            TypedRead::Functions => serde_json::to_value(api.list_functions(project_id).await?)?,
+            TypedRead::Secrets => serde_json::to_value(api.list_secrets(project_id).await?)?,
+        })
+    }
+}
+
+// Risk of changing a field. The field is matched against every segment of a diff key, and
+// a trailing * matches by prefix.
+pub struct RiskRule {
+    pub field: &'static str,
+    pub level: RiskLevel,
+    // Only applies when the migration would set the field to this value
+    pub when: Option<&'static str>,
+}
+
+impl RiskRule {
+    const fn always(field: &'static str, level: RiskLevel) -> Self {
+        RiskRule { field, level, when: None }
+    }
+
+    const fn when(field: &'static str, value: &'static str, level: RiskLevel) -> Self {
+        RiskRule { field, level, when: Some(value) }
+    }
+
+    fn matches(&self, entry: &DiffEntry) -> bool {
+        let field_matches = entry
+            .key
+            .split('.')
+            .map(|segment| segment.split('[').next().unwrap_or_default())
+            .any(|segment| match self.field.strip_suffix('*') {
+                Some(prefix) => segment.starts_with(prefix),
+                None => segment == self.field,
+            });
+        field_matches && self.when.is_none_or(|value| entry.source_value.as_deref() == Some(value))
+    }
+}
+
+// The first matching rule wins; changes no rule covers are medium risk
+pub fn assess_risk(spec: &ServiceSpec, entry: &DiffEntry) -> RiskLevel {
+    spec.risk
+        .iter()
+        .find(|rule| rule.matches(entry))
+        .map(|rule| rule.level)
+        .unwrap_or_default()
+}
+
+// A config type that preview can diff and, unless its apply strategy is unsupported,
+// apply can write
+pub struct ServiceSpec {
+    pub name: &'static str,
+    // Project-relative Management API path the config is read from and written to
+    pub path: &'static str,
+    // Most configs are read with GET; a few list endpoints only accept POST
+    pub fetch_method: Method,
+    // SQL read through the database query endpoint instead of a config path
+    pub fetch_query: Option<&'static str>,
+    // Typed client read used instead of fetching the path as plain JSON
+    pub read: Option<TypedRead>,
+    pub apply: ApplyStrategy,
+    // Services that must be migrated first, e.g. functions read secrets at runtime
+    pub depends_on: &'static [&'static str],
+    // Field that identifies the same item in both projects when the config is an array
+    pub id_key: &'static str,
+    // Paths of arrays whose items are only unique by several fields together, e.g. webhooks
+    // by schema, table and name; "" is the config itself
+    pub identity: &'static [(&'static str, &'static [&'static str])],
+    // Paths of lists of plain values whose order carries no meaning, e.g. a bucket's MIME
+    // types; they are sorted before comparing. Every other list is compared in order.
+    pub unordered: &'static [&'static str],
+    pub diff: &'static dyn DiffStrategy,
+    // Fields that differ between any two projects and are left out of the diff, on top of
+    // the timestamps every service ignores
+    pub ignore: &'static [&'static str],
+    pub risk: &'static [RiskRule],
+    // Reshapes the fetched payload before diffing, e.g. to keep only the comparable fields
+    pub transform: Option<fn(Value) -> Value>,
+    // Typed model the transformed payload must deserialize into; unknown fields are dropped
+    pub model: Option<fn(Value) -> Result<Value, serde_json::Error>>,
+    // Maps a patch built from transformed keys back to the field names the API accepts
+    pub patch_transform: Option<fn(Value) -> Value>,
+    // Compares the transformed source and dest configs for problems the diff alone would not flag
+    pub check: Option<fn(&Value, &Value) -> Option<String>>,
+    // Supabase's documented defaults in the shape the API returns, used when the source is
+    // DEFAULTS_SOURCE
+    pub defaults: Option<fn() -> Value>,
+}
+
+impl ServiceSpec {
+    const DEFAULT: ServiceSpec = ServiceSpec {
+        name: "",
+        path: "",
+        fetch_method: Method::GET,
+        fetch_query: None,
+        read: None,
+        apply: ApplyStrategy::Unsupported,
+        depends_on: &[],
+        id_key: "id",
+        identity: &[],
+        unordered: &[],
+        diff: &Recursive,
+        ignore: &[],
+        risk: &[],
+        transform: None,
+        model: None,
+        patch_transform: None,
+        check: None,
+        defaults: None,
+    };
+}
+
+pub static SERVICES: &[ServiceSpec] = &[
+    ServiceSpec {
+        name: "Auth",
+        path: "/config/auth",
+        read: Some(TypedRead::AuthConfig),
+        apply: ApplyStrategy::Patch(Method::PATCH),
+        transform: Some(auth_settings),
+        model: Some(typed::<AuthConfig>),
+        risk: &[
+            RiskRule::always("site_url", RiskLevel::High),
+            RiskRule::always("uri_allow_list", RiskLevel::High),
+            RiskRule::when("disable_signup", "true", RiskLevel::High),
+            // Turning on autoconfirm turns off email confirmations
+            RiskRule::when("mailer_autoconfirm", "true", RiskLevel::High),
+            RiskRule::when("external_email_enabled", "false", RiskLevel::High),
+            RiskRule::always("rate_limit_*", RiskLevel::Low),
+        ],
+        defaults: Some(auth_defaults),
+        ..ServiceSpec::DEFAULT
+    },
+    ServiceSpec {
+        name: "EmailTemplates",
+        path: "/config/auth",
+        apply: ApplyStrategy::Patch(Method::PATCH),
+        transform: Some(email_templates),
+        patch_transform: Some(email_template_fields),
+        ..ServiceSpec::DEFAULT
+    },
+    ServiceSpec {
+        name: "Sms",
+        path: "/config/auth",
+        transform: Some(sms_settings),
+        ..ServiceSpec::DEFAULT
+    },
+    ServiceSpec {
+        name: "Postgrest",
+        path: "/postgrest",
+        apply: ApplyStrategy::Patch(Method::PATCH),
+        model: Some(typed::<PostgrestConfig>),
+        depends_on: &["Postgres"],
+        risk: &[
+            // Exposes or hides whole schemas through the API
+            RiskRule::always("db_schema", RiskLevel::High),
+            RiskRule::always("max_rows", RiskLevel::Low),
+        ],
+        defaults: Some(postgrest_defaults),
+        ..ServiceSpec::DEFAULT
+    },
+    ServiceSpec {
+        name: "EdgeFunctions",
+        path: "/functions",
+        read: Some(TypedRead::Functions),
+        apply: ApplyStrategy::Functions,
+        depends_on: &["Secrets"],
+        diff: &FunctionsBySlug,
+        transform: Some(function_settings),
+        risk: &[RiskRule::when("verify_jwt", "false", RiskLevel::High)],
+        defaults: Some(no_items),
+        ..ServiceSpec::DEFAULT
+    },
+    ServiceSpec {
+        name: "Secrets",
+        path: "/secrets",
+        read: Some(TypedRead::Secrets),
+        apply: ApplyStrategy::Secrets,
+        diff: &SecretsByName,
+        model: Some(typed::<Vec<Secret>>),
+        defaults: Some(no_items),
+        ..ServiceSpec::DEFAULT
+    },
+    ServiceSpec {
+        name: "Postgres",
+        path: "/config/database/postgres",
+        apply: ApplyStrategy::Patch(Method::PUT),
+        model: Some(typed::<PostgresConfig>),
+        risk: &[RiskRule::always("max_connections", RiskLevel::High)],
+        ..ServiceSpec::DEFAULT
+    },
+    ServiceSpec {
+        name: "Storage",
+        path: "/config/storage",
+        apply: ApplyStrategy::Patch(Method::PATCH),
+        ..ServiceSpec::DEFAULT
+    },
+    ServiceSpec {
+        name: "Pooler",
+        path: "/config/database/pooler",
+        apply: ApplyStrategy::Patch(Method::PATCH),
+        depends_on: &["Postgres"],
+        transform: Some(pooler_settings),
+        patch_transform: Some(pooler_patch),
+        ..ServiceSpec::DEFAULT
+    },
+    ServiceSpec {
+        name: "Backups",
+        path: "/database/backups",
+        transform: Some(backup_settings),
+        ..ServiceSpec::DEFAULT
+    },
+    ServiceSpec {
+        name: "ReadReplicas",
+        path: "/databases",
+        id_key: "replica",
+        transform: Some(read_replicas),
+        defaults: Some(no_items),
+        ..ServiceSpec::DEFAULT
+    },
+    ServiceSpec {
+        name: "Addons",
+        path: "/billing/addons",
+        transform: Some(addon_settings),
+        check: Some(compute_capacity),
+        ..ServiceSpec::DEFAULT
+    },
+    ServiceSpec {
+        name: "PostgresVersion",
+        path: "",
+        // Minor releases are rolled out per project; only the major version matters
+        ignore: &["version"],
+        transform: Some(postgres_version),
+        check: Some(postgres_version_compat),
+        ..ServiceSpec::DEFAULT
+    },
+    ServiceSpec {
+        name: "Realtime",
+        path: "/config/realtime",
+        apply: ApplyStrategy::Patch(Method::PATCH),
+        ..ServiceSpec::DEFAULT
+    },
+    ServiceSpec {
+        name: "Buckets",
+        path: "/storage/buckets",
+        id_key: "name",
+        unordered: &["allowed_mime_types"],
+        transform: Some(bucket_settings),
+        risk: &[RiskRule::when("public", "true", RiskLevel::High)],
+        defaults: Some(no_items),
+        ..ServiceSpec::DEFAULT
+    },
+    ServiceSpec {
+        name: "NetworkRestrictions",
+        path: "/network-restrictions",
+        id_key: "cidr",
+        transform: Some(network_restrictions),
+        risk: &[RiskRule::always("dbAllowedCidrs*", RiskLevel::High)],
+        defaults: Some(network_restrictions_defaults),
+        ..ServiceSpec::DEFAULT
+    },
+    ServiceSpec {
+        name: "SslEnforcement",
+        path: "/ssl-enforcement",
+        transform: Some(ssl_enforcement),
+        risk: &[RiskRule::when("database", "false", RiskLevel::High)],
+        defaults: Some(ssl_enforcement_defaults),
+        ..ServiceSpec::DEFAULT
+    },
+    ServiceSpec {
+        name: "OAuthProviders",
+        path: "/config/auth",
+        transform: Some(oauth_providers),
+        ..ServiceSpec::DEFAULT
+    },
+    ServiceSpec {
+        name: "AuthHooks",
+        path: "/config/auth",
+        apply: ApplyStrategy::Patch(Method::PATCH),
+        transform: Some(auth_hooks),
+        ..ServiceSpec::DEFAULT
+    },
+    ServiceSpec {
+        name: "Mfa",
+        path: "/config/auth",
+        apply: ApplyStrategy::Patch(Method::PATCH),
+        transform: Some(mfa_settings),
+        defaults: Some(auth_defaults),
+        ..ServiceSpec::DEFAULT
+    },
+    ServiceSpec {
+        name: "Captcha",
+        path: "/config/auth",
+        apply: ApplyStrategy::Patch(Method::PATCH),
+        transform: Some(captcha_settings),
+        risk: &[RiskRule::when("security_captcha_enabled", "false", RiskLevel::High)],
+        defaults: Some(auth_defaults),
+        ..ServiceSpec::DEFAULT
+    },
+    ServiceSpec {
+        name: "Smtp",
+        path: "/config/auth",
+        transform: Some(smtp_settings),
+        ..ServiceSpec::DEFAULT
+    },
+    ServiceSpec {
+        name: "Sso",
+        path: "/config/auth/sso/providers",
+        id_key: "domain",
+        transform: Some(sso_providers),
+        defaults: Some(no_sso_providers),
+        ..ServiceSpec::DEFAULT
+    },
+    ServiceSpec {
+        name: "Webhooks",
+        path: "/database/query",
+        fetch_method: Method::POST,
+        fetch_query: Some(WEBHOOKS_QUERY),
+        apply: ApplyStrategy::Webhooks,
+        id_key: "name",
+        defaults: Some(no_items),
+        identity: &[("", WEBHOOK_IDENTITY)],
+        ..ServiceSpec::DEFAULT
+    },
+    ServiceSpec {
+        name: "NetworkBans",
+        path: "/network-bans/retrieve",
+        fetch_method: Method::POST,
+        id_key: "address",
+        transform: Some(network_bans),
+        ..ServiceSpec::DEFAULT
+    },
+];
+
+pub fn find_service(name: &str) -> Option<&'static ServiceSpec> {
+    SERVICES.iter().find(|spec| spec.name == name)
+}
+
+pub async fn fetch_service_config(
+    api: &impl ManagementApi,
+    project_id: &str,
+    spec: &ServiceSpec,
+) -> Result<Value, AppError> {
+    if project_id == DEFAULTS_SOURCE {
+        let defaults = spec.defaults.ok_or_else(|| {
+            AppError::BadRequest(format!("No documented defaults to compare {} against", spec.name))
+        })?;
+        return shape_config(spec, defaults());
+    }
+
+    let body = spec.fetch_query.map(|query| json!({ "query": query }));
+    let config = match spec.read {
+        Some(read) => read.fetch(api, project_id).await,
+        None => {
+            api.get_config(spec.fetch_method.clone(), project_id, spec.path, body.as_ref())
+                .await
+        }
+    }
+        .map_err(|e| e.context(format!("Failed to get {} config", spec.name)))?;
+
+    shape_config(spec, config)
+}
+
+// Turns a payload as the API returns it into the config that is diffed
+fn shape_config(spec: &ServiceSpec, config: Value) -> Result<Value, AppError> {
+    let config = match spec.transform {
+        Some(transform) => transform(config),
+        None => config,
+    };
+    match spec.model {
+        Some(model) => model(config)
+            .map_err(|e| AppError::ApiError(format!("Unexpected {} config: {}", spec.name, e))),
+        None => Ok(config),
+    }
+}
+
+// Source ID that stands for a stock project, to audit how far a project has drifted
+pub const DEFAULTS_SOURCE: &str = "defaults";
+
+// Defaults of a new hosted project, per the Supabase docs and CLI config template
+fn auth_defaults() -> Value {
+    json!({
+        "site_url": "http://localhost:3000",
+        "uri_allow_list": "",
+        "disable_signup": false,
+        "jwt_exp": 3600,
+        "external_email_enabled": true,
+        "external_phone_enabled": false,
+        "external_anonymous_users_enabled": false,
+        "mailer_autoconfirm": false,
+        "mailer_secure_email_change_enabled": true,
+        "mailer_otp_exp": 3600,
+        "mailer_otp_length": 6,
+        "password_min_length": 6,
+        "password_required_characters": "",
+        "password_hibp_enabled": false,
+        "refresh_token_rotation_enabled": true,
+        "security_refresh_token_reuse_interval": 10,
+        "security_update_password_require_reauth": false,
+        "security_manual_linking_enabled": false,
+        "sessions_timebox": 0,
+        "sessions_inactivity_timeout": 0,
+        "sessions_single_per_user": false,
+        "sessions_tags": "",
+        "rate_limit_email_sent": 2,
+        "rate_limit_sms_sent": 30,
+        "rate_limit_verify": 30,
+        "rate_limit_token_refresh": 150,
+        "rate_limit_otp": 30,
+        "rate_limit_anonymous_users": 30,
+        "mfa_max_enrolled_factors": 10,
+        "mfa_totp_enroll_enabled": true,
+        "mfa_totp_verify_enabled": true,
+        "mfa_phone_enroll_enabled": false,
+        "mfa_phone_verify_enabled": false,
+        "mfa_web_authn_enroll_enabled": false,
+        "mfa_web_authn_verify_enabled": false,
+        "security_captcha_enabled": false,
+        "security_captcha_provider": "hcaptcha",
+    })
+}
+
+fn postgrest_defaults() -> Value {
+    json!({
+        "db_schema": "public,graphql_public",
+        "db_extra_search_path": "public,extensions",
+        "max_rows": 1000,
+    })
+}
+
+fn ssl_enforcement_defaults() -> Value {
+    json!({ "currentConfig": { "database": false } })
+}
+
+fn network_restrictions_defaults() -> Value {
+    json!({ "config": { "dbAllowedCidrs": ["0.0.0.0/0"], "dbAllowedCidrsV6": ["::/0"] } })
+}
+
+fn no_items() -> Value {
+    json!([])
+}
+
+fn no_sso_providers() -> Value {
+    json!({ "items": [] })
+}
+
+// Round-trips a payload through its typed model
+fn typed<T: DeserializeOwned + Serialize>(config: Value) -> Result<Value, serde_json::Error> {
+    serde_json::to_value(serde_json::from_value::<T>(config)?)
+}
+
+// Auth fields that are diffed under their own config type instead of Auth
+const DERIVED_AUTH_PREFIXES: &[&str] = &[
+    "mailer_subjects_",
+    "mailer_templates_",
+    "sms_",
+    "mfa_",
+    "security_captcha_",
+    "smtp_",
+];
+
+// Suffixes of the SMS provider fields that hold credentials
+const SMS_CREDENTIAL_SUFFIXES: &[&str] = &["_auth_token", "_access_key", "_api_key", "_api_secret"];
+
+// Per-provider external_<provider>_<field> settings, longest first so suffixes match whole
+const OAUTH_PROVIDER_FIELDS: &[&str] = &[
+    "additional_client_ids",
+    "skip_nonce_check",
+    "email_optional",
+    "redirect_uri",
+    "client_id",
+    "enabled",
+    "secret",
+    "url",
+];
+
+// external_*_enabled toggles that are sign-in methods rather than OAuth providers
+const NON_OAUTH_PROVIDERS: &[&str] = &["email", "phone", "anonymous_users"];
+
+const REDACTED: &str = "***";
+
+fn auth_settings(auth: Value) -> Value {
+    retain_fields(auth, |key| {
+        !DERIVED_AUTH_PREFIXES.iter().any(|prefix| key.starts_with(prefix))
+            && oauth_provider_field(key).is_none()
+            && !is_hook_setting(key)
+    })
+}
+
+// The sections below keep the original field names so the selected keys can be patched
+// straight back to /config/auth
+fn mfa_settings(auth: Value) -> Value {
+    retain_fields(auth, |key| key.starts_with("mfa_"))
+}
+
+fn captcha_settings(auth: Value) -> Value {
+    retain_fields(auth, |key| key.starts_with("security_captcha_"))
+}
+
+// Custom SMTP sender settings with the password masked. Preview only, since the masked
+// password cannot be written back.
+fn smtp_settings(auth: Value) -> Value {
+    match retain_fields(auth, |key| key.starts_with("smtp_")) {
+        Value::Object(mut fields) => {
+            if let Some(password) = fields.get_mut("smtp_pass") {
+                *password = redact(password);
+            }
+            Value::Object(fields)
+        }
+        other => other,
+    }
+}
+
+fn retain_fields(config: Value, keep: impl Fn(&str) -> bool) -> Value {
+    match config {
+        Value::Object(mut fields) => {
+            fields.retain(|key, _| keep(key));
+            Value::Object(fields)
+        }
+        other => other,
+    }
+}
+
+// hook_<name>_enabled and hook_<name>_uri. Hook secrets stay with the rest of Auth.
+fn is_hook_setting(key: &str) -> bool {
+    key.starts_with("hook_") && (key.ends_with("_enabled") || key.ends_with("_uri"))
+}
+
+fn auth_hooks(auth: Value) -> Value {
+    retain_fields(auth, is_hook_setting)
+}
+
+// Splits external_google_client_id into ("google", "client_id")
+fn oauth_provider_field(key: &str) -> Option<(&str, &str)> {
+    let rest = key.strip_prefix("external_")?;
+    OAUTH_PROVIDER_FIELDS.iter().find_map(|field| {
+        let provider = rest.strip_suffix(field)?.strip_suffix('_')?;
+        (!provider.is_empty() && !NON_OAUTH_PROVIDERS.contains(&provider)).then_some((provider, *field))
+    })
+}
+
+// Masks a secret while keeping whether it is set visible in the diff
+fn redact(value: &Value) -> Value {
+    match value {
+        Value::String(secret) if !secret.is_empty() => json!(REDACTED),
+        Value::String(_) => Value::Null,
+        other => other.clone(),
+    }
+}
+
+// One entry per OAuth provider, keyed like google.client_id, with client secrets masked.
+// Preview only, since the masked secrets cannot be written back.
+fn oauth_providers(auth: Value) -> Value {
+    let mut providers = Map::new();
+
+    for (key, value) in auth.as_object().into_iter().flatten() {
+        let Some((provider, field)) = oauth_provider_field(key) else {
+            continue;
+        };
+        let value = if field == "secret" { redact(value) } else { value.clone() };
+
+        if let Value::Object(settings) = providers
+            .entry(provider.to_string())
+            .or_insert_with(|| Value::Object(Map::new()))
+        {
+            settings.insert(field.to_string(), value);
+        }
+    }
+
+    Value::Object(providers)
+}
+
+// Splits the flat mailer_* auth fields into one entry per template, so keys read like
+// templates.confirmation.subject
+fn email_templates(auth: Value) -> Value {
+    let mut templates = Map::new();
+
+    for (key, value) in auth.as_object().into_iter().flatten() {
+        let (name, part) = if let Some(name) = key.strip_prefix("mailer_subjects_") {
+            (name, "subject")
+        } else if let Some(name) = key
+            .strip_prefix("mailer_templates_")
+            .and_then(|rest| rest.strip_suffix("_content"))
+        {
+            (name, "content")
+        } else {
+            continue;
+        };
+
+        if let Value::Object(template) = templates
+            .entry(name.to_string())
+            .or_insert_with(|| Value::Object(Map::new()))
+        {
+            template.insert(part.to_string(), value.clone());
+        }
+    }
+
+    json!({ "templates": templates })
+}
+
+fn email_template_fields(patch: Value) -> Value {
+    let mut fields = Map::new();
+
+    let templates = patch.get("templates").and_then(Value::as_object);
+    for (name, template) in templates.into_iter().flatten() {
+        for (part, value) in template.as_object().into_iter().flatten() {
+            let key = match part.as_str() {
+                "subject" => format!("mailer_subjects_{}", name),
+                "content" => format!("mailer_templates_{}_content", name),
+                _ => continue,
+            };
+            fields.insert(key, value.clone());
+        }
+    }
+
+    Value::Object(fields)
+}
+
+// SMS provider, template and OTP settings. Credentials are redacted, so this config type
+// is preview only.
+fn sms_settings(auth: Value) -> Value {
+    let mut settings = Map::new();
+
+    for (key, value) in auth.as_object().into_iter().flatten() {
+        if !key.starts_with("sms_") {
+            continue;
+        }
+        let is_credential = SMS_CREDENTIAL_SUFFIXES.iter().any(|suffix| key.ends_with(suffix));
+        settings.insert(key.clone(), if is_credential { redact(value) } else { value.clone() });
+    }
+
+    Value::Object(settings)
+}
+
+// One item per provider domain, since provider IDs are generated per project. Providers
+// without a domain fall back to their SAML entity ID.
+fn sso_providers(providers: Value) -> Value {
+    let mut items = Vec::new();
+
+    let providers = providers.get("items").and_then(Value::as_array);
+    for provider in providers.into_iter().flatten() {
+        let saml = provider.get("saml").cloned().unwrap_or(Value::Null);
+        let item = |domain: Value| {
+            json!({
+                "domain": domain,
+                "entity_id": saml.get("entity_id"),
+                "metadata_url": saml.get("metadata_url"),
+                "attribute_mapping": saml.get("attribute_mapping"),
+            })
+        };
+
+        let domains: Vec<Value> = provider
+            .get("domains")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|domain| domain.get("domain").cloned())
+            .collect();
+
+        if domains.is_empty() {
+            items.push(item(saml.get("entity_id").cloned().unwrap_or(Value::Null)));
+        } else {
+            items.extend(domains.into_iter().map(item));
+        }
+    }
+
+    Value::Array(items)
+}
+
+// Resolves diff keys like `id:<id>` or `id:<id>.<field>` against the item IDs found in the
+// given array configs, in order of first appearance
+pub fn selected_ids(keys: &[String], configs: &[&Value], identity: &[&str]) -> Vec<String> {
+    let mut ids: Vec<String> = Vec::new();
+
+    for config in configs {
+        for item in config.as_array().into_iter().flatten() {
+            let Some(id) = item_id(item, identity) else {
+                continue;
+            };
+            let key = format!("id:{}", id);
+            let selected = keys
+                .iter()
+                .any(|k| *k == key || k.strip_prefix(key.as_str()).is_some_and(|rest| rest.starts_with('.')));
+            if selected && !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+    }
+
+    ids
+}
+
+pub fn find_item<'a>(config: &'a Value, identity: &[&str], id: &str) -> Option<&'a Value> {
+    config
+        .as_array()?
+        .iter()
+        .find(|item| item_id(item, identity).as_deref() == Some(id))
+}
+
+// An array item's ID as used in diff keys: its identity fields joined with "/". None when
+// one of them is missing or not a string.
+pub fn item_id(item: &Value, identity: &[&str]) -> Option<String> {
+    let parts = identity
+        .iter()
+        .map(|field| item.get(field).and_then(Value::as_str))
+        .collect::<Option<Vec<_>>>()?;
+    Some(parts.join("/"))
+}
+
+// Function IDs and versions are per project; the bundle hash tells whether the code differs
+fn function_settings(functions: Value) -> Value {
+    const FIELDS: [&str; 7] = [
+        "slug",
+        "name",
+        "verify_jwt",
+        "import_map",
+        "entrypoint_path",
+        "import_map_path",
+        "ezbr_sha256",
+    ];
+
+    match functions {
+        Value::Array(functions) => Value::Array(
+            functions
+                .into_iter()
+                .map(|function| retain_fields(function, |key| FIELDS.contains(&key)))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+// Keeps only the bucket settings worth comparing across projects
+fn bucket_settings(buckets: Value) -> Value {
+    const FIELDS: [&str; 4] = ["name", "public", "allowed_mime_types", "file_size_limit"];
+
+    match buckets {
+        Value::Array(buckets) => Value::Array(
+            buckets
+                .into_iter()
+                .map(|bucket| match bucket {
+                    Value::Object(mut fields) => {
+                        fields.retain(|key, _| FIELDS.contains(&key.as_str()));
+                        Value::Object(fields)
+                    }
+                    other => other,
+                })
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+// The pooler endpoint lists one entry per database; compare the primary's settings
+fn pooler_settings(poolers: Value) -> Value {
+    const FIELDS: [&str; 3] = ["pool_mode", "default_pool_size", "max_client_conn"];
+
+    let primary = poolers.as_array().and_then(|poolers| {
+        poolers
+            .iter()
+            .find(|pooler| pooler.get("database_type").and_then(Value::as_str) == Some("PRIMARY"))
+            .or_else(|| poolers.first())
+    });
+
+    retain_fields(
+        primary.cloned().unwrap_or(Value::Object(Map::new())),
+        |key| FIELDS.contains(&key),
+    )
+}
+
+// max_client_conn follows the compute size and is read only
+fn pooler_patch(patch: Value) -> Value {
+    retain_fields(patch, |key| key != "max_client_conn")
+}
+
+// Compares recovery posture rather than the individual backups, which always differ.
+// The number of retained daily backups stands in for the plan's retention period.
+fn backup_settings(backups: Value) -> Value {
+    let retained = backups
+        .get("backups")
+        .and_then(Value::as_array)
+        .map_or(0, |backups| backups.len());
+
+    json!({
+        "pitr_enabled": backups.get("pitr_enabled").cloned().unwrap_or(Value::Bool(false)),
+        "walg_enabled": backups.get("walg_enabled").cloned().unwrap_or(Value::Bool(false)),
+        "retained_backups": retained,
+    })
+}
+
+// Replica identifiers are generated per project, so replicas are matched by region, with
+// a counter when a region has more than one
+fn read_replicas(databases: Value) -> Value {
+    let mut replicas: Vec<Value> = Vec::new();
+    let mut per_region: HashMap<String, usize> = HashMap::new();
+
+    for database in databases.as_array().into_iter().flatten() {
+        if database.get("type").and_then(Value::as_str) != Some("READ_REPLICA") {
+            continue;
+        }
+        let region = database.get("region").and_then(Value::as_str).unwrap_or("unknown");
+        let count = per_region.entry(region.to_string()).or_default();
+        *count += 1;
+
+        let replica = if *count == 1 {
+            region.to_string()
+        } else {
+            format!("{}#{}", region, count)
+        };
+        replicas.push(json!({
+            "replica": replica,
+            "region": region,
+            "status": database.get("status"),
+        }));
+    }
+
+    Value::Array(replicas)
+}
+
+// Database version from the project metadata
+fn postgres_version(project: Value) -> Value {
+    let version = project.pointer("/database/version").cloned().unwrap_or(Value::Null);
+    let major = version
+        .as_str()
+        .and_then(|version| version.split('.').next())
+        .and_then(|major| major.parse::<u64>().ok());
+
+    json!({ "major_version": major, "version": version })
+}
+
+fn postgres_version_compat(source: &Value, dest: &Value) -> Option<String> {
+    let major = |config: &Value| config.get("major_version")?.as_u64();
+
+    let (source_major, dest_major) = (major(source)?, major(dest)?);
+    (dest_major < source_major).then(|| {
+        format!(
+            "Destination runs Postgres {} but source runs Postgres {}; upgrade the destination before migrating",
+            dest_major, source_major
+        )
+    })
+}
+
+const ADDON_TYPES: [&str; 4] = ["compute_instance", "pitr", "custom_domain", "ipv4"];
+
+// Compute sizes from smallest to largest
+const COMPUTE_SIZES: &[&str] = &[
+    "ci_nano",
+    "ci_micro",
+    "ci_small",
+    "ci_medium",
+    "ci_large",
+    "ci_xlarge",
+    "ci_2xlarge",
+    "ci_4xlarge",
+    "ci_8xlarge",
+    "ci_12xlarge",
+    "ci_16xlarge",
+];
+
+// The selected variant of each add-on, or null when it is not enabled
+fn addon_settings(addons: Value) -> Value {
+    let selected = addons.get("selected_addons").and_then(Value::as_array);
+
+    let mut settings = Map::new();
+    for addon_type in ADDON_TYPES {
+        let variant = selected
+            .into_iter()
+            .flatten()
+            .find(|addon| addon.get("type").and_then(Value::as_str) == Some(addon_type))
+            .and_then(|addon| addon.pointer("/variant/id"))
+            .cloned()
+            .unwrap_or(Value::Null);
+        settings.insert(addon_type.to_string(), variant);
+    }
+
+    Value::Object(settings)
+}
+
+fn compute_capacity(source: &Value, dest: &Value) -> Option<String> {
+    let size = |addons: &Value| {
+        let id = addons.get("compute_instance")?.as_str()?;
+        COMPUTE_SIZES.iter().position(|size| *size == id).map(|rank| (rank, id.to_string()))
+    };
+
+    let (source_rank, source_size) = size(source)?;
+    let (dest_rank, dest_size) = size(dest)?;
+    (dest_rank < source_rank).then(|| {
+        format!(
+            "Destination compute {} is smaller than source compute {}",
+            dest_size, source_size
+        )
+    })
+}
+
+// Compares only the applied allow-lists, one item per CIDR so each shows up on its own
+fn network_restrictions(restrictions: Value) -> Value {
+    let mut config = match restrictions.get("config") {
+        Some(Value::Object(config)) => config.clone(),
+        _ => Map::new(),
+    };
+
+    for field in ["dbAllowedCidrs", "dbAllowedCidrsV6"] {
+        if let Some(cidrs) = config.get(field).cloned() {
+            config.insert(field.to_string(), keyed_strings(cidrs, "cidr"));
+        }
+    }
+    Value::Object(config)
+}
+
+fn network_bans(bans: Value) -> Value {
+    let addresses = bans
+        .get("banned_ipv4_addresses")
+        .cloned()
+        .unwrap_or(Value::Array(Vec::new()));
+    json!({ "banned_ipv4_addresses": keyed_strings(addresses, "address") })
+}
+
+// Compares the enforcement currently in effect rather than whether the last change applied
+fn ssl_enforcement(enforcement: Value) -> Value {
+    enforcement
+        .get("currentConfig")
+        .cloned()
+        .unwrap_or(Value::Object(Map::new()))
+}
+
+// Turns ["a", "b"] into [{field: "a"}, {field: "b"}] so the diff matches items by value
+fn keyed_strings(values: Value, field: &str) -> Value {
+    match values {
+        Value::Array(values) => Value::Array(
+            values
+                .into_iter()
+                .map(|value| match value {
+                    Value::String(_) => json!({ field: value }),
+                    other => other,
+                })
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::migrate::preview_handler::{json_diff, DiffOptions};
+    use crate::models::migrate::{ChangeType, RiskLevel};
+    use crate::supabase_client::mock::MockApi;
+
+    #[tokio::test]
+    async fn test_risk_rules_classify_auth_changes() {
+        let source = json!({"site_url": "https://a.dev", "mailer_autoconfirm": true, "rate_limit_otp": 30, "jwt_exp": 3600});
+        let dest = json!({"site_url": "https://b.dev", "mailer_autoconfirm": false, "rate_limit_otp": 60, "jwt_exp": 7200});
+
+        let config = json_diff("Auth".to_string(), source, dest, &DiffOptions::for_service("Auth"))
+            .await
+            .unwrap()
+            .unwrap();
+        let risk = |key: &str| config.diffs.iter().find(|d| d.key == key).unwrap().risk;
+
+        assert_eq!(risk("site_url"), RiskLevel::High);
+        assert_eq!(risk("mailer_autoconfirm"), RiskLevel::High);
+        assert_eq!(risk("rate_limit_otp"), RiskLevel::Low);
+        assert_eq!(risk("jwt_exp"), RiskLevel::Medium);
+
+        // Re-enabling confirmations is not the risky direction
+        let config = json_diff(
+            "Auth".to_string(),
+            json!({"mailer_autoconfirm": false}),
+            json!({"mailer_autoconfirm": true}),
+            &DiffOptions::for_service("Auth"),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(config.diffs[0].risk, RiskLevel::Medium);
+    }
+
+    #[tokio::test]
+    async fn test_defaults_source_uses_documented_baseline() {
+        let api = MockApi::default();
+
+        let auth = fetch_service_config(&api, DEFAULTS_SOURCE, find_service("Auth").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(auth["jwt_exp"], 3600);
+        // Derived settings are still split off into their own config types
+        assert!(auth.get("mfa_max_enrolled_factors").is_none());
+
+        let mfa = fetch_service_config(&api, DEFAULTS_SOURCE, find_service("Mfa").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(mfa["mfa_max_enrolled_factors"], 10);
+
+        let storage = fetch_service_config(&api, DEFAULTS_SOURCE, find_service("Storage").unwrap()).await;
+        assert!(matches!(storage, Err(AppError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_service_config_applies_transform() {
+        let api = MockApi::default().with_response(
+            Method::GET,
+            "/projects/src/storage/buckets",
+            json!([{"id": "avatars", "name": "avatars", "public": true, "owner": "someone"}]),
+        );
+
+        let config = fetch_service_config(&api, "src", find_service("Buckets").unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(config, json!([{"name": "avatars", "public": true}]));
+    }
+
+    #[tokio::test]
+    async fn test_typed_models_drop_unknown_fields() {
+        let api = MockApi::default()
+            .with_response(
+                Method::GET,
+                "/projects/src/postgrest",
+                json!({"db_schema": "public", "max_rows": 1000, "jwt_secret": "secret"}),
+            )
+            .with_response(
+                Method::GET,
+                "/projects/src/secrets?page=1&limit=100",
+                json!([{"name": "API_KEY", "value": "abc", "updated_at": "2026-01-01T00:00:00Z"}]),
+            );
+
+        let postgrest = fetch_service_config(&api, "src", find_service("Postgrest").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            postgrest,
+            json!({"db_schema": "public", "db_extra_search_path": null, "max_rows": 1000, "db_pool": null})
+        );
+
+        let secrets = fetch_service_config(&api, "src", find_service("Secrets").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(secrets, json!([{"name": "API_KEY", "value": "abc"}]));
+    }
+
+    #[tokio::test]
+    async fn test_network_restrictions_diff_per_cidr() {
+        let source = network_restrictions(json!({
+            "entitlement": "allowed",
+            "config": {"dbAllowedCidrs": ["10.0.0.0/8", "192.168.0.0/16"]},
+            "status": "applied"
+        }));
+        let dest = network_restrictions(json!({
+            "entitlement": "allowed",
+            "config": {"dbAllowedCidrs": ["10.0.0.0/8"]},
+            "status": "stored"
+        }));
+
+        let config = json_diff("NetworkRestrictions".to_string(), source, dest, &DiffOptions::for_service("NetworkRestrictions"))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(config.diffs.len(), 1);
+        assert_eq!(config.diffs[0].key, "dbAllowedCidrs.id:192.168.0.0/16");
+        assert_eq!(config.diffs[0].dest_value, None);
+    }
+
+    #[test]
+    fn test_sms_settings_redacts_credentials() {
+        let settings = sms_settings(json!({
+            "site_url": "https://a.example.com",
+            "sms_provider": "twilio",
+            "sms_otp_exp": 60,
+            "sms_twilio_account_sid": "AC123",
+            "sms_twilio_auth_token": "secret",
+            "sms_messagebird_access_key": null
+        }));
+
+        assert_eq!(
+            settings,
+            json!({
+                "sms_provider": "twilio",
+                "sms_otp_exp": 60,
+                "sms_twilio_account_sid": "AC123",
+                "sms_twilio_auth_token": "***",
+                "sms_messagebird_access_key": null
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_oauth_provider_secrets_masked_but_presence_diffed() {
+        let source = json!({
+            "external_email_enabled": true,
+            "external_google_enabled": true,
+            "external_google_client_id": "client",
+            "external_google_secret": "source-secret",
+            "external_slack_oidc_secret": "slack-secret"
+        });
+        let dest = json!({
+            "external_email_enabled": true,
+            "external_google_enabled": true,
+            "external_google_client_id": "client",
+            "external_google_secret": "dest-secret",
+            "external_slack_oidc_secret": ""
+        });
+
+        assert_eq!(auth_settings(source.clone()), json!({"external_email_enabled": true}));
+
+        let config = json_diff(
+            "OAuthProviders".to_string(),
+            oauth_providers(source),
+            oauth_providers(dest),
+            &DiffOptions::for_service("OAuthProviders"),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(config.diffs.len(), 1);
+        assert_eq!(config.diffs[0].key, "slack_oidc.secret");
+        assert_eq!(config.diffs[0].source_value.as_deref(), Some("***"));
+        // The unset secret is still a field on the destination, just a null one
+        assert_eq!(config.diffs[0].change_type, ChangeType::Modified);
+        assert_eq!(config.diffs[0].dest_value.as_deref(), Some("null"));
+    }
+
+    #[test]
+    fn test_auth_hooks_split_from_auth() {
+        let auth = json!({
+            "site_url": "https://a.example.com",
+            "hook_custom_access_token_enabled": true,
+            "hook_custom_access_token_uri": "pg-functions://postgres/public/custom_claims",
+            "hook_send_sms_secrets": "v1,whsec_abc"
+        });
+
+        assert_eq!(
+            auth_hooks(auth.clone()),
+            json!({
+                "hook_custom_access_token_enabled": true,
+                "hook_custom_access_token_uri": "pg-functions://postgres/public/custom_claims"
+            })
+        );
+        assert_eq!(
+            auth_settings(auth),
+            json!({"site_url": "https://a.example.com", "hook_send_sms_secrets": "v1,whsec_abc"})
+        );
+    }
+
+    #[test]
+    fn test_mfa_and_captcha_split_from_auth() {
+        let auth = json!({
+            "site_url": "https://a.example.com",
+            "mfa_totp_enroll_enabled": true,
+            "mfa_phone_otp_length": 6,
+            "security_captcha_enabled": true,
+            "security_captcha_provider": "turnstile"
+        });
+
+        assert_eq!(
+            mfa_settings(auth.clone()),
+            json!({"mfa_totp_enroll_enabled": true, "mfa_phone_otp_length": 6})
+        );
+        assert_eq!(
+            captcha_settings(auth.clone()),
+            json!({"security_captcha_enabled": true, "security_captcha_provider": "turnstile"})
+        );
+        assert_eq!(auth_settings(auth), json!({"site_url": "https://a.example.com"}));
+    }
+
+    #[test]
+    fn test_smtp_settings_masks_password() {
+        let auth = json!({
+            "site_url": "https://a.example.com",
+            "smtp_host": "smtp.example.com",
+            "smtp_port": "587",
+            "smtp_user": "mailer",
+            "smtp_pass": "hunter2",
+            "smtp_sender_name": "Example"
+        });
+
+        assert_eq!(
+            smtp_settings(auth),
+            json!({
+                "smtp_host": "smtp.example.com",
+                "smtp_port": "587",
+                "smtp_user": "mailer",
+                "smtp_pass": "***",
+                "smtp_sender_name": "Example"
+            })
+        );
+    }
+
+    #[test]
+    fn test_pooler_settings_use_primary() {
+        let poolers = json!([
+            {"database_type": "READ_REPLICA", "pool_mode": "session", "default_pool_size": 10},
+            {
+                "database_type": "PRIMARY",
+                "db_host": "db.example.supabase.co",
+                "pool_mode": "transaction",
+                "default_pool_size": 15,
+                "max_client_conn": 200
+            }
+        ]);
+
+        assert_eq!(
+            pooler_settings(poolers),
+            json!({"pool_mode": "transaction", "default_pool_size": 15, "max_client_conn": 200})
+        );
+        assert_eq!(
+            pooler_patch(json!({"default_pool_size": 15, "max_client_conn": 200})),
+            json!({"default_pool_size": 15})
+        );
+    }
+
+    #[test]
+    fn test_backup_settings_ignore_individual_backups() {
+        let backups = json!({
+            "region": "us-east-1",
+            "pitr_enabled": true,
+            "walg_enabled": true,
+            "backups": [
+                {"status": "COMPLETED", "inserted_at": "2026-01-01T00:00:00Z"},
+                {"status": "COMPLETED", "inserted_at": "2026-01-02T00:00:00Z"}
+            ]
+        });
+
+        assert_eq!(
+            backup_settings(backups),
+            json!({"pitr_enabled": true, "walg_enabled": true, "retained_backups": 2})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_replicas_missing_on_dest() {
+        let source = read_replicas(json!([
+            {"identifier": "src", "type": "PRIMARY", "region": "us-east-1", "status": "ACTIVE_HEALTHY"},
+            {"identifier": "src-rr-1", "type": "READ_REPLICA", "region": "eu-west-1", "status": "ACTIVE_HEALTHY"},
+            {"identifier": "src-rr-2", "type": "READ_REPLICA", "region": "eu-west-1", "status": "ACTIVE_HEALTHY"}
+        ]));
+        let dest = read_replicas(json!([
+            {"identifier": "dst", "type": "PRIMARY", "region": "us-east-1", "status": "ACTIVE_HEALTHY"},
+            {"identifier": "dst-rr-1", "type": "READ_REPLICA", "region": "eu-west-1", "status": "ACTIVE_HEALTHY"}
+        ]));
+
+        let config = json_diff("ReadReplicas".to_string(), source, dest, &DiffOptions::for_service("ReadReplicas")).await.unwrap().unwrap();
+
+        assert_eq!(config.diffs.len(), 1);
+        assert_eq!(config.diffs[0].key, "id:eu-west-1#2");
+        assert_eq!(config.diffs[0].dest_value, None);
+    }
+
+    #[test]
+    fn test_addons_flag_smaller_destination_compute() {
+        let addons = |compute: &str| {
+            addon_settings(json!({
+                "selected_addons": [
+                    {"type": "compute_instance", "variant": {"id": compute, "name": "Compute"}},
+                    {"type": "ipv4", "variant": {"id": "ipv4_default"}}
+                ],
+                "available_addons": []
+            }))
+        };
+
+        assert_eq!(
+            addons("ci_large"),
+            json!({
+                "compute_instance": "ci_large",
+                "pitr": null,
+                "custom_domain": null,
+                "ipv4": "ipv4_default"
+            })
+        );
+        assert_eq!(
+            compute_capacity(&addons("ci_large"), &addons("ci_small")).as_deref(),
+            Some("Destination compute ci_small is smaller than source compute ci_large")
+        );
+        assert_eq!(compute_capacity(&addons("ci_small"), &addons("ci_large")), None);
+    }
+
+    #[test]
+    fn test_postgres_version_warns_when_dest_is_older() {
+        let project = |version: &str| {
+            postgres_version(json!({
+                "id": "abc",
+                "database": {"host": "db.abc.supabase.co", "version": version}
+            }))
+        };
+
+        assert_eq!(
+            project("15.1.0.117"),
+            json!({"major_version": 15, "version": "15.1.0.117"})
+        );
+        assert!(postgres_version_compat(&project("15.1.0.117"), &project("14.1.0.89")).is_some());
+        assert!(postgres_version_compat(&project("15.1.0.117"), &project("15.6.1.100")).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_webhooks_matched_by_schema_table_and_name() {
+        let webhook = |table: &str, definition: &str| json!({"name": "notify", "schema": "public", "table": table, "definition": definition});
+        let source = json!([webhook("orders", "a"), webhook("users", "b")]);
+        let dest = json!([webhook("users", "b")]);
+
+        let config = json_diff("Webhooks".to_string(), source, dest, &DiffOptions::for_service("Webhooks"))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(config.diffs.len(), 1);
+        assert_eq!(config.diffs[0].key, "id:public/orders/notify");
+        assert_eq!(config.diffs[0].change_type, ChangeType::Added);
+    }
+
+    #[tokio::test]
+    async fn test_sso_providers_matched_by_domain() {
+        let provider = |id: &str, domain: &str, metadata_url: &str| {
+            json!({
+                "id": id,
+                "saml": {"entity_id": "https://idp.example.com", "metadata_url": metadata_url},
+                "domains": [{"id": id, "domain": domain}]
+            })
+        };
+        let source = sso_providers(json!({"items": [
+            provider("a1", "example.com", "https://idp.example.com/v2"),
+            provider("a2", "corp.example.com", "https://idp.example.com/v1")
+        ]}));
+        let dest = sso_providers(json!({"items": [
+            provider("b1", "example.com", "https://idp.example.com/v1")
+        ]}));
+
+        let config = json_diff("Sso".to_string(), source, dest, &DiffOptions::for_service("Sso")).await.unwrap().unwrap();
+        let mut keys: Vec<&str> = config.diffs.iter().map(|d| d.key.as_str()).collect();
+        keys.sort();
+
+        assert_eq!(keys, vec!["id:corp.example.com", "id:example.com.metadata_url"]);
+    }
+
+    #[tokio::test]
+    async fn test_email_templates_diff_and_patch_fields() {
+        let source = json!({
+            "site_url": "https://a.example.com",
+            "mailer_subjects_confirmation": "Confirm your signup",
+            "mailer_templates_confirmation_content": "<p>Welcome</p>",
+            "mailer_subjects_magic_link": "Your link"
+        });
+        let dest = json!({
+            "site_url": "https://b.example.com",
+            "mailer_subjects_confirmation": "Confirm",
+            "mailer_templates_confirmation_content": "<p>Welcome</p>",
+            "mailer_subjects_magic_link": "Your link"
+        });
+
+        assert_eq!(auth_settings(source.clone()), json!({"site_url": "https://a.example.com"}));
+
+        let config = json_diff(
+            "EmailTemplates".to_string(),
+            email_templates(source),
+            email_templates(dest),
+            &DiffOptions::for_service("EmailTemplates"),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(config.diffs.len(), 1);
+        assert_eq!(config.diffs[0].key, "templates.confirmation.subject");
+
+        let patch = json!({"templates": {"confirmation": {"subject": "Confirm your signup"}}});
+        assert_eq!(
+            email_template_fields(patch),
+            json!({"mailer_subjects_confirmation": "Confirm your signup"})
+        );
+    }
+}
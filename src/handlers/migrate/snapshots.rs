@@ -0,0 +1,322 @@
+use crate::error::AppError;
+use crate::handlers::auth::AuthenticatedUser;
+use crate::handlers::migrate::preview_handler::{json_diff, redact_diffs, truncate_diffs, DiffOptions};
+use crate::handlers::migrate::services::{fetch_service_config, find_service, ServiceSpec, DEFAULTS_SOURCE, SERVICES};
+use crate::models::migrate::{
+    ConfigSnapshot, CreateSnapshotRequest, PreviewSummary, ProjectConfig, SnapshotDiffResponse, SnapshotSummary,
+};
+use crate::models::AppState;
+use crate::storage::Records;
+use crate::supabase_client::redact::mask_sensitive_fields;
+use crate::supabase_client::ManagementApi;
+
+use axum::{
+    extract::{rejection::JsonRejection, Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use futures::future::try_join_all;
+use reqwest::Method;
+use serde::Deserialize;
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+// Named captures of every supported config of a project. With storage configured every
+// snapshot is saved there as well, so they outlive a restart. Sensitive values are masked
+// before a snapshot is kept, so no secret is ever stored in plain text; live configs are
+// masked the same way before they are compared with one.
+#[derive(Clone, Default)]
+pub struct ConfigSnapshots {
+    snapshots: Arc<RwLock<HashMap<String, ConfigSnapshot>>>,
+    records: Option<Records>,
+}
+
+impl ConfigSnapshots {
+    pub async fn load(records: Option<Records>) -> Result<Self, String> {
+        let Some(records) = records else {
+            return Ok(Self::default());
+        };
+
+        let mut snapshots = HashMap::new();
+        for mut snapshot in records.load::<ConfigSnapshot>().await? {
+            // Snapshots saved before values were masked are rewritten masked
+            if mask_configs(&mut snapshot.configs) {
+                records.save(&snapshot.id, &snapshot);
+            }
+            snapshots.insert(snapshot.id.clone(), snapshot);
+        }
+        Ok(Self {
+            snapshots: Arc::new(RwLock::new(snapshots)),
+            records: Some(records),
+        })
+    }
+
+    pub fn insert(&self, mut snapshot: ConfigSnapshot) {
+        mask_configs(&mut snapshot.configs);
+        if let Some(records) = &self.records {
+            records.save(&snapshot.id, &snapshot);
+        }
+        self.snapshots
+            .write()
+            .expect("snapshot store lock poisoned")
+            .insert(snapshot.id.clone(), snapshot);
+    }
+
+    pub fn get(&self, snapshot_id: &str) -> Result<ConfigSnapshot, AppError> {
+        self.snapshots
+            .read()
+            .expect("snapshot store lock poisoned")
+            .get(snapshot_id)
+            .cloned()
+            .ok_or_else(|| AppError::NotFound(format!("No snapshot found with id {}", snapshot_id)))
+    }
+
+    // A project's snapshots, newest first
+    pub fn list(&self, project_id: &str) -> Vec<SnapshotSummary> {
+        let mut summaries: Vec<SnapshotSummary> = self
+            .snapshots
+            .read()
+            .expect("snapshot store lock poisoned")
+            .values()
+            .filter(|snapshot| snapshot.project_id == project_id)
+            .map(SnapshotSummary::of)
+            .collect();
+        summaries.sort_by_key(|summary| Reverse(summary.created_at));
+        summaries
+    }
+}
+
+// Masks the sensitive values of every config; true when anything was left to mask
+fn mask_configs(configs: &mut BTreeMap<String, Value>) -> bool {
+    let mut masked = false;
+    for config in configs.values_mut() {
+        let before = config.clone();
+        mask_sensitive_fields(config);
+        masked |= *config != before;
+    }
+    masked
+}
+
+#[tracing::instrument(skip_all, fields(project_id = %project_id))]
+pub async fn create_snapshot_handler(
+    State(app_state): State<AppState>,
+    Path(project_id): Path<String>,
+    user: AuthenticatedUser,
+    body: Result<Json<CreateSnapshotRequest>, JsonRejection>,
+) -> Result<(StatusCode, Json<SnapshotSummary>), AppError> {
+    let Json(request) = body?;
+    if request.name.trim().is_empty() {
+        return Err(AppError::BadRequest("Snapshot name must not be empty".to_string()));
+    }
+    if project_id == DEFAULTS_SOURCE {
+        return Err(AppError::BadRequest(format!("{} cannot be snapshotted", DEFAULTS_SOURCE)));
+    }
+
+    let api = app_state.management_client(user.access_token);
+    let configs = fetch_configs(&api, &project_id, SERVICES.iter()).await?;
+
+    let snapshot = ConfigSnapshot {
+        id: Uuid::new_v4().to_string(),
+        project_id,
+        name: request.name.trim().to_string(),
+        created_at: OffsetDateTime::now_utc(),
+        configs,
+    };
+    let summary = SnapshotSummary::of(&snapshot);
+    app_state.config_snapshots.insert(snapshot);
+
+    Ok((StatusCode::CREATED, Json(summary)))
+}
+
+pub async fn list_snapshots_handler(
+    State(app_state): State<AppState>,
+    Path(project_id): Path<String>,
+    user: AuthenticatedUser,
+) -> Result<Json<Vec<SnapshotSummary>>, AppError> {
+    let api = app_state.management_client(user.access_token);
+    check_project_access(&api, &project_id).await?;
+
+    Ok(Json(app_state.config_snapshots.list(&project_id)))
+}
+
+pub async fn snapshot_diff_handler(
+    State(app_state): State<AppState>,
+    Path((from_id, to_id)): Path<(String, String)>,
+    user: AuthenticatedUser,
+) -> Result<Json<SnapshotDiffResponse>, AppError> {
+    let from = app_state.config_snapshots.get(&from_id)?;
+    let to = app_state.config_snapshots.get(&to_id)?;
+
+    let api = app_state.management_client(user.access_token);
+    check_project_access(&api, &from.project_id).await?;
+    if to.project_id != from.project_id {
+        check_project_access(&api, &to.project_id).await?;
+    }
+
+    let configs = diff_snapshot(&from.configs, &to.configs, app_state.config.diff_max_value_len).await?;
+    Ok(Json(SnapshotDiffResponse {
+        from: SnapshotSummary::of(&from),
+        to: Some(SnapshotSummary::of(&to)),
+        summary: PreviewSummary::of(&configs),
+        configs,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DriftQuery {
+    pub snapshot: String,
+}
+
+// What changed on the live project since one of its snapshots, for the services the snapshot has
+#[tracing::instrument(skip_all, fields(project_id = %project_id))]
+pub async fn drift_handler(
+    State(app_state): State<AppState>,
+    Path(project_id): Path<String>,
+    Query(params): Query<DriftQuery>,
+    user: AuthenticatedUser,
+) -> Result<Json<SnapshotDiffResponse>, AppError> {
+    let snapshot = app_state.config_snapshots.get(&params.snapshot)?;
+    if snapshot.project_id != project_id {
+        return Err(AppError::NotFound(format!(
+            "Snapshot {} is not a snapshot of project {}",
+            snapshot.id, project_id
+        )));
+    }
+
+    let api = app_state.management_client(user.access_token);
+    let specs = snapshot.configs.keys().filter_map(|service| find_service(service));
+    let live = fetch_masked_configs(&api, &project_id, specs).await?;
+
+    let configs = diff_snapshot(&snapshot.configs, &live, app_state.config.diff_max_value_len).await?;
+    Ok(Json(SnapshotDiffResponse {
+        from: SnapshotSummary::of(&snapshot),
+        to: None,
+        summary: PreviewSummary::of(&configs),
+        configs,
+    }))
+}
+
+// Stored snapshots are shared by every user, so reading one needs a token that can still read
+// its project. The Management API answers for projects the token cannot see with an error.
+async fn check_project_access(api: &impl ManagementApi, project_id: &str) -> Result<(), AppError> {
+    api.request(Method::GET, &format!("/projects/{}", project_id), None).await?;
+    Ok(())
+}
+
+// The project's configs for the given services, fetched at once and keyed by service
+pub async fn fetch_configs(
+    api: &impl ManagementApi,
+    project_id: &str,
+    specs: impl Iterator<Item = &'static ServiceSpec>,
+) -> Result<BTreeMap<String, Value>, AppError> {
+    let configs = try_join_all(specs.map(|spec| async move {
+        let config = fetch_service_config(api, project_id, spec).await?;
+        Ok::<_, AppError>((spec.name.to_string(), config))
+    }))
+    .await?;
+    Ok(configs.into_iter().collect())
+}
+
+// The live configs masked like a stored snapshot's, so sensitive values only differ where
+// one side has none
+pub async fn fetch_masked_configs(
+    api: &impl ManagementApi,
+    project_id: &str,
+    specs: impl Iterator<Item = &'static ServiceSpec>,
+) -> Result<BTreeMap<String, Value>, AppError> {
+    let mut configs = fetch_configs(api, project_id, specs).await?;
+    configs.values_mut().for_each(mask_sensitive_fields);
+    Ok(configs)
+}
+
+// Diffs the services both sides have, masked and truncated for display. The later side is
+// the source, so entries read as what changed since `from`.
+pub async fn diff_snapshot(
+    from: &BTreeMap<String, Value>,
+    to: &BTreeMap<String, Value>,
+    max_value_len: usize,
+) -> Result<Vec<ProjectConfig>, AppError> {
+    let mut configs = Vec::new();
+    for (service, from_config) in from {
+        let Some(to_config) = to.get(service) else {
+            continue;
+        };
+
+        let options = DiffOptions::for_service(service);
+        if let Some(mut config) = json_diff(service.clone(), to_config.clone(), from_config.clone(), &options).await? {
+            redact_diffs(&mut config);
+            truncate_diffs(&mut config, max_value_len);
+            configs.push(config);
+        }
+    }
+    Ok(configs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{Collection, FileStorage};
+    use serde_json::json;
+    use time::Duration;
+
+    fn snapshot(id: &str, project_id: &str, age_minutes: i64) -> ConfigSnapshot {
+        ConfigSnapshot {
+            id: id.to_string(),
+            project_id: project_id.to_string(),
+            name: format!("before {}", id),
+            created_at: OffsetDateTime::now_utc() - Duration::minutes(age_minutes),
+            configs: [("Auth".to_string(), json!({"site_url": "https://a.dev"}))].into_iter().collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_snapshots_listed_per_project_and_reloaded() {
+        let dir = std::env::temp_dir().join(format!("supabasemm-snapshots-{}", Uuid::new_v4()));
+        let storage = Arc::new(FileStorage {
+            job_state_dir: None,
+            snapshot_dir: Some(dir.clone()),
+            audit_log_path: None,
+            profile_dir: None,
+        });
+        let records = Records::new(storage.clone(), Collection::Snapshots);
+        let snapshots = ConfigSnapshots::load(Some(records.clone())).await.unwrap();
+        snapshots.insert(snapshot("old", "proj", 60));
+        snapshots.insert(snapshot("new", "proj", 1));
+        let mut secret = snapshot("other", "elsewhere", 1);
+        secret.configs.insert("Smtp".to_string(), json!({"smtp_host": "mail", "smtp_pass": "hunter2"}));
+        snapshots.insert(secret);
+        records.flush().await;
+
+        // Secrets never reach storage
+        let stored = std::fs::read_to_string(dir.join("other.json")).unwrap();
+        assert!(!stored.contains("hunter2"));
+
+        let reloaded = ConfigSnapshots::load(Some(Records::new(storage, Collection::Snapshots))).await.unwrap();
+        let ids: Vec<String> = reloaded.list("proj").into_iter().map(|summary| summary.id).collect();
+        assert_eq!(ids, vec!["new", "old"]);
+        assert_eq!(reloaded.list("proj")[0].services, vec!["Auth"]);
+        assert!(matches!(reloaded.get("missing"), Err(AppError::NotFound(_))));
+        assert_eq!(reloaded.get("other").unwrap().configs["Smtp"], json!({"smtp_host": "mail", "smtp_pass": "***"}));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_diff_reads_as_changes_since_from() {
+        let from = snapshot("old", "proj", 60).configs;
+        let mut to = from.clone();
+        to.insert("Auth".to_string(), json!({"site_url": "https://b.dev", "disable_signup": true}));
+
+        let configs = diff_snapshot(&from, &to, 0).await.unwrap();
+        let diffs = &configs[0].diffs;
+        assert_eq!(diffs.len(), 2);
+        let site_url = diffs.iter().find(|diff| diff.key == "site_url").unwrap();
+        assert_eq!(site_url.source_value.as_deref(), Some("https://b.dev"));
+        assert_eq!(site_url.dest_value.as_deref(), Some("https://a.dev"));
+        assert!(diff_snapshot(&from, &from, 0).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,86 @@
+use crate::handlers::migrate::preview_handler::{diff_values, DiffOptions};
+use crate::models::migrate::DiffEntry;
+
+use serde_json::Value;
+
+// How a config type is compared. A service picks its strategy in its spec, so a new config
+// type can bring its own comparer without touching calculate_diff.
+pub trait DiffStrategy: Sync {
+    // Reshapes a config after ignored fields are dropped and values normalized, e.g. to leave
+    // out items that are never migrated
+    fn prepare(&self, value: Value) -> Value {
+        value
+    }
+
+    fn diff(&self, source: &Value, dest: &Value, options: &DiffOptions) -> Vec<DiffEntry>;
+}
+
+// Objects field by field, arrays by the identity the options give for their path
+pub struct Recursive;
+
+impl DiffStrategy for Recursive {
+    fn diff(&self, source: &Value, dest: &Value, options: &DiffOptions) -> Vec<DiffEntry> {
+        let mut diffs = Vec::new();
+        diff_values("", source, dest, options, &mut diffs);
+        diffs
+    }
+}
+
+// Secrets matched by name, without the SUPABASE_ ones every project manages itself
+pub struct SecretsByName;
+
+impl DiffStrategy for SecretsByName {
+    fn prepare(&self, value: Value) -> Value {
+        match value {
+            Value::Array(items) => {
+                Value::Array(items.into_iter().filter(|item| !is_supabase_secret(item)).collect())
+            }
+            other => other,
+        }
+    }
+
+    fn diff(&self, source: &Value, dest: &Value, options: &DiffOptions) -> Vec<DiffEntry> {
+        let options = DiffOptions { id_key: "name", ..options.clone() };
+        Recursive.diff(source, dest, &options)
+    }
+}
+
+// Edge functions matched by slug, since their IDs differ per project
+pub struct FunctionsBySlug;
+
+impl DiffStrategy for FunctionsBySlug {
+    fn diff(&self, source: &Value, dest: &Value, options: &DiffOptions) -> Vec<DiffEntry> {
+        let options = DiffOptions { id_key: "slug", ..options.clone() };
+        Recursive.diff(source, dest, &options)
+    }
+}
+
+fn is_supabase_secret(value: &Value) -> bool {
+    matches!(value.get("name"), Some(Value::String(name)) if name.starts_with("SUPABASE_"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_services_compare_with_their_strategy() {
+        let source = json!([
+            {"slug": "hello", "name": "Hello", "version": 2},
+            {"slug": "bye", "name": "Bye", "version": 1}
+        ]);
+        let dest = json!([{"slug": "hello", "name": "Hello", "version": 1}]);
+
+        let mut keys: Vec<String> = FunctionsBySlug
+            .diff(&source, &dest, &DiffOptions::default())
+            .into_iter()
+            .map(|diff| diff.key)
+            .collect();
+        keys.sort();
+        assert_eq!(keys, vec!["id:bye", "id:hello.version"]);
+
+        let secrets = json!([{"name": "SUPABASE_URL", "value": "x"}, {"name": "API_KEY", "value": "y"}]);
+        assert_eq!(SecretsByName.prepare(secrets), json!([{"name": "API_KEY", "value": "y"}]));
+    }
+}
@@ -0,0 +1,75 @@
+use crate::models::migrate::{DiffEntry, DiffNode};
+
+use std::collections::HashMap;
+
+// Nests a service's diff by the segments of its keys. Flat fields that share a prefix, like
+// Auth's rate_limit_* or mailer_* settings, get a section of their own.
+pub fn diff_tree(entries: &[DiffEntry]) -> DiffNode {
+    let mut root = DiffNode::default();
+
+    for entry in entries {
+        let mut node = &mut root;
+        let mut segments: Vec<&str> = entry.key.split('.').collect();
+        segments.pop();
+        for segment in segments {
+            node = node.children.entry(segment.to_string()).or_default();
+        }
+        node.entries.push(entry.clone());
+    }
+
+    group_by_prefix(&mut root);
+    root
+}
+
+fn group_by_prefix(node: &mut DiffNode) {
+    node.children.values_mut().for_each(group_by_prefix);
+
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for entry in &node.entries {
+        if let Some(prefix) = field_prefix(entry) {
+            *counts.entry(prefix.to_string()).or_default() += 1;
+        }
+    }
+
+    let entries = std::mem::take(&mut node.entries);
+    for entry in entries {
+        match field_prefix(&entry).filter(|prefix| counts[*prefix] > 1) {
+            Some(prefix) => node.children.entry(prefix.to_string()).or_default().entries.push(entry),
+            None => node.entries.push(entry),
+        }
+    }
+}
+
+fn field_prefix(entry: &DiffEntry) -> Option<&str> {
+    let field = entry.key.rsplit('.').next()?;
+    field.split_once('_').map(|(prefix, _)| prefix).filter(|prefix| !prefix.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::migrate::{ChangeType, RiskLevel};
+
+    #[test]
+    fn test_tree_nests_by_key_and_prefix() {
+        let entry = |key: &str| DiffEntry {
+            key: key.to_string(),
+            change_type: ChangeType::Modified,
+            source_value: Some("1".to_string()),
+            dest_value: Some("2".to_string()),
+            risk: RiskLevel::Medium,
+            truncated: false,
+        };
+        let tree = diff_tree(&[
+            entry("site_url"),
+            entry("rate_limit_otp"),
+            entry("rate_limit_verify"),
+            entry("smtp.port"),
+        ]);
+
+        assert_eq!(tree.entries.len(), 1);
+        assert_eq!(tree.entries[0].key, "site_url");
+        assert_eq!(tree.children["rate"].entries.len(), 2);
+        assert_eq!(tree.children["smtp"].entries[0].key, "smtp.port");
+    }
+}
@@ -0,0 +1,141 @@
+use crate::error::AppError;
+use crate::handlers::migrate::services::{find_item, selected_ids};
+use crate::supabase_client::ManagementApi;
+
+use serde_json::Value;
+
+// Trigger names are only unique per table
+pub const WEBHOOK_IDENTITY: &[&str] = &["schema", "table", "name"];
+
+// Database webhooks are triggers that call supabase_functions.http_request
+pub const WEBHOOKS_QUERY: &str = "\
+select t.tgname as name, n.nspname as schema, c.relname as table, \
+pg_get_triggerdef(t.oid) as definition \
+from pg_trigger t \
+join pg_class c on c.oid = t.tgrelid \
+join pg_namespace n on n.oid = c.relnamespace \
+join pg_proc p on p.oid = t.tgfoid \
+join pg_namespace pn on pn.oid = p.pronamespace \
+where not t.tgisinternal and pn.nspname = 'supabase_functions' and p.proname = 'http_request' \
+order by t.tgname";
+
+// Creates the selected webhooks that are missing on the destination and recreates the ones
+// whose definition differs. Webhooks that only exist on the destination are left alone.
+pub async fn apply_webhooks(
+    api: &impl ManagementApi,
+    dest_id: &str,
+    source: &Value,
+    dest: &Value,
+    keys: &[String],
+) -> Result<(), AppError> {
+    let mut statements = Vec::new();
+
+    for id in selected_ids(keys, &[source, dest], WEBHOOK_IDENTITY) {
+        let Some(webhook) = find_webhook(source, &id) else {
+            continue;
+        };
+        if let Some(existing) = find_webhook(dest, &id) {
+            statements.push(drop_statement(existing)?);
+        }
+        statements.push(create_statement(webhook)?);
+    }
+
+    run_statements(api, dest_id, statements).await
+}
+
+// Puts the selected webhooks back the way the snapshot recorded them, dropping any that did
+// not exist before the apply
+pub async fn restore_webhooks(
+    api: &impl ManagementApi,
+    dest_id: &str,
+    snapshot: &Value,
+    current: &Value,
+    keys: &[String],
+) -> Result<(), AppError> {
+    let mut statements = Vec::new();
+
+    for id in selected_ids(keys, &[snapshot, current], WEBHOOK_IDENTITY) {
+        if let Some(existing) = find_webhook(current, &id) {
+            statements.push(drop_statement(existing)?);
+        }
+        if let Some(previous) = find_webhook(snapshot, &id) {
+            statements.push(create_statement(previous)?);
+        }
+    }
+
+    run_statements(api, dest_id, statements).await
+}
+
+async fn run_statements(
+    api: &impl ManagementApi,
+    project_id: &str,
+    statements: Vec<String>,
+) -> Result<(), AppError> {
+    if statements.is_empty() {
+        return Ok(());
+    }
+
+    let query = format!("begin;\n{};\ncommit;", statements.join(";\n"));
+    api.run_query(project_id, &query).await?;
+    Ok(())
+}
+
+fn find_webhook<'a>(config: &'a Value, id: &str) -> Option<&'a Value> {
+    find_item(config, WEBHOOK_IDENTITY, id)
+}
+
+fn create_statement(webhook: &Value) -> Result<String, AppError> {
+    webhook
+        .get("definition")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| AppError::ApiError("Webhook is missing its trigger definition".to_string()))
+}
+
+fn drop_statement(webhook: &Value) -> Result<String, AppError> {
+    let field = |name: &str| {
+        webhook
+            .get(name)
+            .and_then(Value::as_str)
+            .ok_or_else(|| AppError::ApiError(format!("Webhook is missing its {}", name)))
+    };
+
+    Ok(format!(
+        "drop trigger if exists {} on {}.{}",
+        quote_ident(field("name")?),
+        quote_ident(field("schema")?),
+        quote_ident(field("table")?)
+    ))
+}
+
+fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_webhooks_selected_by_schema_table_and_name() {
+        let source = json!([
+            {"name": "notify.orders", "schema": "public", "table": "orders", "definition": "CREATE TRIGGER a"},
+            {"name": "audit", "schema": "public", "table": "users", "definition": "CREATE TRIGGER b"}
+        ]);
+        let dest = json!([{"name": "audit", "schema": "public", "table": "users", "definition": "CREATE TRIGGER c"}]);
+
+        let keys = vec![
+            "id:public/orders/notify.orders".to_string(),
+            "id:public/users/audit.definition".to_string(),
+        ];
+        assert_eq!(
+            selected_ids(&keys, &[&source, &dest], WEBHOOK_IDENTITY),
+            vec!["public/orders/notify.orders".to_string(), "public/users/audit".to_string()]
+        );
+        assert_eq!(
+            drop_statement(find_webhook(&dest, "public/users/audit").unwrap()).unwrap(),
+            r#"drop trigger if exists "audit" on "public"."users""#
+        );
+    }
+}
@@ -1,5 +1,25 @@
+pub mod accounts;
+pub mod admin;
+pub mod api_version;
+pub mod auth;
+pub mod csrf;
+pub mod health;
 pub mod oauth;
 pub mod migrate;
+pub mod panic;
+pub mod request_id;
 pub mod test_handler;
+pub mod timeout;
 
-pub use test_handler::test_handler;
\ No newline at end of file
+pub use admin::admin_sessions_handler;
+pub use accounts::{accounts_handler, me_handler, switch_account_handler};
+pub use api_version::{legacy_api_redirect, API_PREFIX, LEGACY_API_PATHS};
+pub use csrf::{csrf_token_handler, require_csrf_token};
+pub use health::{healthz_handler, readyz_handler};
+pub use oauth::{
+    api_callback_handler, api_login_handler, callback_handler, device_login_handler, device_poll_handler, login_handler,
+};
+pub use panic::{install_panic_hook, panic_response};
+pub use request_id::with_request_id;
+pub use test_handler::test_handler;
+pub use timeout::with_timeout;
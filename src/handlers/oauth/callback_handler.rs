@@ -1,36 +1,62 @@
+use crate::handlers::accounts::{account_for_token, missing_scopes, remember_account};
+use crate::error::AppError;
 use crate::models::AppState;
-use crate::models::oauth::{OAuthSessionData, CallbackParams};
+use crate::models::oauth::{OAuthSessionData, CallbackParams, StoredAccount};
 use axum::{
     extract::{Query, State},
-    response::{Html, IntoResponse},
+    response::Html,
 };
 use oauth2::PkceCodeVerifier;
 use serde::Deserialize;
+use time::{Duration, OffsetDateTime};
 use tower_sessions::Session;
 
 pub async fn callback_handler(
     Query(params): Query<CallbackParams>,
     State(app_state): State<AppState>,
     session: Session,
-) -> impl IntoResponse {
-    eprintln!(
-        "OAuth callback received. Code: {}, State: {}",
-        params.code, params.state
-    );
-
-    let oauth_data: Option<OAuthSessionData> = match session.get("oauth_data").await {
-        Ok(data) => data,
-        Err(_) => None,
-    };
-    eprintln!(
-        "Session ID: {:?} to get oauth retrieved from session: {:?}",
-        session.id(),
-        oauth_data
-    );
+) -> Result<Html<&'static str>, AppError> {
+    // Logins started by a headless client are finished here but picked up by its polling
+    if let Some(pkce_verifier_secret) = app_state.device_logins.verifier_for_state(&params.state) {
+        let account = exchange_code(&app_state, &params.code, pkce_verifier_secret).await?;
+        app_state.device_logins.complete(&params.state, account);
+        return Ok(Html("<h1>Login complete</h1><p>You can close this window and return to your terminal.</p>"));
+    }
+
+    complete_login(&app_state, &session, params).await?;
+
+    Ok(Html(
+        r#"
+        <!DOCTYPE html>
+        <html>
+        <head>
+            <meta http-equiv="refresh" content="0;url=/migrate">
+            <title>Redirecting...</title>
+        </head>
+        <body>
+            <p>Authentication successful! Redirecting to your projects...</p>
+            <p>If you are not redirected, <a href="/migrate">click here</a>.</p>
+        </body>
+        </html>
+        "#,
+    ))
+}
+
+// Checks the callback against the login started in this session, exchanges the code for a
+// token and stores it as the active account
+pub async fn complete_login(
+    app_state: &AppState,
+    session: &Session,
+    params: CallbackParams,
+) -> Result<StoredAccount, AppError> {
+    let oauth_data: Option<OAuthSessionData> =
+        session.get("oauth_data").await.unwrap_or_default();
+    // The verifier and state are secrets, so only whether they were found is logged
+    tracing::debug!(session_id = ?session.id(), found = oauth_data.is_some(), "OAuth callback received");
     let oauth_data = match oauth_data {
         Some(data) => data,
         None => {
-            eprintln!("No oauth_data found in session");
+            tracing::debug!("no oauth_data in session, looking for the separate keys");
             let pkce_verifier = session
                 .get::<String>("pkce_verifier_secret")
                 .await
@@ -43,76 +69,70 @@ pub async fn callback_handler(
                 .flatten();
 
             if pkce_verifier.is_some() && csrf_token.is_some() {
-                eprintln!("Found direct PKCE and CSRF keys instead");
+                tracing::debug!("found separate PKCE and CSRF keys");
                 OAuthSessionData {
                     pkce_verifier_secret: pkce_verifier,
                     csrf_token_secret: csrf_token,
                 }
             } else {
-                return Html(
-                    "<h1>Error</h1><p>No session data found. Please try logging in again.</p>\
-                     <p><a href=\"/connect-supabase/login\">Back to Login</a></p>"
-                        .to_string(),
-                );
+                return Err(AppError::BadRequest("No session data found".to_string()));
             }
         }
     };
 
     session.remove::<OAuthSessionData>("oauth_data").await.ok();
 
-    if oauth_data.pkce_verifier_secret.is_none() {
-        eprintln!("No PKCE verifier found in session");
-        return Html(
-            "<h1>Error</h1><p>No PKCE verifier found in session. Please try logging in again.</p>\
-             <p><a href=\"/connect-supabase/login\">Back to Login</a></p>"
-                .to_string(),
-        );
-    }
-    let pkce_verifier_secret = oauth_data.pkce_verifier_secret.unwrap();
-
-    if oauth_data.csrf_token_secret.is_none() {
-        eprintln!("No CSRF token found in session");
-        return Html(
-            "<h1>Error</h1><p>No CSRF token found in session. Please try logging in again.</p>\
-             <p><a href=\"/connect-supabase/login\">Back to Login</a></p>"
-                .to_string(),
-        );
-    }
-    let original_csrf_secret = oauth_data.csrf_token_secret.unwrap();
+    let Some(pkce_verifier_secret) = oauth_data.pkce_verifier_secret else {
+        tracing::warn!("no PKCE verifier in session");
+        return Err(AppError::BadRequest("No PKCE verifier found in session".to_string()));
+    };
+
+    let Some(original_csrf_secret) = oauth_data.csrf_token_secret else {
+        tracing::warn!("no CSRF state in session");
+        return Err(AppError::BadRequest("No CSRF token found in session".to_string()));
+    };
 
     if original_csrf_secret != params.state {
-        eprintln!(
-            "CSRF token mismatch. Expected: {}, Got: {}",
-            original_csrf_secret, params.state
-        );
-        return Html(
-            "<h1>Error</h1><p>CSRF token mismatch. Please try logging in again.</p>".to_string(),
-        );
+        tracing::warn!("OAuth state does not match the one stored in session");
+        return Err(AppError::BadRequest("CSRF token mismatch".to_string()));
     }
 
-    let pkce_verifier = PkceCodeVerifier::new(pkce_verifier_secret);
+    let account = exchange_code(app_state, &params.code, pkce_verifier_secret).await?;
+    remember_account(session, account.clone()).await.map_err(|e| {
+        tracing::error!(error = %e, "failed to store account in session");
+        e
+    })?;
 
-    let client = reqwest::Client::new();
+    Ok(account)
+}
+
+// Trades an authorization code for a token and describes the account it belongs to
+pub async fn exchange_code(
+    app_state: &AppState,
+    code: &str,
+    pkce_verifier_secret: String,
+) -> Result<StoredAccount, AppError> {
+    let pkce_verifier = PkceCodeVerifier::new(pkce_verifier_secret);
 
-    let params = [
+    let form = [
         ("client_id", app_state.config.client_id.as_str()),
         ("client_secret", app_state.config.client_secret.as_str()),
-        ("code", params.code.as_str()),
+        ("code", code),
         ("code_verifier", pkce_verifier.secret()),
         ("grant_type", "authorization_code"),
         ("redirect_uri", app_state.config.redirect_url.as_str()),
     ];
 
-    let response = match client.post("https://api.supabase.com/v1/oauth/token").form(&params).send().await {
-        Ok(res) => res,
-        Err(e) => {
-            eprintln!("Failed to exchange token: {:?}", e);
-            return Html(format!(
-                "<h1>Error</h1><p>Failed to exchange token: {}. Please try logging in again.</p>",
-                e
-            ));
-        }
-    };
+    let response = app_state
+        .http
+        .post("https://api.supabase.com/v1/oauth/token")
+        .form(&form)
+        .send()
+        .await
+        .map_err(|e| {
+            tracing::error!(error = %e, "failed to exchange authorization code");
+            AppError::ApiError(format!("Failed to exchange token: {}", e))
+        })?;
 
     if !response.status().is_success() {
         let status = response.status();
@@ -120,55 +140,44 @@ pub async fn callback_handler(
             .text()
             .await
             .unwrap_or_else(|_| "Could not read error body".to_string());
-        eprintln!("Failed to exchange token (HTTP {}): {}", status, error_text);
-        return Html(format!(
-            "<h1>Error</h1><p>Failed to exchange token: HTTP {} - {}. Please try logging in again.</p>",
+        tracing::error!(%status, body = %error_text, "token exchange rejected");
+        return Err(AppError::ApiError(format!(
+            "Failed to exchange token: HTTP {} - {}",
             status, error_text
-        ));
+        )));
     }
 
     #[derive(Deserialize)]
     struct TokenResponse {
         access_token: String,
         refresh_token: Option<String>,
+        expires_in: Option<i64>,
+        scope: Option<String>,
     }
 
-    let token_data = match response.json::<TokenResponse>().await {
-        Ok(data) => data,
-        Err(e) => {
-            eprintln!("Failed to parse token response: {:?}", e);
-            return Html(format!(
-                "<h1>Error</h1><p>Failed to parse token response: {}. Please try logging in again.</p>",
-                e
-            ));
-        }
-    };
-
-    session
-        .insert("supabase_access_token", token_data.access_token.clone())
-        .await
-        .expect("Failed to store access token in session");
-
-    if let Some(refresh_token) = token_data.refresh_token {
-        eprintln!(
-            "Refresh Token received (store securely if needed for long-term use): {}",
-            refresh_token
-        );
+    let token_data = response.json::<TokenResponse>().await.map_err(|e| {
+        tracing::error!(error = %e, "failed to parse token response");
+        AppError::ApiError(format!("Failed to parse token response: {}", e))
+    })?;
+
+    let mut account = account_for_token(app_state, token_data.access_token).await;
+    account.expires_at = token_data
+        .expires_in
+        .map(|seconds| OffsetDateTime::now_utc() + Duration::seconds(seconds));
+    account.scopes = token_data
+        .scope
+        .map(|scope| scope.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default();
+    let missing = missing_scopes(&app_state.config.oauth_scopes, &account.scopes);
+    if !missing.is_empty() {
+        tracing::warn!(account = %account.name, missing = %missing.join(", "), "token was not granted all requested scopes");
+    }
+    // Refresh tokens outlive the session's access token, so they are never stored unencrypted
+    if app_state.config.token_encryption_key.is_some() {
+        account.refresh_token = token_data.refresh_token;
+    } else if token_data.refresh_token.is_some() {
+        tracing::debug!("refresh token received but not stored without TOKEN_ENCRYPTION_KEY");
     }
 
-    Html(format!(
-        r#"
-        <!DOCTYPE html>
-        <html>
-        <head>
-            <meta http-equiv="refresh" content="0;url=/migrate">
-            <title>Redirecting...</title>
-        </head>
-        <body>
-            <p>Authentication successful! Redirecting to your projects...</p>
-            <p>If you are not redirected, <a href="/migrate">click here</a>.</p>
-        </body>
-        </html>
-        "#
-    ))
+    Ok(account)
 }
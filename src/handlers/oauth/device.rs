@@ -0,0 +1,158 @@
+use crate::error::AppError;
+use crate::handlers::oauth::login_handler::authorize_url;
+use crate::models::oauth::{DeviceLoginResponse, DeviceLoginStatus, StoredAccount};
+use crate::models::AppState;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json},
+};
+use oauth2::{CsrfToken, PkceCodeChallenge};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+const DEVICE_LOGIN_TTL: Duration = Duration::from_secs(10 * 60);
+const POLL_INTERVAL_SECS: u64 = 5;
+
+// Logins started without a browser session. The client gets a login URL to open anywhere
+// and a device code to poll with; the OAuth callback matches the login by its state.
+#[derive(Clone, Default)]
+pub struct DeviceLogins {
+    pending: Arc<RwLock<HashMap<String, DeviceLogin>>>,
+}
+
+struct DeviceLogin {
+    state: String,
+    pkce_verifier_secret: String,
+    started_at: Instant,
+    account: Option<StoredAccount>,
+}
+
+impl DeviceLogins {
+    fn start(&self, device_code: String, state: String, pkce_verifier_secret: String) {
+        let mut pending = self.pending.write().expect("device login lock poisoned");
+        pending.retain(|_, login| login.started_at.elapsed() < DEVICE_LOGIN_TTL);
+        pending.insert(
+            device_code,
+            DeviceLogin {
+                state,
+                pkce_verifier_secret,
+                started_at: Instant::now(),
+                account: None,
+            },
+        );
+    }
+
+    // PKCE verifier of an unfinished login with this OAuth state
+    pub fn verifier_for_state(&self, state: &str) -> Option<String> {
+        let pending = self.pending.read().expect("device login lock poisoned");
+        pending
+            .values()
+            .find(|login| login.state == state && login.account.is_none() && login.started_at.elapsed() < DEVICE_LOGIN_TTL)
+            .map(|login| login.pkce_verifier_secret.clone())
+    }
+
+    pub fn complete(&self, state: &str, account: StoredAccount) {
+        let mut pending = self.pending.write().expect("device login lock poisoned");
+        if let Some(login) = pending.values_mut().find(|login| login.state == state) {
+            login.account = Some(account);
+        }
+    }
+
+    // None for unknown or expired codes. A finished login is handed out once and forgotten.
+    fn poll(&self, device_code: &str) -> Option<Option<StoredAccount>> {
+        let mut pending = self.pending.write().expect("device login lock poisoned");
+        let login = pending
+            .get(device_code)
+            .filter(|login| login.started_at.elapsed() < DEVICE_LOGIN_TTL)?;
+
+        if login.account.is_none() {
+            return Some(None);
+        }
+        pending.remove(device_code).map(|login| login.account)
+    }
+}
+
+pub async fn device_login_handler(State(app_state): State<AppState>) -> Result<Json<DeviceLoginResponse>, AppError> {
+    if app_state.config.client_id.is_empty() {
+        return Err(AppError::NotFound("OAuth login is not configured".to_string()));
+    }
+
+    let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+    let csrf_token = CsrfToken::new_random();
+    let device_code = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+
+    let login_url = authorize_url(&app_state, csrf_token.secret(), &pkce_challenge);
+    app_state.device_logins.start(
+        device_code.clone(),
+        csrf_token.secret().to_string(),
+        pkce_verifier.secret().to_string(),
+    );
+
+    Ok(Json(DeviceLoginResponse {
+        device_code,
+        login_url,
+        expires_in: DEVICE_LOGIN_TTL.as_secs(),
+        interval: POLL_INTERVAL_SECS,
+    }))
+}
+
+// 202 while the user has not finished logging in, then the token for use as a bearer token
+pub async fn device_poll_handler(
+    State(app_state): State<AppState>,
+    Path(device_code): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    match app_state.device_logins.poll(&device_code) {
+        None => Err(AppError::NotFound("Unknown or expired device code".to_string())),
+        Some(None) => Ok((
+            StatusCode::ACCEPTED,
+            Json(DeviceLoginStatus {
+                status: "pending",
+                access_token: None,
+                account_name: None,
+            }),
+        )),
+        Some(Some(account)) => Ok((
+            StatusCode::OK,
+            Json(DeviceLoginStatus {
+                status: "complete",
+                access_token: Some(account.access_token),
+                account_name: Some(account.name),
+            }),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_login_is_handed_out_once() {
+        let logins = DeviceLogins::default();
+        logins.start("device".to_string(), "state".to_string(), "verifier".to_string());
+
+        assert!(matches!(logins.poll("device"), Some(None)));
+        assert_eq!(logins.verifier_for_state("state").as_deref(), Some("verifier"));
+
+        logins.complete(
+            "state",
+            StoredAccount {
+                id: "org".to_string(),
+                name: "org".to_string(),
+                access_token: "token".to_string(),
+                refresh_token: None,
+                expires_at: None,
+                scopes: Vec::new(),
+            },
+        );
+        assert_eq!(logins.verifier_for_state("state"), None);
+
+        let account = logins.poll("device").flatten().unwrap();
+        assert_eq!(account.access_token, "token");
+        assert!(logins.poll("device").is_none());
+    }
+}
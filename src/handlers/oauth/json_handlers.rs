@@ -0,0 +1,33 @@
+use crate::error::AppError;
+use crate::handlers::oauth::callback_handler::complete_login;
+use crate::handlers::oauth::login_handler::start_login;
+use crate::models::oauth::{AccountSummary, AuthorizeResponse, CallbackParams};
+use crate::models::AppState;
+use axum::{
+    extract::{Query, State},
+    response::Json,
+};
+use tower_sessions::Session;
+
+// The same OAuth flow for frontends that drive it themselves: they open the returned URL,
+// and with REDIRECT_URL pointing at the frontend, pass the code and state on to the callback
+pub async fn api_login_handler(
+    State(app_state): State<AppState>,
+    session: Session,
+) -> Result<Json<AuthorizeResponse>, AppError> {
+    let authorize_url = start_login(&app_state, &session).await?;
+    Ok(Json(AuthorizeResponse { authorize_url }))
+}
+
+pub async fn api_callback_handler(
+    State(app_state): State<AppState>,
+    Query(params): Query<CallbackParams>,
+    session: Session,
+) -> Result<Json<AccountSummary>, AppError> {
+    let account = complete_login(&app_state, &session, params).await?;
+    Ok(Json(AccountSummary {
+        id: account.id,
+        name: account.name,
+        active: true,
+    }))
+}
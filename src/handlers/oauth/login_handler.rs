@@ -1,7 +1,8 @@
+use crate::error::AppError;
 use crate::models::AppState;
-use crate::models::oauth::OAuthSessionData;
+use crate::models::oauth::{LoginParams, OAuthSessionData};
 use axum::{
-    extract::State,
+    extract::{Query, State},
     response::{IntoResponse, Redirect},
 };
 use oauth2::{CsrfToken, PkceCodeChallenge};
@@ -9,54 +10,61 @@ use tower_sessions::Session;
 
 pub async fn login_handler(
     State(app_state): State<AppState>,
+    Query(params): Query<LoginParams>,
     session: Session,
 ) -> impl IntoResponse {
     let access_token_option: Option<String> =
         session.get("supabase_access_token").await.ok().flatten();
 
-    if let Some(_) = access_token_option {
-        eprintln!("Existing Supabase access token found in session. Skipping full OAuth flow.");
+    if access_token_option.is_some() && !params.add_account {
+        tracing::debug!("access token already in session, skipping the OAuth flow");
         return Redirect::to("/connect-supabase/projects").into_response();
     }
 
-    let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
-    let csrf_token = CsrfToken::new_random();
-
-    let mut url = reqwest::Url::parse("https://api.supabase.com/v1/oauth/authorize").expect("Failed to parse auth URL");
+    match start_login(&app_state, &session).await {
+        Ok(url) => Redirect::to(&url).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
 
-    url.query_pairs_mut()
-        .append_pair("client_id", &app_state.config.client_id)
-        .append_pair("redirect_uri", &app_state.config.redirect_url.as_str())
-        .append_pair("response_type", "code")
-        .append_pair("state", csrf_token.secret())
-        .append_pair("code_challenge", &pkce_challenge.as_str())
-        .append_pair("code_challenge_method", "S256");
+// Stores a fresh PKCE verifier and CSRF state in the session and returns the URL that
+// starts the authorization on Supabase
+pub async fn start_login(app_state: &AppState, session: &Session) -> Result<String, AppError> {
+    if app_state.config.client_id.is_empty() {
+        return Err(AppError::NotFound("OAuth login is not configured".to_string()));
+    }
 
-    let constructed_url = url.to_string();
+    let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+    let csrf_token = CsrfToken::new_random();
+    let url = authorize_url(app_state, csrf_token.secret(), &pkce_challenge);
 
     let session_data = OAuthSessionData {
         pkce_verifier_secret: Some(pkce_verifier.secret().to_string()),
         csrf_token_secret: Some(csrf_token.secret().to_string()),
     };
 
-    eprintln!("oauth inserted into session: {:?}", session_data);
-    if let Err(e) = session.insert("oauth_data", session_data).await {
-        eprintln!("Failed to insert oauth_data into session: {:?}", e);
-    }
+    session.insert("oauth_data", session_data).await?;
+    session.save().await?;
 
-    match session.get::<OAuthSessionData>("oauth_data").await {
-        Ok(Some(_)) => eprintln!("Successfully verified oauth_data in session"),
-        Ok(None) => eprintln!("WARNING: oauth_data was not found during verification"),
-        Err(e) => eprintln!("Error verifying oauth_data in session: {:?}", e),
-    }
+    tracing::debug!(session_id = ?session.id(), "OAuth state stored, redirecting to Supabase");
+    Ok(url)
+}
+
+pub fn authorize_url(app_state: &AppState, state: &str, pkce_challenge: &PkceCodeChallenge) -> String {
+    let mut url = reqwest::Url::parse("https://api.supabase.com/v1/oauth/authorize").expect("Failed to parse auth URL");
+
+    url.query_pairs_mut()
+        .append_pair("client_id", &app_state.config.client_id)
+        .append_pair("redirect_uri", app_state.config.redirect_url.as_str())
+        .append_pair("response_type", "code")
+        .append_pair("state", state)
+        .append_pair("code_challenge", pkce_challenge.as_str())
+        .append_pair("code_challenge_method", "S256");
 
-    if let Err(e) = session.save().await {
-        eprintln!("Failed to save session: {:?}", e);
+    if !app_state.config.oauth_scopes.is_empty() {
+        url.query_pairs_mut()
+            .append_pair("scope", &app_state.config.oauth_scopes.join(" "));
     }
 
-    eprintln!(
-        "oauth session stored for session ID: {:?}. Redirecting to Supabase...",
-        session.id()
-    );
-    Redirect::to(&constructed_url).into_response()
+    url.to_string()
 }
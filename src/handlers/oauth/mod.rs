@@ -1,2 +1,9 @@
 pub mod callback_handler;
+pub mod device;
+pub mod json_handlers;
 pub mod login_handler;
+
+pub use callback_handler::callback_handler;
+pub use device::{device_login_handler, device_poll_handler};
+pub use json_handlers::{api_callback_handler, api_login_handler};
+pub use login_handler::login_handler;
@@ -0,0 +1,55 @@
+use crate::error::ErrorResponse;
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use std::any::Any;
+use std::backtrace::Backtrace;
+
+// Target of panic logs, which the Sentry layer skips since its panic integration reports them
+pub const PANIC_LOG_TARGET: &str = "panic";
+
+// Logs panics with a backtrace of where they happened, inside the span of the request that
+// panicked. Sentry chains its own hook onto this one when it is set up afterwards.
+pub fn install_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        let backtrace = Backtrace::force_capture();
+        tracing::error!(target: PANIC_LOG_TARGET, panic = %info, %backtrace, "handler panicked");
+    }));
+}
+
+// Answers a request whose handler panicked. The panic message stays in the logs, the client
+// only gets the request ID to quote.
+pub fn panic_response(_panic: Box<dyn Any + Send + 'static>) -> Response {
+    let body = Json(ErrorResponse::new("Internal server error".to_string()));
+    (StatusCode::INTERNAL_SERVER_ERROR, body).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::request_id::{with_request_id, REQUEST_ID_HEADER};
+    use axum::{body::Body, extract::Request, middleware, routing::get, Router};
+    use serde_json::Value;
+    use tower::ServiceExt;
+    use tower_http::catch_panic::CatchPanicLayer;
+
+    #[tokio::test]
+    async fn test_panic_answered_with_request_id() {
+        let app: Router = Router::new()
+            .route("/boom", get(|| async { panic!("boom") as &'static str }))
+            .layer(CatchPanicLayer::custom(panic_response))
+            .layer(middleware::from_fn(with_request_id));
+
+        let request = Request::get("/boom").header(REQUEST_ID_HEADER, "req-1").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "req-1");
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["request_id"], "req-1");
+        assert_eq!(body["error"], "Internal server error");
+    }
+}
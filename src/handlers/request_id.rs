@@ -0,0 +1,74 @@
+use axum::{
+    extract::Request,
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+// Longest ID taken over from a client or proxy; anything longer gets a fresh one
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+// ID of the request being handled, for error responses. None outside a request, e.g. in
+// background jobs.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+// Gives every request an ID, or keeps the one a proxy already set in X-Request-Id. Logs
+// written while handling it carry the ID, and the response echoes it back.
+pub async fn with_request_id(request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid(id))
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    sentry::configure_scope(|scope| scope.set_tag("request_id", &request_id));
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %request.method(),
+        path = request.uri().path(),
+    );
+    let mut response = REQUEST_ID
+        .scope(request_id.clone(), next.run(request))
+        .instrument(span)
+        .await;
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_request_id_visible_inside_scope_only() {
+        assert!(is_valid("3f2a-req_1"));
+        assert!(!is_valid(""));
+        assert!(!is_valid("id with spaces"));
+        assert!(!is_valid(&"a".repeat(MAX_REQUEST_ID_LEN + 1)));
+
+        assert_eq!(current_request_id(), None);
+        let inside = REQUEST_ID.scope("req-1".to_string(), async { current_request_id() }).await;
+        assert_eq!(inside.as_deref(), Some("req-1"));
+    }
+}
@@ -5,6 +5,6 @@ use axum::{
 use crate::models::app_config::AppState;
 
 pub async fn test_handler(State(_app_state): State<AppState>) -> impl IntoResponse {
-    eprintln!("Hello world log!");
-    return Html(format!("<h1>Hello World!</h1>"));
+    tracing::info!("Hello world log!");
+    Html("<h1>Hello World!</h1>")
 }
\ No newline at end of file
@@ -0,0 +1,41 @@
+use crate::error::AppError;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::time::Duration;
+
+// Fails requests whose handler runs longer than the configured timeout with a 408. Only the
+// wait for the response is limited, so streamed bodies such as job events keep going.
+pub async fn with_timeout(State(timeout): State<Duration>, request: Request, next: Next) -> Response {
+    match tokio::time::timeout(timeout, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            tracing::warn!(timeout_secs = timeout.as_secs(), "request timed out");
+            AppError::RequestTimeout(format!("Request did not finish within {} seconds", timeout.as_secs()))
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::StatusCode, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_slow_handler_times_out() {
+        let app = Router::new()
+            .route("/fast", get(|| async { "done" }))
+            .route("/slow", get(|| tokio::time::sleep(Duration::from_secs(5))))
+            .layer(middleware::from_fn_with_state(Duration::from_millis(50), with_timeout));
+
+        let fast = app.clone().oneshot(Request::get("/fast").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(fast.status(), StatusCode::OK);
+        let slow = app.oneshot(Request::get("/slow").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(slow.status(), StatusCode::REQUEST_TIMEOUT);
+    }
+}
@@ -1,42 +1,259 @@
 mod models;
+mod cors;
+mod error;
 mod handlers;
+mod maintenance;
+mod sessions;
+mod storage;
+mod supabase_client;
+mod telemetry;
+mod token_cipher;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    use axum::{routing::get, Router};
+    use axum::{extract::DefaultBodyLimit, middleware, routing::{any, delete, get, post}, Router};
+    use axum_server::tls_rustls::RustlsConfig;
+    use tower_http::catch_panic::CatchPanicLayer;
+    use tower_http::services::{ServeDir, ServeFile};
     use models::{AppConfig, AppState};
     use handlers::test_handler;
-    use handlers::migrate::preview_handler;
-    use tower_sessions::{Expiry, MemoryStore, SessionManagerLayer};
-    use time::Duration;
+    use handlers::migrate::{
+        apply_handler, audit_handler, branches_handler, create_snapshot_handler, create_webhook_handler,
+        delete_profile_handler, delete_webhook_handler, drift_handler, drift_status_handler, export_handler,
+        get_preferences_handler, get_profile_handler, import_handler, job_events_handler, job_status_handler, list_profiles_handler,
+        list_snapshots_handler, list_webhooks_handler, organizations_handler, plan_handler, preview_handler,
+        preview_post_handler, preview_profile_handler, preview_report_handler, preview_value_handler, projects_handler,
+        resume_job_handler, rollback_handler, save_profile_handler, snapshot_diff_handler, update_preferences_handler,
+    };
+    use handlers::migrate::apply_snapshots::ApplySnapshots;
+    use handlers::migrate::audit::AuditLog;
+    use handlers::migrate::diff_cache::DiffCache;
+    use handlers::migrate::drift::spawn_drift_checks;
+    use handlers::migrate::jobs::JobManager;
+    use handlers::migrate::notify::Notifier;
+    use handlers::migrate::profiles::Profiles;
+    use handlers::migrate::snapshots::ConfigSnapshots;
+    use maintenance::spawn_maintenance;
+    use storage::{open_storage, Collection, Records};
+    use supabase_client::{build_http_client, CircuitBreaker, ResponseCache};
+    use cors::cors_layer;
+    use sessions::{with_sessions, SessionStats};
+    use sentry::integrations::tower::{NewSentryLayer, SentryHttpLayer};
+    use telemetry::{init_sentry, init_tracing};
+    use token_cipher::TokenCipher;
     
-    //use handlers::{callback_handler, login_handler};
+    use handlers::{
+        accounts_handler, admin_sessions_handler, api_callback_handler, api_login_handler, callback_handler, csrf_token_handler,
+        device_login_handler, device_poll_handler, healthz_handler, install_panic_hook, legacy_api_redirect, login_handler,
+        me_handler, panic_response, readyz_handler, require_csrf_token, switch_account_handler, with_request_id,
+        with_timeout, API_PREFIX, LEGACY_API_PATHS,
+    };
 
     let app_config = AppConfig::from_env()?;
 
+    install_panic_hook();
+    let _sentry = init_sentry(&app_config);
+    let tracer_provider = init_tracing(&app_config)?;
+
+    // JSON endpoints, versioned so the preview and apply contracts can change without breaking clients
+    let api = Router::new()
+        .route("/preview", get(preview_handler).post(preview_post_handler))
+        .route("/preview/report", get(preview_report_handler))
+        .route("/preview/value", get(preview_value_handler))
+        .route("/migrate/plan", get(plan_handler))
+        .route("/organizations", get(organizations_handler))
+        .route("/projects", get(projects_handler))
+        .route("/projects/{project_id}/branches", get(branches_handler))
+        .route(
+            "/projects/{project_id}/snapshots",
+            get(list_snapshots_handler).post(create_snapshot_handler),
+        )
+        .route("/projects/{project_id}/drift", get(drift_handler))
+        .route("/projects/{project_id}/export", get(export_handler))
+        .route("/projects/{project_id}/import", post(import_handler))
+        .route("/snapshots/{from_id}/diff/{to_id}", get(snapshot_diff_handler))
+        .route("/preferences", get(get_preferences_handler).put(update_preferences_handler))
+        .route("/profiles", get(list_profiles_handler).post(save_profile_handler))
+        .route("/profiles/{name}", get(get_profile_handler).delete(delete_profile_handler))
+        .route("/profiles/{name}/preview", post(preview_profile_handler))
+        .route("/drift/status", get(drift_status_handler))
+        .route("/audit", get(audit_handler))
+        .route("/migrate/apply", post(apply_handler))
+        .route("/migrate/rollback/{apply_id}", post(rollback_handler))
+        .route("/migrate/jobs/{job_id}", get(job_status_handler))
+        .route("/migrate/jobs/{job_id}/events", get(job_events_handler))
+        .route("/migrate/jobs/{job_id}/resume", post(resume_job_handler))
+        .route("/me", get(me_handler))
+        .route("/accounts", get(accounts_handler))
+        .route("/accounts/switch", post(switch_account_handler))
+        .route("/admin/sessions", get(admin_sessions_handler))
+        .route("/admin/webhooks", get(list_webhooks_handler).post(create_webhook_handler))
+        .route("/admin/webhooks/{webhook_id}", delete(delete_webhook_handler))
+        .route("/csrf-token", get(csrf_token_handler));
+
+    // Probes and the login flows stay where load balancers and OAuth apps expect them
+    let app = Router::new()
+        .nest(API_PREFIX, api)
+        .route("/healthz", get(healthz_handler))
+        .route("/readyz", get(readyz_handler))
+        .route("/connect-supabase/login", get(login_handler))
+        .route("/connect-supabase/oauth2/callback", get(callback_handler))
+        .route("/api/auth/login", get(api_login_handler))
+        .route("/api/auth/callback", get(api_callback_handler))
+        .route("/api/auth/device", post(device_login_handler))
+        .route("/api/auth/device/{device_code}", get(device_poll_handler));
+    let app = LEGACY_API_PATHS
+        .iter()
+        .fold(app, |app, path| app.route(path, any(legacy_api_redirect)));
+    // The frontend takes over the root and every path the API does not know
+    let app = match &app_config.frontend_dir {
+        Some(dir) => app.fallback_service(ServeDir::new(dir).fallback(ServeFile::new(dir.join("index.html")))),
+        None => app.route("/", get(test_handler)),
+    };
+    let app = app
+        // Inside the session layer, which with_sessions adds around everything above
+        .layer(middleware::from_fn(require_csrf_token));
+    let session_stats = SessionStats::default();
+    let token_cipher = app_config.token_encryption_key.as_ref().map(TokenCipher::new);
+    let (app, session_probe, session_sweeper) = with_sessions(
+        app,
+        &app_config.session_store,
+        &session_stats,
+        token_cipher.clone(),
+        app_config.tls.is_some(),
+    )
+    .await?;
+
+    let http = build_http_client()?;
+    let storage = open_storage(&app_config).await?;
+    let [job_records, snapshot_records, audit_records, profile_records, apply_snapshot_records] = [
+        Collection::Jobs,
+        Collection::Snapshots,
+        Collection::Audit,
+        Collection::Profiles,
+        Collection::ApplySnapshots,
+    ]
+    .map(|collection| Records::new(storage.clone(), collection));
+    if token_cipher.is_none() && (app_config.database_url.is_some() || app_config.job_state_dir.is_some()) {
+        tracing::warn!("rollback snapshots are only kept until restart without TOKEN_ENCRYPTION_KEY");
+    }
     let app_state = AppState {
         config: app_config.clone(),
+        apply_snapshots: ApplySnapshots::load(Some(apply_snapshot_records.clone()), token_cipher).await?,
+        jobs: JobManager::load(app_config.max_concurrent_jobs, Some(job_records.clone())).await?,
+        config_snapshots: ConfigSnapshots::load(Some(snapshot_records.clone())).await?,
+        audit: AuditLog::load(Some(audit_records.clone())).await?,
+        profiles: Profiles::load(Some(profile_records.clone())).await?,
+        drift: Default::default(),
+        notifier: Notifier::new(http.clone(), &app_config.notify_webhook_urls)?,
+        http,
+        api_cache: ResponseCache::new(std::time::Duration::from_secs(app_config.api_cache_ttl_secs)),
+        diff_cache: DiffCache::new(std::time::Duration::from_secs(app_config.diff_cache_ttl_secs)),
+        api_breaker: CircuitBreaker::new(
+            app_config.api_breaker_threshold,
+            std::time::Duration::from_secs(app_config.api_breaker_cooldown_secs),
+        ),
+        device_logins: Default::default(),
+        session_stats,
+        session_probe,
+    };
+    spawn_drift_checks(app_state.clone());
+    spawn_maintenance(app_state.clone(), session_sweeper);
+
+    let app = match &app_config.base_path {
+        Some(base_path) => Router::new().nest(base_path, app),
+        None => app,
+    };
+    let app = app.layer(DefaultBodyLimit::max(app_config.request_body_limit));
+    // Inside the request ID, so the 500 answering a panic can carry it
+    let app = app.layer(CatchPanicLayer::custom(panic_response));
+    let app = if app_config.request_timeout_secs == 0 {
+        app
+    } else {
+        let timeout = std::time::Duration::from_secs(app_config.request_timeout_secs);
+        app.layer(middleware::from_fn_with_state(timeout, with_timeout))
+    };
+    // Outside the session and CSRF layers, so their failures carry the request ID as well
+    let app = app.layer(middleware::from_fn(with_request_id));
+    // Outside the request ID, so every request reports through its own hub with the request attached
+    let app = if app_config.sentry_dsn.is_some() {
+        app.layer(SentryHttpLayer::new())
+            .layer(NewSentryLayer::<axum::extract::Request>::new_from_top())
+    } else {
+        app
+    };
+    // Outermost, so preflight requests are answered before anything else runs
+    let app = if app_config.cors_allowed_origins.is_empty() {
+        app
+    } else {
+        app.layer(cors_layer(&app_config.cors_allowed_origins))
     };
+    let app = app.with_state(app_state);
 
-    let session_store = MemoryStore::default();
-    let session_expiry = Expiry::OnInactivity(Duration::hours(6));
-    let session_layer = SessionManagerLayer::new(session_store)
-        .with_secure(false)
-        .with_same_site(tower_sessions::cookie::SameSite::Lax)
-        .with_expiry(session_expiry);
+    let listener = tokio::net::TcpListener::bind((app_config.host.as_str(), app_config.port)).await?;
+    let base_path = app_config.base_path.as_deref().unwrap_or_default();
+    let served: Result<(), Box<dyn std::error::Error>> = match &app_config.tls {
+        Some(tls) => {
+            // Picked explicitly, as rustls cannot choose once more than one provider is compiled in
+            let _ = rustls::crypto::ring::default_provider().install_default();
+            let rustls_config = RustlsConfig::from_pem_file(&tls.cert_path, &tls.key_path)
+                .await
+                .map_err(|e| format!("Failed to load TLS certificate and key: {}", e))?;
+            tracing::info!("listening on https://{}{}", listener.local_addr()?, base_path);
+            let handle = axum_server::Handle::new();
+            let shutdown = handle.clone();
+            tokio::spawn(async move {
+                shutdown_signal().await;
+                shutdown.graceful_shutdown(Some(SHUTDOWN_GRACE));
+            });
+            axum_server::from_tcp_rustls(listener.into_std()?, rustls_config)
+                .handle(handle)
+                .serve(app.into_make_service())
+                .await
+                .map_err(Into::into)
+        }
+        None => {
+            tracing::info!("listening on http://{}{}", listener.local_addr()?, base_path);
+            axum::serve(listener, app.into_make_service())
+                .with_graceful_shutdown(shutdown_signal())
+                .await
+                .map_err(Into::into)
+        }
+    };
 
-    let app = Router::new()
-        .route("/", get(test_handler))
-        .route("/preview", get(preview_handler))
-        //.route("/connect-supabase/login", get(login_handler))
-        //.route("/connect-supabase/oauth2/callback", get(callback_handler))
-        .layer(session_layer)
-        .with_state(app_state);
+    // Saves still queued are written before the process exits
+    for records in [job_records, snapshot_records, audit_records, profile_records, apply_snapshot_records] {
+        records.flush().await;
+    }
+    if let Some(provider) = tracer_provider {
+        let _ = provider.shutdown();
+    }
+    served
+}
 
-    eprintln!("listening on http://{}", "0.0.0.0:10000");
+// How long open connections get to finish once a shutdown is asked for, when serving TLS
+const SHUTDOWN_GRACE: std::time::Duration = std::time::Duration::from_secs(10);
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:10000").await?;
-    axum::serve(listener, app.into_make_service()).await?;
+// Resolves on Ctrl-C, or the SIGTERM a container runtime sends to stop the server
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
 
-    Ok(())
-}
\ No newline at end of file
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    tracing::info!("shutting down");
+}
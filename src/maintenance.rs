@@ -0,0 +1,106 @@
+use crate::models::AppState;
+use crate::sessions::SessionSweeper;
+
+use std::time::Duration;
+use time::OffsetDateTime;
+
+// Prunes what a long-running deployment would otherwise pile up, on the configured interval:
+// expired sessions, finished jobs past the retention window and the rollback snapshots no
+// job refers to any more
+pub fn spawn_maintenance(app_state: AppState, sessions: SessionSweeper) {
+    let interval = app_state.config.maintenance_interval_secs;
+    if interval == 0 {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(interval));
+        // The first tick completes immediately, and there is nothing to prune at startup
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            run_maintenance(&app_state, &sessions).await;
+        }
+    });
+}
+
+#[tracing::instrument(skip_all)]
+async fn run_maintenance(app_state: &AppState, sessions: &SessionSweeper) {
+    let expired_sessions = sessions
+        .delete_expired(&app_state.session_stats)
+        .await
+        .inspect_err(|e| tracing::warn!(error = %e, "failed to delete expired sessions"))
+        .unwrap_or_default();
+
+    let retention = time::Duration::hours(app_state.config.job_retention_hours.try_into().unwrap_or(i64::MAX));
+    let cutoff = OffsetDateTime::now_utc()
+        .checked_sub(retention)
+        .unwrap_or(OffsetDateTime::UNIX_EPOCH);
+    let jobs = app_state.jobs.prune_finished(cutoff);
+    // After the jobs, so the snapshots of jobs pruned just now go as well. Imports have no job,
+    // so theirs go once past the cutoff.
+    let snapshots = app_state.apply_snapshots.prune(cutoff, &app_state.jobs.apply_ids());
+
+    if expired_sessions + jobs + snapshots > 0 {
+        tracing::info!(expired_sessions, jobs, snapshots, "pruned expired sessions, old jobs and orphaned snapshots");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::migrate::apply_snapshots::ApplySnapshots;
+    use crate::handlers::migrate::jobs::JobManager;
+    use crate::models::migrate::{ApplySnapshot, JobStatus, MigrationJob};
+
+    fn job(id: &str, status: JobStatus, age_hours: i64) -> MigrationJob {
+        let updated_at = OffsetDateTime::now_utc() - time::Duration::hours(age_hours);
+        MigrationJob {
+            id: id.to_string(),
+            apply_id: format!("apply-{}", id),
+            source_id: "src".to_string(),
+            dest_id: "dst".to_string(),
+            status,
+            steps: Vec::new(),
+            error: None,
+            residual_diffs: None,
+            requested_by: None,
+            created_at: updated_at,
+            updated_at,
+        }
+    }
+
+    fn snapshot(apply_id: &str, age_hours: i64) -> ApplySnapshot {
+        ApplySnapshot {
+            apply_id: apply_id.to_string(),
+            dest_id: "dst".to_string(),
+            services: Vec::new(),
+            created_at: OffsetDateTime::now_utc() - time::Duration::hours(age_hours),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_old_jobs_and_orphaned_snapshots_pruned() {
+        let jobs = JobManager::new(1);
+        let cutoff = OffsetDateTime::now_utc() - time::Duration::hours(24);
+        jobs.insert(job("old", JobStatus::Succeeded, 48));
+        jobs.insert(job("recent", JobStatus::Failed, 1));
+        jobs.insert(job("interrupted", JobStatus::Interrupted, 48));
+
+        assert_eq!(jobs.prune_finished(cutoff), 1);
+        assert!(jobs.get("old").is_none());
+        assert!(jobs.get("recent").is_some());
+        assert!(jobs.get("interrupted").is_some());
+
+        let snapshots = ApplySnapshots::default();
+        for (apply_id, age_hours) in [("apply-old", 48), ("apply-interrupted", 48), ("import", 48), ("recent-import", 1)] {
+            snapshots.insert(snapshot(apply_id, age_hours)).await;
+        }
+        assert_eq!(snapshots.prune(cutoff, &jobs.apply_ids()), 2);
+        let kept: Vec<bool> = ["apply-old", "apply-interrupted", "import", "recent-import"]
+            .iter()
+            .map(|apply_id| snapshots.contains(apply_id))
+            .collect();
+        assert_eq!(kept, vec![false, true, false, true]);
+    }
+}
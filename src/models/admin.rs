@@ -0,0 +1,15 @@
+use serde::Serialize;
+
+// Session overview for GET /admin/sessions. Counts only cover sessions this instance has
+// seen since it started, which for Redis and Postgres may not be all of them.
+#[derive(Debug, Serialize)]
+pub struct SessionReport {
+    pub store: &'static str,
+    pub tracked_sessions: usize,
+    pub active_sessions: usize,
+    pub authenticated_sessions: usize,
+    // Serialized size of the active sessions' data
+    pub approximate_bytes: usize,
+    pub token_expiries: Vec<String>,
+    pub expired_tokens: usize,
+}
@@ -1,8 +1,118 @@
+use crate::handlers::migrate::apply_snapshots::ApplySnapshots;
+use crate::handlers::migrate::audit::AuditLog;
+use crate::handlers::migrate::diff_cache::DiffCache;
+use crate::handlers::migrate::drift::{DriftMonitor, DriftSchedule, DriftWatch};
+use crate::handlers::migrate::jobs::JobManager;
+use crate::handlers::migrate::notify::Notifier;
+use crate::handlers::migrate::profiles::Profiles;
+use crate::handlers::migrate::snapshots::ConfigSnapshots;
+use crate::handlers::oauth::device::DeviceLogins;
+use crate::sessions::{SessionProbe, SessionStats};
+use crate::supabase_client::{CircuitBreaker, ResponseCache, RetryPolicy, SupabaseManagementClient};
+use axum::http::HeaderValue;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::path::PathBuf;
+use std::time::Duration;
+use uuid::Uuid;
+
 #[derive(Clone)]
 pub struct AppConfig {
+    pub host: String,
+    pub port: u16,
+    // Prefix every route is served under, e.g. /api behind a reverse proxy
+    pub base_path: Option<String>,
+    // Certificate and key to serve HTTPS with; plain HTTP behind a proxy without them
+    pub tls: Option<TlsConfig>,
+    // Frontend origins allowed to call the API with credentials; none keeps it same-origin
+    pub cors_allowed_origins: Vec<String>,
+    // Built frontend served next to the API, with unknown paths answered by its index.html
+    pub frontend_dir: Option<PathBuf>,
+    // Longest a handler may take before the request fails with a 408; 0 lets it run
+    pub request_timeout_secs: u64,
+    // Largest request body accepted, in bytes, before the request fails with a 413
+    pub request_body_limit: usize,
     pub client_id: String,
     pub client_secret: String,
     pub redirect_url: String,
+    pub max_concurrent_jobs: usize,
+    pub job_state_dir: Option<PathBuf>,
+    // Where config snapshots are written; without one they only live until restart
+    pub snapshot_dir: Option<PathBuf>,
+    // JSON lines file the audit log is appended to; without one it only lives until restart
+    pub audit_log_path: Option<PathBuf>,
+    // Where saved migration profiles are written; without one they only live until restart
+    pub profile_dir: Option<PathBuf>,
+    // Postgres or SQLite database that jobs, snapshots, profiles and the audit log are kept in
+    // instead of the directories and file above
+    pub database_url: Option<String>,
+    // Project pairs and snapshots re-diffed in the background, and the cron schedule they are
+    // re-diffed on; None turns scheduled drift checks off
+    pub drift_watches: Vec<DriftWatch>,
+    pub drift_schedule: Option<DriftSchedule>,
+    // How often expired sessions, old finished jobs and orphaned rollback snapshots are pruned;
+    // 0 turns it off
+    pub maintenance_interval_secs: u64,
+    // How long finished jobs are kept, and rollback snapshots of applies without a job
+    pub job_retention_hours: u64,
+    // Webhooks notified of drift and finished applies, next to those registered through the API
+    pub notify_webhook_urls: Vec<String>,
+    pub confirmation_secret: String,
+    pub confirmation_ttl_minutes: i64,
+    pub api_max_attempts: u32,
+    pub api_cache_ttl_secs: u64,
+    pub api_timeout_secs: u64,
+    pub api_breaker_threshold: u32,
+    pub api_breaker_cooldown_secs: u64,
+    pub api_debug_log: bool,
+    // One JSON object per log line, for log aggregation, instead of human-readable text
+    pub log_json: bool,
+    // OTLP/HTTP collector that request and upstream call spans are exported to, e.g. Jaeger or Tempo
+    pub otlp_endpoint: Option<String>,
+    pub otel_service_name: String,
+    // Error events and panics are reported to this Sentry project
+    pub sentry_dsn: Option<String>,
+    pub sentry_environment: Option<String>,
+    // Longest diff value returned by a preview before it is truncated; 0 returns values whole
+    pub diff_max_value_len: usize,
+    pub diff_cache_ttl_secs: u64,
+    // Used for Management API calls when a request brings no token of its own
+    pub personal_access_token: Option<String>,
+    pub session_store: SessionStoreConfig,
+    // AES-256 key tokens and rollback snapshots are encrypted with before they are saved.
+    // Required with the Redis and Postgres session stores; refresh tokens are only kept, and
+    // rollback snapshots only outlive a restart, with one.
+    pub token_encryption_key: Option<[u8; 32]>,
+    pub oauth_scopes: Vec<String>,
+    // Bearer token for the /admin endpoints, which are off without one
+    pub admin_token: Option<String>,
+    // Whether /readyz also requires the Management API to be reachable
+    pub readyz_check_api: bool,
+}
+
+// PEM files for serving HTTPS directly
+#[derive(Clone)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+// Where sessions are kept: in memory by default, or in Redis or Postgres so they survive
+// restarts and are shared between instances
+#[derive(Clone)]
+pub enum SessionStoreConfig {
+    Memory,
+    Redis { url: String },
+    Postgres { url: String },
+}
+
+impl SessionStoreConfig {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SessionStoreConfig::Memory => "memory",
+            SessionStoreConfig::Redis { .. } => "redis",
+            SessionStoreConfig::Postgres { .. } => "postgres",
+        }
+    }
 }
 
 impl AppConfig {
@@ -12,17 +122,285 @@ impl AppConfig {
 
         dotenv().ok();
 
-        let client_id = env::var("SUPA_CONNECT_CLIENT_ID")
-            .map_err(|e| format!("SUPA_CONNECT_CLIENT_ID not found: {}", e))?;
-        let client_secret = env::var("SUPA_CONNECT_CLIENT_SECRET")
-            .map_err(|e| format!("SUPA_CONNECT_CLIENT_SECRET not found: {}", e))?;
-        let redirect_url =
-            env::var("REDIRECT_URL").map_err(|e| format!("REDIRECT_URL not found: {}", e))?;
+        let host = env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
+        let port = match env::var("PORT") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| format!("PORT is not a valid port number: {}", e))?,
+            Err(_) => 10000,
+        };
+        // Normalized to a leading slash and no trailing one; empty or "/" serves from the root
+        let base_path = env::var("BASE_PATH")
+            .ok()
+            .map(|path| path.trim().trim_matches('/').to_string())
+            .filter(|path| !path.is_empty())
+            .map(|path| format!("/{}", path));
+        let tls = match (env::var("TLS_CERT_PATH"), env::var("TLS_KEY_PATH")) {
+            (Ok(cert_path), Ok(key_path)) => Some(TlsConfig {
+                cert_path: PathBuf::from(cert_path),
+                key_path: PathBuf::from(key_path),
+            }),
+            (Err(_), Err(_)) => None,
+            _ => return Err("TLS_CERT_PATH and TLS_KEY_PATH must be set together".to_string()),
+        };
+        // Credentials are allowed, so every origin has to be listed; a wildcard is not accepted
+        let cors_allowed_origins = env::var("CORS_ALLOWED_ORIGINS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|origin| origin.trim().trim_end_matches('/'))
+            .filter(|origin| !origin.is_empty())
+            .map(|origin| match origin.parse::<HeaderValue>() {
+                Ok(_) if origin != "*" => Ok(origin.to_string()),
+                _ => Err(format!("CORS_ALLOWED_ORIGINS entry {} is not an origin", origin)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let frontend_dir = env::var("FRONTEND_DIR").ok().map(PathBuf::from);
+        if let Some(dir) = &frontend_dir
+            && !dir.join("index.html").is_file()
+        {
+            return Err(format!("FRONTEND_DIR {} has no index.html", dir.display()));
+        }
+        let request_timeout_secs = match env::var("REQUEST_TIMEOUT_SECS") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| format!("REQUEST_TIMEOUT_SECS is not a valid number: {}", e))?,
+            Err(_) => 60,
+        };
+        let request_body_limit = match env::var("REQUEST_BODY_LIMIT_BYTES") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| format!("REQUEST_BODY_LIMIT_BYTES is not a valid number: {}", e))?,
+            Err(_) => 2 * 1024 * 1024,
+        };
+
+        let personal_access_token = env::var("SUPABASE_ACCESS_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty());
+        // The OAuth app is optional when the server runs with a personal access token
+        let oauth_var = |name: &str| match env::var(name) {
+            Ok(value) => Ok(value),
+            Err(_) if personal_access_token.is_some() => Ok(String::new()),
+            Err(e) => Err(format!("{} not found: {}", name, e)),
+        };
+        let client_id = oauth_var("SUPA_CONNECT_CLIENT_ID")?;
+        let client_secret = oauth_var("SUPA_CONNECT_CLIENT_SECRET")?;
+        let redirect_url = oauth_var("REDIRECT_URL")?;
+        let max_concurrent_jobs = match env::var("MAX_CONCURRENT_JOBS") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| format!("MAX_CONCURRENT_JOBS is not a valid number: {}", e))?,
+            Err(_) => 4,
+        };
+        let job_state_dir = env::var("JOB_STATE_DIR").ok().map(PathBuf::from);
+        let snapshot_dir = env::var("SNAPSHOT_DIR").ok().map(PathBuf::from);
+        let audit_log_path = env::var("AUDIT_LOG_PATH").ok().map(PathBuf::from);
+        let profile_dir = env::var("PROFILE_DIR").ok().map(PathBuf::from);
+        // Shared with the Postgres session store when that is used
+        let database_url = env::var("DATABASE_URL").ok().filter(|url| !url.trim().is_empty());
+        let drift_watches = env::var("DRIFT_WATCH")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(DriftWatch::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        // Hourly by default, "off" turns scheduled drift checks off
+        let drift_schedule = match env::var("DRIFT_SCHEDULE") {
+            Ok(value) if value.trim() == "off" => None,
+            Ok(value) => Some(DriftSchedule::parse(&value)?),
+            Err(_) => Some(DriftSchedule::parse("0 * * * *")?),
+        };
+        let maintenance_interval_secs = match env::var("MAINTENANCE_INTERVAL_SECS") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| format!("MAINTENANCE_INTERVAL_SECS is not a valid number: {}", e))?,
+            Err(_) => 3600,
+        };
+        let job_retention_hours = match env::var("JOB_RETENTION_HOURS") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| format!("JOB_RETENTION_HOURS is not a valid number: {}", e))?,
+            Err(_) => 7 * 24,
+        };
+        // Without a configured secret, confirmation tokens only survive until restart
+        let confirmation_secret = env::var("CONFIRMATION_SECRET").unwrap_or_else(|_| {
+            format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+        });
+        let confirmation_ttl_minutes = match env::var("CONFIRMATION_TTL_MINUTES") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| format!("CONFIRMATION_TTL_MINUTES is not a valid number: {}", e))?,
+            Err(_) => 15,
+        };
+        let api_max_attempts = match env::var("MGMT_API_MAX_ATTEMPTS") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| format!("MGMT_API_MAX_ATTEMPTS is not a valid number: {}", e))?,
+            Err(_) => 3,
+        };
+        // 0 turns the Management API response cache off
+        let api_cache_ttl_secs = match env::var("MGMT_API_CACHE_TTL_SECS") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| format!("MGMT_API_CACHE_TTL_SECS is not a valid number: {}", e))?,
+            Err(_) => 30,
+        };
+        let api_timeout_secs = match env::var("MGMT_API_TIMEOUT_SECS") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| format!("MGMT_API_TIMEOUT_SECS is not a valid number: {}", e))?,
+            Err(_) => 15,
+        };
+        // Consecutive upstream failures before requests are paused; 0 turns the breaker off
+        let api_breaker_threshold = match env::var("MGMT_API_BREAKER_THRESHOLD") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| format!("MGMT_API_BREAKER_THRESHOLD is not a valid number: {}", e))?,
+            Err(_) => 5,
+        };
+        let api_breaker_cooldown_secs = match env::var("MGMT_API_BREAKER_COOLDOWN_SECS") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| format!("MGMT_API_BREAKER_COOLDOWN_SECS is not a valid number: {}", e))?,
+            Err(_) => 30,
+        };
+        // Logs Management API requests and responses, with credentials redacted
+        let api_debug_log = match env::var("MGMT_API_DEBUG_LOG") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| format!("MGMT_API_DEBUG_LOG is not a valid boolean: {}", e))?,
+            Err(_) => false,
+        };
+        let log_json = match env::var("LOG_FORMAT").as_deref() {
+            Err(_) | Ok("text") => false,
+            Ok("json") => true,
+            Ok(other) => return Err(format!("LOG_FORMAT must be text or json, got {}", other)),
+        };
+        let otlp_endpoint = env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .ok()
+            .map(|endpoint| endpoint.trim().trim_end_matches('/').to_string())
+            .filter(|endpoint| !endpoint.is_empty());
+        let otel_service_name = env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "supabasemm-server".to_string());
+        let sentry_dsn = env::var("SENTRY_DSN").ok().filter(|dsn| !dsn.trim().is_empty());
+        let sentry_environment = env::var("SENTRY_ENVIRONMENT").ok();
+        let diff_max_value_len = match env::var("DIFF_MAX_VALUE_LEN") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| format!("DIFF_MAX_VALUE_LEN is not a valid number: {}", e))?,
+            Err(_) => 4096,
+        };
+        // 0 turns the diff cache off
+        let diff_cache_ttl_secs = match env::var("DIFF_CACHE_TTL_SECS") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| format!("DIFF_CACHE_TTL_SECS is not a valid number: {}", e))?,
+            Err(_) => 60,
+        };
+
+        let notify_webhook_urls = env::var("NOTIFY_WEBHOOK_URLS")
+            .map(|urls| {
+                urls.split(',')
+                    .map(str::trim)
+                    .filter(|url| !url.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        // Scopes requested on login, separated by spaces or commas; none leaves it to the OAuth app
+        let oauth_scopes = env::var("OAUTH_SCOPES")
+            .map(|scopes| {
+                scopes
+                    .split(|c: char| c.is_whitespace() || c == ',')
+                    .filter(|scope| !scope.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let admin_token = env::var("ADMIN_TOKEN").ok().filter(|token| !token.is_empty());
+        let readyz_check_api = match env::var("READYZ_CHECK_API") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| format!("READYZ_CHECK_API is not a valid boolean: {}", e))?,
+            Err(_) => false,
+        };
+        let session_store = match env::var("SESSION_STORE").as_deref() {
+            Err(_) | Ok("memory") => SessionStoreConfig::Memory,
+            Ok("redis") => SessionStoreConfig::Redis {
+                url: env::var("REDIS_URL")
+                    .map_err(|e| format!("REDIS_URL is required when SESSION_STORE=redis: {}", e))?,
+            },
+            Ok("postgres") => SessionStoreConfig::Postgres {
+                url: env::var("DATABASE_URL")
+                    .map_err(|e| format!("DATABASE_URL is required when SESSION_STORE=postgres: {}", e))?,
+            },
+            Ok(other) => {
+                return Err(format!("SESSION_STORE must be memory, redis or postgres, got {}", other));
+            }
+        };
+        // 32 bytes, base64-encoded, e.g. from `openssl rand -base64 32`
+        let token_encryption_key = match env::var("TOKEN_ENCRYPTION_KEY") {
+            Ok(key) => Some(
+                STANDARD
+                    .decode(key.trim())
+                    .map_err(|e| format!("TOKEN_ENCRYPTION_KEY is not valid base64: {}", e))?
+                    .try_into()
+                    .map_err(|_| "TOKEN_ENCRYPTION_KEY must be 32 bytes".to_string())?,
+            ),
+            Err(_) => None,
+        };
+        // Sessions in Redis or Postgres outlive the process, and tokens are never stored there in plaintext
+        if token_encryption_key.is_none() && !matches!(session_store, SessionStoreConfig::Memory) {
+            return Err(format!(
+                "TOKEN_ENCRYPTION_KEY is required when SESSION_STORE={}",
+                session_store.name()
+            ));
+        }
 
         Ok(Self {
+            host,
+            port,
+            base_path,
+            tls,
+            cors_allowed_origins,
+            frontend_dir,
+            request_timeout_secs,
+            request_body_limit,
             client_id,
             client_secret,
             redirect_url,
+            max_concurrent_jobs,
+            job_state_dir,
+            snapshot_dir,
+            audit_log_path,
+            profile_dir,
+            database_url,
+            drift_watches,
+            drift_schedule,
+            maintenance_interval_secs,
+            job_retention_hours,
+            notify_webhook_urls,
+            confirmation_secret,
+            confirmation_ttl_minutes,
+            api_max_attempts,
+            api_cache_ttl_secs,
+            api_timeout_secs,
+            api_breaker_threshold,
+            api_breaker_cooldown_secs,
+            api_debug_log,
+            log_json,
+            otlp_endpoint,
+            otel_service_name,
+            sentry_dsn,
+            sentry_environment,
+            diff_max_value_len,
+            diff_cache_ttl_secs,
+            personal_access_token,
+            session_store,
+            token_encryption_key,
+            oauth_scopes,
+            admin_token,
+            readyz_check_api,
         })
     }
 }
@@ -30,4 +408,33 @@ impl AppConfig {
 #[derive(Clone)]
 pub struct AppState {
     pub config: AppConfig,
+    pub apply_snapshots: ApplySnapshots,
+    pub jobs: JobManager,
+    pub config_snapshots: ConfigSnapshots,
+    pub audit: AuditLog,
+    pub profiles: Profiles,
+    pub drift: DriftMonitor,
+    pub notifier: Notifier,
+    pub http: reqwest::Client,
+    pub api_cache: ResponseCache,
+    pub diff_cache: DiffCache,
+    pub api_breaker: CircuitBreaker,
+    pub device_logins: DeviceLogins,
+    pub session_stats: SessionStats,
+    pub session_probe: SessionProbe,
+}
+
+impl AppState {
+    // Management API client for one user's token, with the configured retries and timeout. It keeps
+    // the response cache up to date but only reads from it when asked to.
+    pub fn management_client(&self, token: String) -> SupabaseManagementClient {
+        SupabaseManagementClient::new(self.http.clone(), token)
+            .with_retry(RetryPolicy {
+                max_attempts: self.config.api_max_attempts.max(1),
+                ..RetryPolicy::default()
+            })
+            .with_timeout(Duration::from_secs(self.config.api_timeout_secs))
+            .with_cache(self.api_cache.clone())
+            .with_breaker(self.api_breaker.clone())
+    }
 }
\ No newline at end of file
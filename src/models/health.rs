@@ -0,0 +1,38 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+// Which build is running, so deploys can be checked from the probes
+#[derive(Debug, Serialize, Clone, Copy)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_sha: Option<&'static str>,
+}
+
+impl BuildInfo {
+    pub const CURRENT: BuildInfo = BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: option_env!("GIT_SHA"),
+    };
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthResponse {
+    pub status: &'static str,
+    #[serde(flatten)]
+    pub build: BuildInfo,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadinessResponse {
+    pub ready: bool,
+    #[serde(flatten)]
+    pub build: BuildInfo,
+    pub checks: BTreeMap<&'static str, ReadinessCheck>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadinessCheck {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
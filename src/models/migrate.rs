@@ -1,14 +1,614 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use time::OffsetDateTime;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ProjectConfig {
     pub name: String,
     pub diffs: Vec<DiffEntry>,
+    #[serde(default)]
+    pub summary: DiffSummary,
+}
+
+impl ProjectConfig {
+    pub fn new(name: String, diffs: Vec<DiffEntry>) -> Self {
+        let summary = DiffSummary::of(&diffs);
+        ProjectConfig { name, diffs, summary }
+    }
+}
+
+// Entry counts by change type, so callers can show totals without walking the diffs
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiffSummary {
+    pub added: usize,
+    pub removed: usize,
+    pub modified: usize,
+    pub total: usize,
+}
+
+impl DiffSummary {
+    pub fn of(diffs: &[DiffEntry]) -> Self {
+        let mut summary = DiffSummary::default();
+        for diff in diffs {
+            match diff.change_type {
+                ChangeType::Added => summary.added += 1,
+                ChangeType::Removed => summary.removed += 1,
+                ChangeType::Modified => summary.modified += 1,
+            }
+            summary.total += 1;
+        }
+        summary
+    }
+}
+
+// Totals across every service of a preview, plus each service's change count
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct PreviewSummary {
+    #[serde(flatten)]
+    pub totals: DiffSummary,
+    pub services: BTreeMap<String, usize>,
+}
+
+impl PreviewSummary {
+    pub fn of(configs: &[ProjectConfig]) -> Self {
+        let mut summary = PreviewSummary::default();
+        for config in configs {
+            summary.totals.added += config.summary.added;
+            summary.totals.removed += config.summary.removed;
+            summary.totals.modified += config.summary.modified;
+            summary.totals.total += config.summary.total;
+            summary.services.insert(config.name.clone(), config.summary.total);
+        }
+        summary
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DiffEntry {
     pub key: String,
-    pub source_value: String,
-    pub dest_value: String,
-}
\ No newline at end of file
+    pub change_type: ChangeType,
+    // None on the side the key is missing from; a JSON null is Some("null")
+    pub source_value: Option<String>,
+    pub dest_value: Option<String>,
+    pub risk: RiskLevel,
+    // Set when a value was cut to the preview's max length; /preview/value returns it whole
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+// Diff entries grouped by key, for output=tree. Entries keep their full key.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct DiffNode {
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub children: BTreeMap<String, DiffNode>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub entries: Vec<DiffEntry>,
+}
+
+// What migrating the key would do to the destination
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeType {
+    // Only in the source
+    Added,
+    // Only in the destination
+    Removed,
+    Modified,
+}
+
+// How closely a change should be looked at before apply, from the service's risk rules
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskLevel {
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+// A source/dest mismatch worth calling out on its own, e.g. an undersized destination
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PreviewWarning {
+    pub service: String,
+    pub message: String,
+}
+
+// Typed views of the config payloads. Services that use one are deserialized into it before
+// diffing, so payloads are validated and fields the model does not list never reach the diff.
+
+// General Auth settings; templates, SMS, providers, hooks, MFA, captcha and SMTP are diffed
+// as their own config types
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AuthConfig {
+    pub site_url: Option<String>,
+    pub uri_allow_list: Option<String>,
+    pub disable_signup: Option<bool>,
+    pub jwt_exp: Option<i64>,
+    pub external_email_enabled: Option<bool>,
+    pub external_phone_enabled: Option<bool>,
+    pub external_anonymous_users_enabled: Option<bool>,
+    pub mailer_autoconfirm: Option<bool>,
+    pub mailer_secure_email_change_enabled: Option<bool>,
+    pub mailer_otp_exp: Option<i64>,
+    pub mailer_otp_length: Option<i64>,
+    pub password_min_length: Option<i64>,
+    pub password_required_characters: Option<String>,
+    pub password_hibp_enabled: Option<bool>,
+    pub refresh_token_rotation_enabled: Option<bool>,
+    pub security_refresh_token_reuse_interval: Option<i64>,
+    pub security_update_password_require_reauth: Option<bool>,
+    pub security_manual_linking_enabled: Option<bool>,
+    pub sessions_timebox: Option<i64>,
+    pub sessions_inactivity_timeout: Option<i64>,
+    pub sessions_single_per_user: Option<bool>,
+    pub sessions_tags: Option<String>,
+    pub rate_limit_email_sent: Option<i64>,
+    pub rate_limit_sms_sent: Option<i64>,
+    pub rate_limit_verify: Option<i64>,
+    pub rate_limit_token_refresh: Option<i64>,
+    pub rate_limit_otp: Option<i64>,
+    pub rate_limit_anonymous_users: Option<i64>,
+    pub db_max_pool_size: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PostgrestConfig {
+    pub db_schema: Option<String>,
+    pub db_extra_search_path: Option<String>,
+    pub max_rows: Option<i64>,
+    pub db_pool: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PostgresConfig {
+    pub effective_cache_size: Option<String>,
+    pub logical_decoding_work_mem: Option<String>,
+    pub maintenance_work_mem: Option<String>,
+    pub max_connections: Option<i64>,
+    pub max_locks_per_transaction: Option<i64>,
+    pub max_parallel_maintenance_workers: Option<i64>,
+    pub max_parallel_workers: Option<i64>,
+    pub max_parallel_workers_per_gather: Option<i64>,
+    pub max_replication_slots: Option<i64>,
+    pub max_slot_wal_keep_size: Option<String>,
+    pub max_standby_archive_delay: Option<String>,
+    pub max_standby_streaming_delay: Option<String>,
+    pub max_wal_size: Option<String>,
+    pub max_wal_senders: Option<i64>,
+    pub max_worker_processes: Option<i64>,
+    pub session_replication_role: Option<String>,
+    pub shared_buffers: Option<String>,
+    pub statement_timeout: Option<String>,
+    pub track_activity_query_size: Option<String>,
+    pub track_commit_timestamp: Option<bool>,
+    pub wal_keep_size: Option<String>,
+    pub wal_sender_timeout: Option<String>,
+    pub work_mem: Option<String>,
+}
+
+// One project secret; updated_at differs between projects and is left out
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Secret {
+    pub name: String,
+    pub value: String,
+}
+
+// One deployed edge function, as the function list describes it
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EdgeFunction {
+    pub slug: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verify_jwt: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub import_map: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entrypoint_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub import_map_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ezbr_sha256: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ApplyRequest {
+    pub source_id: String,
+    pub dest_id: String,
+    pub services: Vec<ServiceApply>,
+    pub confirmation_token: String,
+}
+
+// Body of POST /migrate/jobs/{job_id}/resume, with a token from a preview run after the failure
+#[derive(Debug, Deserialize)]
+pub struct ResumeJobRequest {
+    pub confirmation_token: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ServiceApply {
+    pub service: String,
+    pub keys: Vec<String>,
+    // Also remove selected items that only exist on the destination; only Secrets honours it
+    #[serde(default)]
+    pub delete_missing: bool,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ApplyResponse {
+    pub apply_id: String,
+    pub results: Vec<ApplyResult>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ApplyResult {
+    pub service: String,
+    pub applied_keys: Vec<String>,
+}
+
+// Every supported config of a project as fetched at one point in time, keyed by service
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConfigSnapshot {
+    pub id: String,
+    pub project_id: String,
+    pub name: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    pub configs: BTreeMap<String, Value>,
+}
+
+// A snapshot without its configs, for listing
+#[derive(Debug, Serialize, Clone)]
+pub struct SnapshotSummary {
+    pub id: String,
+    pub project_id: String,
+    pub name: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    pub services: Vec<String>,
+}
+
+impl SnapshotSummary {
+    pub fn of(snapshot: &ConfigSnapshot) -> Self {
+        SnapshotSummary {
+            id: snapshot.id.clone(),
+            project_id: snapshot.project_id.clone(),
+            name: snapshot.name.clone(),
+            created_at: snapshot.created_at,
+            services: snapshot.configs.keys().cloned().collect(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSnapshotRequest {
+    pub name: String,
+}
+
+// Defaults for previews, kept per session and used for the options a request leaves out
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Preferences {
+    // Services diffed when a request names none
+    #[serde(default)]
+    pub services: Vec<String>,
+    pub output: Option<String>,
+    pub format: Option<String>,
+    // Whether sensitive values are masked, which they are unless turned off
+    pub redact: Option<bool>,
+}
+
+// A preview saved under a name, so a recurring sync between two projects can be re-run
+// without entering its options again
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MigrationProfile {
+    pub name: String,
+    // The account, or for bearer and personal access tokens the token fingerprint, that saved
+    // it; profiles are only visible to their owner
+    #[serde(default)]
+    pub owner: String,
+    pub source_id: String,
+    pub dest_id: String,
+    pub services: Vec<String>,
+    // Fields left out of the diff on top of each service's defaults
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub updated_at: OffsetDateTime,
+}
+
+// Body of POST /profiles; saving under an existing name replaces that profile
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SaveProfileRequest {
+    pub name: String,
+    pub source_id: String,
+    pub dest_id: String,
+    pub services: Vec<String>,
+    #[serde(default)]
+    pub ignore: Vec<String>,
+}
+
+// What changed from one snapshot to another, or to the live project. Added entries exist
+// only in `to`, removed ones only in `from`.
+#[derive(Debug, Serialize, Clone)]
+pub struct SnapshotDiffResponse {
+    pub from: SnapshotSummary,
+    // None for the live project
+    pub to: Option<SnapshotSummary>,
+    pub configs: Vec<ProjectConfig>,
+    pub summary: PreviewSummary,
+}
+
+// A project's configs as exported to a file, with sensitive values masked. Function bodies
+// are base64-encoded bundles keyed by slug.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConfigBundle {
+    pub version: u32,
+    pub project_id: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub exported_at: OffsetDateTime,
+    pub configs: BTreeMap<String, Value>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub function_bodies: BTreeMap<String, String>,
+}
+
+// A bundle to restore onto a project. Without a confirmation token the import only previews;
+// with the token from that preview the selected keys are applied.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ImportRequest {
+    pub bundle: ConfigBundle,
+    #[serde(default)]
+    pub services: Vec<ServiceApply>,
+    pub confirmation_token: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportPreview {
+    pub configs: Vec<ProjectConfig>,
+    pub summary: PreviewSummary,
+    pub confirmation_token: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub confirmation_expires_at: OffsetDateTime,
+}
+
+// One apply, import or rollback as kept in the audit log
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuditEntry {
+    pub id: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub at: OffsetDateTime,
+    // Fingerprint of the token the change was made with; tokens themselves are never logged
+    pub actor: String,
+    pub action: AuditAction,
+    pub apply_id: String,
+    // Rollbacks have no source; imports name the project the bundle was exported from
+    pub source_id: Option<String>,
+    pub dest_id: String,
+    pub services: Vec<String>,
+    // Number of keys written across all services
+    pub changes: usize,
+    pub outcome: AuditOutcome,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Apply,
+    Import,
+    Rollback,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Succeeded,
+    Failed,
+}
+
+// Outcome of the latest scheduled drift check of one watch
+#[derive(Debug, Serialize, Clone)]
+pub struct DriftStatus {
+    pub watch: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub checked_at: OffsetDateTime,
+    pub drift: bool,
+    pub changes: usize,
+    // Services that drifted
+    pub services: Vec<String>,
+    // Set when the check could not run; drift is false then
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// A URL that drift and apply events are posted to
+#[derive(Debug, Serialize, Clone)]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    // Configured in NOTIFY_WEBHOOK_URLS rather than through the API
+    pub from_env: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookRequest {
+    pub url: String,
+}
+
+// Payload posted to webhooks, tagged with its event name
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Notification {
+    // A scheduled check found drift, or a different amount of it than last time
+    DriftDetected(DriftStatus),
+    ApplyFinished {
+        job_id: String,
+        apply_id: String,
+        source_id: String,
+        dest_id: String,
+        status: JobStatus,
+        error: Option<String>,
+    },
+}
+
+// Destination config captured before an apply, used to restore the applied keys on rollback
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApplySnapshot {
+    pub apply_id: String,
+    pub dest_id: String,
+    pub services: Vec<ServiceSnapshot>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+// How an apply snapshot is saved to storage: the snapshot itself sealed with
+// TOKEN_ENCRYPTION_KEY, since it holds the destination's secrets
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SealedApplySnapshot {
+    pub apply_id: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    pub sealed: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ServiceSnapshot {
+    pub service: String,
+    pub keys: Vec<String>,
+    pub config: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Interrupted,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MigrationJob {
+    pub id: String,
+    pub apply_id: String,
+    pub source_id: String,
+    pub dest_id: String,
+    pub status: JobStatus,
+    pub steps: Vec<JobStep>,
+    pub error: Option<String>,
+    // Applied keys that still differ after the apply; None until verification has run
+    pub residual_diffs: Option<Vec<ProjectConfig>>,
+    // Fingerprint of the token that started the job, for the audit log
+    #[serde(default)]
+    pub requested_by: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub updated_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JobStep {
+    pub service: String,
+    pub keys: Vec<String>,
+    #[serde(default)]
+    pub delete_missing: bool,
+    pub status: StepStatus,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct JobAccepted {
+    pub job_id: String,
+    pub apply_id: String,
+}
+
+// Progress events published while a job runs, streamed to clients over SSE
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JobEvent {
+    StepStarted { step: usize, service: String },
+    StepSucceeded { step: usize, service: String },
+    StepFailed { step: usize, service: String, error: Option<String> },
+    Done { status: JobStatus, error: Option<String> },
+}
+
+impl JobEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobEvent::StepStarted { .. } => "step_started",
+            JobEvent::StepSucceeded { .. } => "step_succeeded",
+            JobEvent::StepFailed { .. } => "step_failed",
+            JobEvent::Done { .. } => "done",
+        }
+    }
+}
+
+impl JobStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobStatus::Succeeded | JobStatus::Failed)
+    }
+
+    pub fn is_resumable(&self) -> bool {
+        matches!(self, JobStatus::Failed | JobStatus::Interrupted)
+    }
+}
+
+// A preview branch as listed by the Management API
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Branch {
+    pub id: String,
+    pub name: String,
+    // Project ref of the branch itself; preview and apply accept it like any project ID
+    pub project_ref: String,
+    pub parent_project_ref: String,
+    #[serde(default)]
+    pub is_default: bool,
+    pub git_branch: Option<String>,
+    pub status: Option<String>,
+}
+
+// A project the token can access, for picking the source and destination
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Project {
+    pub id: String,
+    pub name: String,
+    pub organization_id: String,
+    pub region: String,
+}
+
+// An organization the token can access with its projects, for scoping the project pickers
+#[derive(Debug, Serialize, Clone)]
+pub struct OrganizationProjects {
+    pub id: String,
+    pub name: String,
+    pub projects: Vec<Project>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MigrationPlan {
+    pub source_id: String,
+    pub dest_id: String,
+    pub steps: Vec<PlanStep>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PlanStep {
+    pub order: usize,
+    pub service: String,
+    pub keys: Vec<String>,
+    pub depends_on: Vec<String>,
+}
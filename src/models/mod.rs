@@ -1,4 +1,6 @@
+pub mod admin;
 pub mod app_config;
+pub mod health;
 pub mod oauth;
 pub mod migrate;
 
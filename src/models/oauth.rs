@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct OAuthSessionData {
@@ -11,3 +12,86 @@ pub struct CallbackParams {
     pub code: String,
     pub state: String,
 }
+
+// Organization the token was granted for; OAuth tokens are scoped to a single one
+#[derive(Debug, Deserialize, Clone)]
+pub struct Organization {
+    pub id: String,
+    pub name: String,
+}
+
+// A Supabase account signed in within this browser session
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StoredAccount {
+    pub id: String,
+    pub name: String,
+    pub access_token: String,
+    // Only kept when tokens are encrypted in the session store
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub expires_at: Option<OffsetDateTime>,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccountSummary {
+    pub id: String,
+    pub name: String,
+    pub active: bool,
+}
+
+// What the frontend needs to decide whether to show the login button. Token values are
+// never included.
+#[derive(Debug, Serialize)]
+pub struct MeResponse {
+    pub authenticated: bool,
+    // "oauth" or "personal_access_token"
+    pub auth_mode: Option<&'static str>,
+    pub account: Option<AccountSummary>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub expires_at: Option<OffsetDateTime>,
+    pub scopes: Vec<String>,
+    // Configured scopes the token was not granted; empty when the grant is unknown
+    pub missing_scopes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SwitchAccountRequest {
+    pub account_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CsrfTokenResponse {
+    pub csrf_token: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuthorizeResponse {
+    pub authorize_url: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeviceLoginResponse {
+    // Secret the client polls with; only it can collect the token
+    pub device_code: String,
+    pub login_url: String,
+    pub expires_in: u64,
+    pub interval: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeviceLoginStatus {
+    // "pending" or "complete"
+    pub status: &'static str,
+    pub access_token: Option<String>,
+    pub account_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginParams {
+    // Runs the OAuth flow even when the session already has a token, to add another account
+    #[serde(default)]
+    pub add_account: bool,
+}
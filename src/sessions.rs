@@ -0,0 +1,386 @@
+use crate::handlers::accounts::{ACCESS_TOKEN_KEY, ACCOUNTS_KEY};
+use crate::models::admin::SessionReport;
+use crate::models::app_config::SessionStoreConfig;
+use crate::models::oauth::StoredAccount;
+use crate::models::AppState;
+use crate::token_cipher::TokenCipher;
+
+use async_trait::async_trait;
+use axum::Router;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use time::format_description::well_known::Rfc3339;
+use time::{Duration, OffsetDateTime};
+use tower_sessions::session::{Id, Record};
+use tower_sessions::{session_store, ExpiredDeletion, Expiry, MemoryStore, SessionManagerLayer, SessionStore};
+use tower_sessions_redis_store::fred::prelude::{ClientLike, Config, Pool};
+use tower_sessions_redis_store::RedisStore;
+use tower_sessions_sqlx_store::{sqlx::PgPool, PostgresStore};
+
+const REDIS_POOL_SIZE: usize = 6;
+
+// The configured session store, kept for readiness checks
+pub type SessionProbe = Arc<dyn SessionStore>;
+
+// Removes expired sessions from the configured store on each maintenance run
+#[derive(Clone)]
+pub enum SessionSweeper {
+    // The memory store never forgets a session by itself, so the expired ones this instance
+    // tracked are deleted one by one
+    Memory(MemoryStore),
+    // Redis expires keys by itself
+    Redis,
+    Postgres(PostgresStore),
+}
+
+impl SessionSweeper {
+    // Returns how many of the sessions tracked by this instance had expired
+    pub async fn delete_expired(&self, stats: &SessionStats) -> session_store::Result<usize> {
+        let expired = stats.take_expired();
+        match self {
+            SessionSweeper::Memory(store) => {
+                for id in &expired {
+                    store.delete(id).await?;
+                }
+            }
+            SessionSweeper::Redis => {}
+            SessionSweeper::Postgres(store) => store.delete_expired().await?,
+        }
+        Ok(expired.len())
+    }
+}
+
+// Adds the session layer backed by the configured store. Secure cookies are only sent back
+// over HTTPS, so they are used when the server terminates TLS itself. With a cipher, tokens
+// are sealed before they reach the store.
+pub async fn with_sessions(
+    router: Router<AppState>,
+    store: &SessionStoreConfig,
+    stats: &SessionStats,
+    cipher: Option<TokenCipher>,
+    secure: bool,
+) -> Result<(Router<AppState>, SessionProbe, SessionSweeper), Box<dyn std::error::Error>> {
+    if cipher.is_none() && !matches!(store, SessionStoreConfig::Memory) {
+        return Err(format!("TOKEN_ENCRYPTION_KEY is required with the {} session store", store.name()).into());
+    }
+
+    Ok(match store {
+        SessionStoreConfig::Memory => {
+            let store = MemoryStore::default();
+            (
+                router.layer(session_layer(store.clone(), stats, cipher, secure)),
+                Arc::new(store.clone()),
+                SessionSweeper::Memory(store),
+            )
+        }
+        SessionStoreConfig::Redis { url } => {
+            let pool = Pool::new(Config::from_url(url)?, None, None, None, REDIS_POOL_SIZE)?;
+            pool.init().await?;
+            let store = RedisStore::new(pool);
+            (
+                router.layer(session_layer(store.clone(), stats, cipher, secure)),
+                Arc::new(store),
+                SessionSweeper::Redis,
+            )
+        }
+        SessionStoreConfig::Postgres { url } => {
+            let store = PostgresStore::new(PgPool::connect(url).await?);
+            store.migrate().await?;
+            (
+                router.layer(session_layer(store.clone(), stats, cipher, secure)),
+                Arc::new(store.clone()),
+                SessionSweeper::Postgres(store),
+            )
+        }
+    })
+}
+
+// Loads a session that does not exist, which only succeeds while the store is reachable
+pub async fn check_session_store(store: &dyn SessionStore) -> Result<(), String> {
+    store.load(&Id::default()).await.map(|_| ()).map_err(|e| e.to_string())
+}
+
+fn session_layer<S: SessionStore + Clone>(
+    store: S,
+    stats: &SessionStats,
+    cipher: Option<TokenCipher>,
+    secure: bool,
+) -> SessionManagerLayer<TrackedStore<S>> {
+    SessionManagerLayer::new(TrackedStore {
+        inner: store,
+        stats: stats.clone(),
+        cipher,
+    })
+        .with_secure(secure)
+        .with_same_site(tower_sessions::cookie::SameSite::Lax)
+        .with_expiry(Expiry::OnInactivity(Duration::hours(6)))
+}
+
+// What is known about the sessions this instance has created, saved or loaded since it
+// started. Token values are never kept, only whether there is one and when it expires.
+#[derive(Clone, Debug, Default)]
+pub struct SessionStats {
+    sessions: Arc<RwLock<HashMap<Id, SessionInfo>>>,
+}
+
+#[derive(Debug)]
+struct SessionInfo {
+    expiry_date: OffsetDateTime,
+    bytes: usize,
+    authenticated: bool,
+    token_expiries: Vec<OffsetDateTime>,
+}
+
+impl SessionStats {
+    fn record(&self, record: &Record) {
+        let accounts: Vec<StoredAccount> = record
+            .data
+            .get("supabase_accounts")
+            .and_then(|accounts| serde_json::from_value(accounts.clone()).ok())
+            .unwrap_or_default();
+        let info = SessionInfo {
+            expiry_date: record.expiry_date,
+            bytes: serde_json::to_vec(&record.data).map_or(0, |bytes| bytes.len()),
+            authenticated: record.data.contains_key("supabase_access_token"),
+            token_expiries: accounts.iter().filter_map(|account| account.expires_at).collect(),
+        };
+
+        self.sessions
+            .write()
+            .expect("session stats lock poisoned")
+            .insert(record.id, info);
+    }
+
+    fn forget(&self, id: &Id) {
+        self.sessions
+            .write()
+            .expect("session stats lock poisoned")
+            .remove(id);
+    }
+
+    // Stops tracking the expired sessions and returns their ids
+    fn take_expired(&self) -> Vec<Id> {
+        let now = OffsetDateTime::now_utc();
+        self.sessions
+            .write()
+            .expect("session stats lock poisoned")
+            .extract_if(|_, info| info.expiry_date <= now)
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    pub fn report(&self, store: &SessionStoreConfig) -> SessionReport {
+        let now = OffsetDateTime::now_utc();
+        let sessions = self.sessions.read().expect("session stats lock poisoned");
+        let active: Vec<&SessionInfo> = sessions.values().filter(|info| info.expiry_date > now).collect();
+
+        let mut token_expiries: Vec<OffsetDateTime> = active
+            .iter()
+            .flat_map(|info| info.token_expiries.iter().copied())
+            .collect();
+        token_expiries.sort();
+
+        SessionReport {
+            store: store.name(),
+            tracked_sessions: sessions.len(),
+            active_sessions: active.len(),
+            authenticated_sessions: active.iter().filter(|info| info.authenticated).count(),
+            approximate_bytes: active.iter().map(|info| info.bytes).sum(),
+            expired_tokens: token_expiries.iter().filter(|expiry| **expiry <= now).count(),
+            token_expiries: token_expiries
+                .iter()
+                .filter_map(|expiry| expiry.format(&Rfc3339).ok())
+                .collect(),
+        }
+    }
+}
+
+// Passes everything through to the real store, with tokens sealed when there is a cipher,
+// and keeps SessionStats up to date
+#[derive(Clone, Debug)]
+pub struct TrackedStore<S> {
+    inner: S,
+    stats: SessionStats,
+    cipher: Option<TokenCipher>,
+}
+
+impl<S> TrackedStore<S> {
+    fn sealed(&self, record: &Record) -> Record {
+        let mut record = record.clone();
+        if let Some(cipher) = &self.cipher {
+            map_tokens(&mut record, |token| Some(cipher.seal(token)));
+        }
+        record
+    }
+
+    // Tokens that do not open, e.g. after the key changed, are dropped, so their users log in
+    // again rather than every request failing
+    fn opened(&self, mut record: Record) -> Record {
+        if let Some(cipher) = &self.cipher {
+            map_tokens(&mut record, |token| {
+                cipher
+                    .open(token)
+                    .inspect_err(|e| tracing::warn!(error = %e, "dropping a session token"))
+                    .ok()
+            });
+        }
+        record
+    }
+}
+
+// Replaces the session's active token and every account's tokens with what f returns for them.
+// Where f returns None the token is removed, along with its account.
+fn map_tokens(record: &mut Record, f: impl Fn(&str) -> Option<String>) {
+    if let Some(Value::String(token)) = record.data.get_mut(ACCESS_TOKEN_KEY) {
+        match f(token) {
+            Some(mapped) => *token = mapped,
+            None => {
+                record.data.remove(ACCESS_TOKEN_KEY);
+            }
+        }
+    }
+
+    if let Some(Value::Array(accounts)) = record.data.get_mut(ACCOUNTS_KEY) {
+        accounts.retain_mut(|account| {
+            ["access_token", "refresh_token"].into_iter().all(|field| match account.get_mut(field) {
+                Some(Value::String(token)) => f(token).map(|mapped| *token = mapped).is_some(),
+                _ => true,
+            })
+        });
+    }
+}
+
+#[async_trait]
+impl<S: SessionStore + Clone> SessionStore for TrackedStore<S> {
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        let mut sealed = self.sealed(record);
+        self.inner.create(&mut sealed).await?;
+        // The store picks another id when the first one is taken
+        record.id = sealed.id;
+        self.stats.record(record);
+        Ok(())
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        self.inner.save(&self.sealed(record)).await?;
+        self.stats.record(record);
+        Ok(())
+    }
+
+    async fn load(&self, id: &Id) -> session_store::Result<Option<Record>> {
+        let record = self.inner.load(id).await?.map(|record| self.opened(record));
+        if let Some(record) = &record {
+            self.stats.record(record);
+        }
+        Ok(record)
+    }
+
+    async fn delete(&self, id: &Id) -> session_store::Result<()> {
+        self.inner.delete(id).await?;
+        self.stats.forget(id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_tracked_store_reports_sessions_without_tokens() {
+        let stats = SessionStats::default();
+        let store = TrackedStore {
+            inner: MemoryStore::default(),
+            stats: stats.clone(),
+            cipher: None,
+        };
+
+        let expires_at = OffsetDateTime::now_utc() + Duration::hours(1);
+        let mut record = Record {
+            id: Id::default(),
+            data: HashMap::from([
+                ("supabase_access_token".to_string(), serde_json::json!("token")),
+                (
+                    "supabase_accounts".to_string(),
+                    serde_json::json!([{
+                        "id": "org",
+                        "name": "org",
+                        "access_token": "token",
+                        "expires_at": expires_at.format(&Rfc3339).unwrap()
+                    }]),
+                ),
+            ]),
+            expiry_date: OffsetDateTime::now_utc() + Duration::hours(6),
+        };
+        store.create(&mut record).await.unwrap();
+
+        let report = stats.report(&SessionStoreConfig::Memory);
+        assert_eq!(report.active_sessions, 1);
+        assert_eq!(report.authenticated_sessions, 1);
+        assert_eq!(report.token_expiries.len(), 1);
+        assert_eq!(report.expired_tokens, 0);
+        assert!(!serde_json::to_string(&report).unwrap().contains("\"token\""));
+
+        store.delete(&record.id).await.unwrap();
+        assert_eq!(stats.report(&SessionStoreConfig::Memory).tracked_sessions, 0);
+    }
+
+    #[tokio::test]
+    async fn test_expired_sessions_swept_from_memory() {
+        let stats = SessionStats::default();
+        let inner = MemoryStore::default();
+        let store = TrackedStore {
+            inner: inner.clone(),
+            stats: stats.clone(),
+            cipher: None,
+        };
+        let mut records = [Duration::hours(-1), Duration::hours(1)].map(|expires_in| Record {
+            id: Id::default(),
+            data: HashMap::new(),
+            expiry_date: OffsetDateTime::now_utc() + expires_in,
+        });
+        for record in &mut records {
+            store.create(record).await.unwrap();
+        }
+
+        let swept = SessionSweeper::Memory(inner.clone()).delete_expired(&stats).await.unwrap();
+        assert_eq!(swept, 1);
+        assert_eq!(stats.report(&SessionStoreConfig::Memory).tracked_sessions, 1);
+        assert!(store.load(&records[1].id).await.unwrap().is_some());
+        assert_eq!(SessionSweeper::Memory(inner).delete_expired(&stats).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_tokens_sealed_in_store() {
+        let inner = MemoryStore::default();
+        let store = |key| TrackedStore {
+            inner: inner.clone(),
+            stats: SessionStats::default(),
+            cipher: Some(TokenCipher::new(&[key; 32])),
+        };
+
+        let mut record = Record {
+            id: Id::default(),
+            data: HashMap::from([
+                (ACCESS_TOKEN_KEY.to_string(), serde_json::json!("sbp_active")),
+                (
+                    ACCOUNTS_KEY.to_string(),
+                    serde_json::json!([{"id": "org", "name": "org", "access_token": "sbp_active", "refresh_token": "refresh"}]),
+                ),
+            ]),
+            expiry_date: OffsetDateTime::now_utc() + Duration::hours(6),
+        };
+        store(1).create(&mut record).await.unwrap();
+
+        let at_rest = serde_json::to_string(&inner.load(&record.id).await.unwrap().unwrap().data).unwrap();
+        assert!(!at_rest.contains("sbp_active"));
+        assert!(!at_rest.contains("\"refresh\""));
+        assert_eq!(store(1).load(&record.id).await.unwrap().unwrap().data, record.data);
+
+        // Under another key the tokens are dropped, along with the accounts holding them
+        let opened = store(2).load(&record.id).await.unwrap().unwrap();
+        assert!(!opened.data.contains_key(ACCESS_TOKEN_KEY));
+        assert_eq!(opened.data[ACCOUNTS_KEY], serde_json::json!([]));
+    }
+}
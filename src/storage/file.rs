@@ -0,0 +1,205 @@
+use super::{Collection, Storage};
+
+use async_trait::async_trait;
+use serde_json::Value;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+// Jobs, snapshots and profiles as one JSON file per record in their directories, the audit
+// log as JSON lines appended to one file. Rollback snapshots go next to the jobs they belong to.
+// A collection without a location is only kept in memory.
+// std::fs blocks, so every operation runs on the blocking thread pool.
+pub struct FileStorage {
+    pub job_state_dir: Option<PathBuf>,
+    pub snapshot_dir: Option<PathBuf>,
+    pub audit_log_path: Option<PathBuf>,
+    pub profile_dir: Option<PathBuf>,
+}
+
+#[async_trait]
+impl Storage for FileStorage {
+    async fn load(&self, collection: Collection) -> Result<Vec<Value>, String> {
+        let (location, label) = self.location(collection);
+        blocking(move || match collection {
+            Collection::Audit => load_lines(location.as_deref()),
+            _ => load_dir(location.as_deref(), label),
+        })
+        .await
+    }
+
+    // Audit entries are never saved twice, so appending them is enough
+    async fn save(&self, collection: Collection, id: &str, record: Value) -> Result<(), String> {
+        let (location, _) = self.location(collection);
+        let id = id.to_string();
+        blocking(move || match collection {
+            Collection::Audit => append_line(location.as_deref(), &record),
+            _ => save_file(location.as_deref(), &id, &record),
+        })
+        .await
+    }
+
+    async fn delete(&self, collection: Collection, id: &str) -> Result<(), String> {
+        if collection == Collection::Audit {
+            return Err("Audit entries cannot be deleted".to_string());
+        }
+        let Some(dir) = self.location(collection).0 else {
+            return Ok(());
+        };
+
+        let path = dir.join(format!("{}.json", id));
+        blocking(move || match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.to_string()),
+            _ => Ok(()),
+        })
+        .await
+    }
+}
+
+impl FileStorage {
+    // The collection's directory, or file for the audit log, and what its records are called
+    fn location(&self, collection: Collection) -> (Option<PathBuf>, &'static str) {
+        match collection {
+            Collection::Jobs => (self.job_state_dir.clone(), "job state"),
+            Collection::Snapshots => (self.snapshot_dir.clone(), "snapshot"),
+            Collection::Audit => (self.audit_log_path.clone(), "audit entry"),
+            Collection::Profiles => (self.profile_dir.clone(), "profile"),
+            Collection::ApplySnapshots => (
+                self.job_state_dir.as_ref().map(|dir| dir.join("apply_snapshots")),
+                "rollback snapshot",
+            ),
+        }
+    }
+}
+
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> Result<T, String> + Send + 'static) -> Result<T, String> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| format!("File storage task failed: {}", e))?
+}
+
+fn load_dir(dir: Option<&Path>, label: &str) -> Result<Vec<Value>, String> {
+    let Some(dir) = dir else {
+        return Ok(Vec::new());
+    };
+
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {} dir {}: {}", label, dir.display(), e))?;
+    let entries = fs::read_dir(dir).map_err(|e| format!("Failed to read {} dir {}: {}", label, dir.display(), e))?;
+
+    let mut records = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+
+        let record = fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| serde_json::from_slice::<Value>(&bytes).map_err(|e| e.to_string()));
+        match record {
+            Ok(record) => records.push((created_at(&record), path, record)),
+            Err(e) => tracing::warn!(path = %path.display(), error = %e, "skipping unreadable {}", label),
+        }
+    }
+
+    // Directory order is unspecified, so records are returned oldest first, by file name
+    // where they were created at the same time
+    records.sort_by(|(a_created, a_path, _), (b_created, b_path, _)| (a_created, a_path).cmp(&(b_created, b_path)));
+    Ok(records.into_iter().map(|(_, _, record)| record).collect())
+}
+
+fn created_at(record: &Value) -> Option<OffsetDateTime> {
+    record["created_at"]
+        .as_str()
+        .and_then(|created_at| OffsetDateTime::parse(created_at, &Rfc3339).ok())
+}
+
+fn save_file(dir: Option<&Path>, id: &str, record: &Value) -> Result<(), String> {
+    let Some(dir) = dir else {
+        return Ok(());
+    };
+
+    let path = dir.join(format!("{}.json", id));
+    let tmp_path = dir.join(format!("{}.json.tmp", id));
+    serde_json::to_vec_pretty(record)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| fs::write(&tmp_path, bytes).map_err(|e| e.to_string()))
+        .and_then(|_| fs::rename(&tmp_path, &path).map_err(|e| e.to_string()))
+}
+
+fn load_lines(path: Option<&Path>) -> Result<Vec<Value>, String> {
+    let Some(path) = path else {
+        return Ok(Vec::new());
+    };
+
+    if !path.exists() {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(|e| format!("Failed to create audit log dir {}: {}", dir.display(), e))?;
+        }
+        return Ok(Vec::new());
+    }
+
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read audit log {}: {}", path.display(), e))?;
+    let mut records = Vec::new();
+    for (index, line) in contents.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        match serde_json::from_str(line) {
+            Ok(record) => records.push(record),
+            Err(e) => tracing::warn!(path = %path.display(), line = index + 1, error = %e, "skipping unreadable audit entry"),
+        }
+    }
+    Ok(records)
+}
+
+fn append_line(path: Option<&Path>, record: &Value) -> Result<(), String> {
+    let Some(path) = path else {
+        return Ok(());
+    };
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{}", record))
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_records_loaded_oldest_first() {
+        let dir = std::env::temp_dir().join(format!("supabasemm-files-{}", uuid::Uuid::new_v4()));
+        let storage = FileStorage {
+            job_state_dir: None,
+            snapshot_dir: Some(dir.clone()),
+            audit_log_path: None,
+            profile_dir: None,
+        };
+        storage.load(Collection::Snapshots).await.unwrap();
+        for (id, created_at) in [
+            ("a", "2026-03-01T00:00:00.5Z"),
+            ("b", "2026-01-01T00:00:00Z"),
+            ("c", "2026-03-01T00:00:00Z"),
+        ] {
+            let record = json!({"id": id, "created_at": created_at});
+            storage.save(Collection::Snapshots, id, record).await.unwrap();
+        }
+        storage.delete(Collection::Snapshots, "missing").await.unwrap();
+
+        let ids: Vec<Value> = storage
+            .load(Collection::Snapshots)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|record| record["id"].clone())
+            .collect();
+        assert_eq!(ids, vec![json!("b"), json!("c"), json!("a")]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,169 @@
+mod file;
+mod postgres;
+mod sqlite;
+
+pub use file::FileStorage;
+pub use postgres::PostgresStorage;
+pub use sqlite::SqliteStorage;
+
+use crate::models::AppConfig;
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot};
+
+// Table the SQL backends keep every record in, one row per collection and ID
+const RECORDS_TABLE: &str = "supabasemm_records";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Collection {
+    Jobs,
+    Snapshots,
+    Audit,
+    Profiles,
+    ApplySnapshots,
+}
+
+impl Collection {
+    pub fn name(self) -> &'static str {
+        match self {
+            Collection::Jobs => "jobs",
+            Collection::Snapshots => "snapshots",
+            Collection::Audit => "audit",
+            Collection::Profiles => "profiles",
+            Collection::ApplySnapshots => "apply_snapshots",
+        }
+    }
+}
+
+// Where jobs, config and rollback snapshots, audit entries and profiles are kept so they outlive a restart
+#[async_trait]
+pub trait Storage: Send + Sync {
+    // Every record of the collection, in the order they were first saved
+    async fn load(&self, collection: Collection) -> Result<Vec<Value>, String>;
+
+    // Adds the record, or replaces the one saved earlier under the same ID
+    async fn save(&self, collection: Collection, id: &str, record: Value) -> Result<(), String>;
+
+    async fn delete(&self, collection: Collection, id: &str) -> Result<(), String>;
+}
+
+// Postgres or SQLite when DATABASE_URL is set, otherwise the configured files and directories
+pub async fn open_storage(config: &AppConfig) -> Result<Arc<dyn Storage>, String> {
+    let Some(url) = &config.database_url else {
+        return Ok(Arc::new(FileStorage {
+            job_state_dir: config.job_state_dir.clone(),
+            snapshot_dir: config.snapshot_dir.clone(),
+            audit_log_path: config.audit_log_path.clone(),
+            profile_dir: config.profile_dir.clone(),
+        }));
+    };
+
+    if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+        Ok(Arc::new(PostgresStorage::connect(url).await?))
+    } else if url.starts_with("sqlite:") {
+        Ok(Arc::new(SqliteStorage::connect(url).await?))
+    } else {
+        Err("DATABASE_URL must be a postgres:// or sqlite: URL".to_string())
+    }
+}
+
+// Rows of (id, JSON data); rows that are not JSON are skipped with a warning
+fn parse_rows(collection: Collection, rows: Vec<(String, String)>) -> Vec<Value> {
+    rows.into_iter()
+        .filter_map(|(id, data)| match serde_json::from_str(&data) {
+            Ok(record) => Some(record),
+            Err(e) => {
+                tracing::warn!(collection = collection.name(), id, error = %e, "skipping unreadable record");
+                None
+            }
+        })
+        .collect()
+}
+
+enum Write {
+    Save(String, Value),
+    Delete(String),
+    Flush(oneshot::Sender<()>),
+}
+
+// One collection of the storage. Saves are queued and written by a background task in the
+// order they were made, so callers never wait on the database and a later save of a record
+// cannot be overtaken by an earlier one.
+#[derive(Clone)]
+pub struct Records {
+    collection: Collection,
+    storage: Arc<dyn Storage>,
+    writes: mpsc::UnboundedSender<Write>,
+}
+
+impl Records {
+    pub fn new(storage: Arc<dyn Storage>, collection: Collection) -> Self {
+        let (writes, mut queue) = mpsc::unbounded_channel();
+        let writer = storage.clone();
+        tokio::spawn(async move {
+            while let Some(write) = queue.recv().await {
+                match write {
+                    Write::Save(id, record) => {
+                        if let Err(e) = writer.save(collection, &id, record).await {
+                            tracing::error!(collection = collection.name(), id, error = %e, "failed to persist record");
+                        }
+                    }
+                    Write::Delete(id) => {
+                        if let Err(e) = writer.delete(collection, &id).await {
+                            tracing::error!(collection = collection.name(), id, error = %e, "failed to delete record");
+                        }
+                    }
+                    Write::Flush(done) => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+        });
+
+        Self {
+            collection,
+            storage,
+            writes,
+        }
+    }
+
+    // Records that no longer decode, e.g. after a model change, are skipped with a warning
+    pub async fn load<T: DeserializeOwned>(&self) -> Result<Vec<T>, String> {
+        let records = self.storage.load(self.collection).await?;
+        Ok(records
+            .into_iter()
+            .filter_map(|record| match serde_json::from_value(record) {
+                Ok(record) => Some(record),
+                Err(e) => {
+                    tracing::warn!(collection = self.collection.name(), error = %e, "skipping unreadable record");
+                    None
+                }
+            })
+            .collect())
+    }
+
+    pub fn save(&self, id: &str, record: &impl Serialize) {
+        match serde_json::to_value(record) {
+            Ok(record) => {
+                let _ = self.writes.send(Write::Save(id.to_string(), record));
+            }
+            Err(e) => tracing::error!(collection = self.collection.name(), id, error = %e, "failed to encode record"),
+        }
+    }
+
+    pub fn delete(&self, id: &str) {
+        let _ = self.writes.send(Write::Delete(id.to_string()));
+    }
+
+    // Waits until every save queued so far is written
+    pub async fn flush(&self) {
+        let (done, written) = oneshot::channel();
+        if self.writes.send(Write::Flush(done)).is_ok() {
+            let _ = written.await;
+        }
+    }
+}
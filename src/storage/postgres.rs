@@ -0,0 +1,76 @@
+use super::{Collection, Storage, RECORDS_TABLE};
+
+use async_trait::async_trait;
+use serde_json::Value;
+use sqlx::postgres::PgPool;
+
+pub struct PostgresStorage {
+    pool: PgPool,
+}
+
+impl PostgresStorage {
+    // Creates the records table when it does not exist yet
+    pub async fn connect(url: &str) -> Result<Self, String> {
+        let pool = PgPool::connect(url)
+            .await
+            .map_err(|e| format!("Failed to connect to Postgres: {}", e))?;
+
+        sqlx::query(&format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                seq BIGSERIAL,
+                collection TEXT NOT NULL,
+                id TEXT NOT NULL,
+                data TEXT NOT NULL,
+                PRIMARY KEY (collection, id)
+            )",
+            RECORDS_TABLE
+        ))
+        .execute(&pool)
+        .await
+        .map_err(|e| format!("Failed to create {} table: {}", RECORDS_TABLE, e))?;
+
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait]
+impl Storage for PostgresStorage {
+    async fn load(&self, collection: Collection) -> Result<Vec<Value>, String> {
+        // An upsert keeps the row, and with it the sequence number of the first save
+        let rows: Vec<(String, String)> = sqlx::query_as(&format!(
+            "SELECT id, data FROM {} WHERE collection = $1 ORDER BY seq",
+            RECORDS_TABLE
+        ))
+        .bind(collection.name())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| format!("Failed to load {}: {}", collection.name(), e))?;
+
+        Ok(super::parse_rows(collection, rows))
+    }
+
+    async fn save(&self, collection: Collection, id: &str, record: Value) -> Result<(), String> {
+        sqlx::query(&format!(
+            "INSERT INTO {} (collection, id, data) VALUES ($1, $2, $3)
+             ON CONFLICT (collection, id) DO UPDATE SET data = EXCLUDED.data",
+            RECORDS_TABLE
+        ))
+        .bind(collection.name())
+        .bind(id)
+        .bind(record.to_string())
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+    }
+
+    async fn delete(&self, collection: Collection, id: &str) -> Result<(), String> {
+        sqlx::query(&format!("DELETE FROM {} WHERE collection = $1 AND id = $2", RECORDS_TABLE))
+            .bind(collection.name())
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
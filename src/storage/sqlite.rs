@@ -0,0 +1,106 @@
+use super::{Collection, Storage, RECORDS_TABLE};
+
+use async_trait::async_trait;
+use serde_json::Value;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+use std::str::FromStr;
+
+pub struct SqliteStorage {
+    pool: SqlitePool,
+}
+
+impl SqliteStorage {
+    // Creates the database file and the records table when they do not exist yet
+    pub async fn connect(url: &str) -> Result<Self, String> {
+        let options = SqliteConnectOptions::from_str(url)
+            .map_err(|e| format!("DATABASE_URL is not a valid SQLite URL: {}", e))?
+            .create_if_missing(true);
+        let pool = SqlitePool::connect_with(options)
+            .await
+            .map_err(|e| format!("Failed to open SQLite database: {}", e))?;
+
+        sqlx::query(&format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                collection TEXT NOT NULL,
+                id TEXT NOT NULL,
+                data TEXT NOT NULL,
+                PRIMARY KEY (collection, id)
+            )",
+            RECORDS_TABLE
+        ))
+        .execute(&pool)
+        .await
+        .map_err(|e| format!("Failed to create {} table: {}", RECORDS_TABLE, e))?;
+
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    async fn load(&self, collection: Collection) -> Result<Vec<Value>, String> {
+        // An upsert keeps the row, and with it the rowid of the first save
+        let rows: Vec<(String, String)> = sqlx::query_as(&format!(
+            "SELECT id, data FROM {} WHERE collection = ? ORDER BY rowid",
+            RECORDS_TABLE
+        ))
+        .bind(collection.name())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| format!("Failed to load {}: {}", collection.name(), e))?;
+
+        Ok(super::parse_rows(collection, rows))
+    }
+
+    async fn save(&self, collection: Collection, id: &str, record: Value) -> Result<(), String> {
+        sqlx::query(&format!(
+            "INSERT INTO {} (collection, id, data) VALUES (?, ?, ?)
+             ON CONFLICT (collection, id) DO UPDATE SET data = excluded.data",
+            RECORDS_TABLE
+        ))
+        .bind(collection.name())
+        .bind(id)
+        .bind(record.to_string())
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+    }
+
+    async fn delete(&self, collection: Collection, id: &str) -> Result<(), String> {
+        sqlx::query(&format!("DELETE FROM {} WHERE collection = ? AND id = ?", RECORDS_TABLE))
+            .bind(collection.name())
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_records_replaced_in_place() {
+        let path = std::env::temp_dir().join(format!("supabasemm-{}.db", uuid::Uuid::new_v4()));
+        let storage = SqliteStorage::connect(&format!("sqlite://{}", path.display())).await.unwrap();
+
+        storage.save(Collection::Jobs, "b", json!({"id": "b", "status": "queued"})).await.unwrap();
+        storage.save(Collection::Jobs, "a", json!({"id": "a", "status": "queued"})).await.unwrap();
+        storage.save(Collection::Jobs, "b", json!({"id": "b", "status": "succeeded"})).await.unwrap();
+        storage.save(Collection::Audit, "a", json!({"id": "a"})).await.unwrap();
+
+        let jobs = storage.load(Collection::Jobs).await.unwrap();
+        assert_eq!(jobs, vec![json!({"id": "b", "status": "succeeded"}), json!({"id": "a", "status": "queued"})]);
+        assert_eq!(storage.load(Collection::Audit).await.unwrap().len(), 1);
+        assert!(storage.load(Collection::Snapshots).await.unwrap().is_empty());
+
+        storage.delete(Collection::Jobs, "b").await.unwrap();
+        assert_eq!(storage.load(Collection::Jobs).await.unwrap(), vec![json!({"id": "a", "status": "queued"})]);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,100 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Stops calling the Management API after repeated upstream failures. While open, requests
+// are rejected straight away; once the cooldown has passed a single probe request is let
+// through, and its outcome either closes the breaker or opens it for another cooldown.
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Arc<Mutex<BreakerState>>,
+}
+
+#[derive(Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    probe_started: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    // A zero threshold turns the breaker off
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        CircuitBreaker {
+            threshold,
+            cooldown,
+            state: Default::default(),
+        }
+    }
+
+    // Ok when a request may go out, otherwise how long until the next probe is allowed
+    pub fn acquire(&self) -> Result<(), Duration> {
+        if self.threshold == 0 {
+            return Ok(());
+        }
+
+        let mut state = self.state.lock().expect("circuit breaker lock poisoned");
+        let Some(opened_at) = state.opened_at else {
+            return Ok(());
+        };
+
+        if opened_at.elapsed() < self.cooldown {
+            return Err(self.cooldown - opened_at.elapsed());
+        }
+
+        // A probe that never reported back, e.g. because its request was dropped, only
+        // blocks others for one cooldown
+        if let Some(probe_started) = state.probe_started
+            && probe_started.elapsed() < self.cooldown
+        {
+            return Err(self.cooldown - probe_started.elapsed());
+        }
+
+        state.probe_started = Some(Instant::now());
+        Ok(())
+    }
+
+    pub fn record(&self, upstream_failed: bool) {
+        if self.threshold == 0 {
+            return;
+        }
+
+        let mut state = self.state.lock().expect("circuit breaker lock poisoned");
+        if !upstream_failed {
+            *state = BreakerState::default();
+            return;
+        }
+
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+        state.probe_started = None;
+        if state.consecutive_failures >= self.threshold {
+            state.opened_at = Some(Instant::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opens_after_threshold_and_closes_after_probe() {
+        let breaker = CircuitBreaker::new(2, Duration::from_millis(20));
+
+        breaker.record(true);
+        assert!(breaker.acquire().is_ok());
+        breaker.record(true);
+        assert!(breaker.acquire().is_err());
+
+        std::thread::sleep(Duration::from_millis(25));
+        assert!(breaker.acquire().is_ok());
+        // Only the probe goes through while it is in flight
+        assert!(breaker.acquire().is_err());
+
+        breaker.record(false);
+        assert!(breaker.acquire().is_ok());
+        breaker.record(true);
+        assert!(breaker.acquire().is_ok());
+    }
+}
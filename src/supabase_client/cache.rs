@@ -0,0 +1,85 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+// Management API GET responses keyed by (token hash, path), so repeated previews within the
+// TTL skip the API. Tokens are hashed so the cache never holds them in plain text.
+#[derive(Clone)]
+pub struct ResponseCache {
+    ttl: Duration,
+    entries: Arc<RwLock<HashMap<(String, String), CachedResponse>>>,
+}
+
+struct CachedResponse {
+    body: String,
+    stored_at: Instant,
+}
+
+impl ResponseCache {
+    // A zero TTL turns caching off
+    pub fn new(ttl: Duration) -> Self {
+        ResponseCache {
+            ttl,
+            entries: Default::default(),
+        }
+    }
+
+    pub fn token_key(token: &str) -> String {
+        URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+    }
+
+    pub fn get(&self, token_key: &str, path: &str) -> Option<String> {
+        let entries = self.entries.read().expect("response cache lock poisoned");
+        entries
+            .get(&(token_key.to_string(), path.to_string()))
+            .filter(|cached| cached.stored_at.elapsed() < self.ttl)
+            .map(|cached| cached.body.clone())
+    }
+
+    pub fn insert(&self, token_key: &str, path: &str, body: String) {
+        if self.ttl.is_zero() {
+            return;
+        }
+
+        let mut entries = self.entries.write().expect("response cache lock poisoned");
+        entries.retain(|_, cached| cached.stored_at.elapsed() < self.ttl);
+        entries.insert(
+            (token_key.to_string(), path.to_string()),
+            CachedResponse {
+                body,
+                stored_at: Instant::now(),
+            },
+        );
+    }
+
+    // Drops everything cached for a token, e.g. after it was used to change a project
+    pub fn invalidate(&self, token_key: &str) {
+        self.entries
+            .write()
+            .expect("response cache lock poisoned")
+            .retain(|(key, _), _| key != token_key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_expires_and_invalidates_per_token() {
+        let cache = ResponseCache::new(Duration::from_secs(60));
+        cache.insert("a", "/projects/src/config/auth", "{}".to_string());
+        cache.insert("b", "/projects/src/config/auth", "[]".to_string());
+
+        assert_eq!(cache.get("a", "/projects/src/config/auth").as_deref(), Some("{}"));
+        cache.invalidate("a");
+        assert_eq!(cache.get("a", "/projects/src/config/auth"), None);
+        assert_eq!(cache.get("b", "/projects/src/config/auth").as_deref(), Some("[]"));
+
+        let disabled = ResponseCache::new(Duration::ZERO);
+        disabled.insert("a", "/projects/src/config/auth", "{}".to_string());
+        assert_eq!(disabled.get("a", "/projects/src/config/auth"), None);
+    }
+}
@@ -0,0 +1,61 @@
+use crate::error::AppError;
+use crate::supabase_client::ManagementApi;
+
+use reqwest::Method;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// A recorded call: method, path and JSON body
+pub type MockCall = (Method, String, Option<Value>);
+
+// Answers requests from canned responses and records every call it receives.
+// Unknown paths answer with an empty JSON object.
+#[derive(Default)]
+pub struct MockApi {
+    responses: HashMap<(Method, String), String>,
+    calls: Mutex<Vec<MockCall>>,
+}
+
+impl MockApi {
+    pub fn with_response(mut self, method: Method, path: &str, body: Value) -> Self {
+        self.responses.insert((method, path.to_string()), body.to_string());
+        self
+    }
+
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.calls.lock().expect("mock calls lock poisoned").clone()
+    }
+
+    fn respond(&self, method: Method, path: &str, body: Option<Value>) -> String {
+        self.calls
+            .lock()
+            .expect("mock calls lock poisoned")
+            .push((method.clone(), path.to_string(), body));
+        self.responses
+            .get(&(method, path.to_string()))
+            .cloned()
+            .unwrap_or_else(|| "{}".to_string())
+    }
+}
+
+impl ManagementApi for MockApi {
+    async fn request(&self, method: Method, path: &str, body: Option<&Value>) -> Result<String, AppError> {
+        Ok(self.respond(method, path, body.cloned()))
+    }
+
+    async fn download(&self, path: &str) -> Result<Vec<u8>, AppError> {
+        Ok(self.respond(Method::GET, path, None).into_bytes())
+    }
+
+    async fn upload(
+        &self,
+        method: Method,
+        path: &str,
+        _query: &[(&str, String)],
+        _content_type: &str,
+        _body: Vec<u8>,
+    ) -> Result<String, AppError> {
+        Ok(self.respond(method, path, None))
+    }
+}
@@ -0,0 +1,719 @@
+use crate::error::AppError;
+use crate::models::migrate::{AuthConfig, Branch, EdgeFunction, Project, Secret};
+use crate::models::oauth::Organization;
+
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+
+pub mod breaker;
+pub mod cache;
+#[cfg(test)]
+pub mod mock;
+pub mod redact;
+
+pub use breaker::CircuitBreaker;
+pub use cache::ResponseCache;
+
+use redact::{redact_body, LOG_TARGET};
+
+const API_BASE_URL: &str = "https://api.supabase.com/v1";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// Upper bound for any request on the shared client; Management API calls use their own,
+// usually shorter, per-request timeout
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
+
+// Page size requested from list endpoints, and a bound on how many pages are followed
+const PAGE_SIZE: usize = 100;
+const MAX_PAGES: usize = 100;
+
+// Longest a single rate-limited request waits before trying again
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+// Error returned by the Management API, parsed from its JSON error envelope
+#[derive(Debug, Clone, Serialize)]
+pub struct MgmtApiError {
+    pub code: Option<String>,
+    pub message: String,
+    pub status: u16,
+}
+
+#[derive(Deserialize)]
+struct ErrorEnvelope {
+    message: String,
+    code: Option<Value>,
+}
+
+impl MgmtApiError {
+    // Falls back to the raw body as the message when it is not an error envelope
+    pub fn from_body(status: u16, body: &str) -> Self {
+        match serde_json::from_str::<ErrorEnvelope>(body) {
+            Ok(envelope) => MgmtApiError {
+                code: envelope.code.map(|code| match code {
+                    Value::String(code) => code,
+                    other => other.to_string(),
+                }),
+                message: envelope.message,
+                status,
+            },
+            Err(_) => MgmtApiError {
+                code: None,
+                message: format!("HTTP request failed with status {}: {}", status, body),
+                status,
+            },
+        }
+    }
+}
+
+// The Management API calls the migrate handlers make. Handlers take any implementation, so
+// tests can swap in a mock instead of hitting api.supabase.com.
+pub trait ManagementApi: Send + Sync {
+    // Sends a request with an optional JSON body and returns the response body
+    fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&Value>,
+    ) -> impl Future<Output = Result<String, AppError>> + Send;
+
+    // Downloads a raw response body, e.g. an edge function bundle
+    fn download(&self, path: &str) -> impl Future<Output = Result<Vec<u8>, AppError>> + Send;
+
+    // Sends a raw body with its content type, passing the remaining parameters in the query string
+    fn upload(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, String)],
+        content_type: &str,
+        body: Vec<u8>,
+    ) -> impl Future<Output = Result<String, AppError>> + Send;
+
+    // Reads a project config from a project-relative path
+    fn get_config(
+        &self,
+        method: Method,
+        project_id: &str,
+        path: &str,
+        body: Option<&Value>,
+    ) -> impl Future<Output = Result<Value, AppError>> + Send {
+        async move {
+            let config_json = self
+                .request(method, &format!("/projects/{}{}", project_id, path), body)
+                .await?;
+            Ok(serde_json::from_str(&config_json)?)
+        }
+    }
+
+    // Reads a project-relative list, following page parameters until a short page comes back,
+    // and merges the pages into one array. Responses that are not arrays are returned as is.
+    fn get_all_pages(&self, project_id: &str, path: &str) -> impl Future<Output = Result<Value, AppError>> + Send {
+        async move {
+            let separator = if path.contains('?') { '&' } else { '?' };
+            let mut items: Vec<Value> = Vec::new();
+            let mut previous_page: Option<Vec<Value>> = None;
+
+            for page in 1..=MAX_PAGES {
+                let page_path = format!(
+                    "/projects/{}{}{}page={}&limit={}",
+                    project_id, path, separator, page, PAGE_SIZE
+                );
+                let page_items = match serde_json::from_str(&self.request(Method::GET, &page_path, None).await?)? {
+                    Value::Array(page_items) => page_items,
+                    other if page == 1 => return Ok(other),
+                    _ => break,
+                };
+
+                // An endpoint that ignores the page parameters keeps returning the same list
+                if previous_page.as_ref() == Some(&page_items) {
+                    break;
+                }
+                let is_last = page_items.len() < PAGE_SIZE;
+                items.extend(page_items.iter().cloned());
+                if is_last {
+                    break;
+                }
+                previous_page = Some(page_items);
+            }
+
+            Ok(Value::Array(items))
+        }
+    }
+
+    // Writes a partial config to a project-relative path
+    fn update_config(
+        &self,
+        method: Method,
+        project_id: &str,
+        path: &str,
+        patch: &Value,
+    ) -> impl Future<Output = Result<(), AppError>> + Send {
+        async move {
+            self.request(method, &format!("/projects/{}{}", project_id, path), Some(patch))
+                .await?;
+            Ok(())
+        }
+    }
+
+    fn get_auth_config(&self, project_id: &str) -> impl Future<Output = Result<AuthConfig, AppError>> + Send {
+        async move {
+            let config = self.get_config(Method::GET, project_id, "/config/auth", None).await?;
+            Ok(serde_json::from_value(config)?)
+        }
+    }
+
+    fn list_functions(&self, project_id: &str) -> impl Future<Output = Result<Vec<EdgeFunction>, AppError>> + Send {
+        async move { Ok(serde_json::from_value(self.get_all_pages(project_id, "/functions").await?)?) }
+    }
+
+    fn list_secrets(&self, project_id: &str) -> impl Future<Output = Result<Vec<Secret>, AppError>> + Send {
+        async move { Ok(serde_json::from_value(self.get_all_pages(project_id, "/secrets").await?)?) }
+    }
+
+    fn list_branches(&self, project_id: &str) -> impl Future<Output = Result<Vec<Branch>, AppError>> + Send {
+        async move {
+            let branches_json = self
+                .request(Method::GET, &format!("/projects/{}/branches", project_id), None)
+                .await?;
+            Ok(serde_json::from_str(&branches_json)?)
+        }
+    }
+
+    fn list_projects(&self) -> impl Future<Output = Result<Vec<Project>, AppError>> + Send {
+        async move {
+            let projects_json = self.request(Method::GET, "/projects", None).await?;
+            Ok(serde_json::from_str(&projects_json)?)
+        }
+    }
+
+    fn list_organizations(&self) -> impl Future<Output = Result<Vec<Organization>, AppError>> + Send {
+        async move {
+            let organizations_json = self.request(Method::GET, "/organizations", None).await?;
+            Ok(serde_json::from_str(&organizations_json)?)
+        }
+    }
+
+    fn run_query(&self, project_id: &str, query: &str) -> impl Future<Output = Result<Value, AppError>> + Send {
+        async move {
+            let rows_json = self
+                .request(
+                    Method::POST,
+                    &format!("/projects/{}/database/query", project_id),
+                    Some(&json!({ "query": query })),
+                )
+                .await?;
+            Ok(serde_json::from_str(&rows_json)?)
+        }
+    }
+
+    // Creates or updates secrets given as [{name, value}]
+    fn upsert_secrets(&self, project_id: &str, secrets: &[Value]) -> impl Future<Output = Result<(), AppError>> + Send {
+        async move {
+            self.request(
+                Method::POST,
+                &format!("/projects/{}/secrets", project_id),
+                Some(&json!(secrets)),
+            )
+            .await?;
+            Ok(())
+        }
+    }
+
+    fn delete_secrets(&self, project_id: &str, names: &[String]) -> impl Future<Output = Result<(), AppError>> + Send {
+        async move {
+            self.request(
+                Method::DELETE,
+                &format!("/projects/{}/secrets", project_id),
+                Some(&json!(names)),
+            )
+            .await?;
+            Ok(())
+        }
+    }
+
+    fn get_function_body(&self, project_id: &str, slug: &str) -> impl Future<Output = Result<Vec<u8>, AppError>> + Send {
+        async move {
+            self.download(&format!("/projects/{}/functions/{}/body", project_id, slug))
+                .await
+        }
+    }
+
+    // Creates the function, or updates it when it already exists on the project
+    fn deploy_function(
+        &self,
+        project_id: &str,
+        slug: &str,
+        exists: bool,
+        settings: &[(&str, String)],
+        bundle: Vec<u8>,
+    ) -> impl Future<Output = Result<(), AppError>> + Send {
+        async move {
+            let (method, path) = if exists {
+                (Method::PATCH, format!("/projects/{}/functions/{}", project_id, slug))
+            } else {
+                (Method::POST, format!("/projects/{}/functions", project_id))
+            };
+            self.upload(method, &path, settings, "application/vnd.denoland.eszip", bundle)
+                .await?;
+            Ok(())
+        }
+    }
+
+    fn delete_function(&self, project_id: &str, slug: &str) -> impl Future<Output = Result<(), AppError>> + Send {
+        async move {
+            self.request(
+                Method::DELETE,
+                &format!("/projects/{}/functions/{}", project_id, slug),
+                None,
+            )
+            .await?;
+            Ok(())
+        }
+    }
+}
+
+// How failed requests are retried: connection errors, and for reads timeouts and 5xx
+// responses, are tried again with exponential backoff, 429s after the server's Retry-After
+// when it sends one
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(200),
+        }
+    }
+}
+
+impl RetryPolicy {
+    // Doubles per attempt, plus up to half again as jitter so parallel retries spread out
+    fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self.base_delay.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        let jitter_ms = rand::random_range(0..=backoff.as_millis() as u64 / 2);
+        backoff + Duration::from_millis(jitter_ms)
+    }
+}
+
+// The HTTP client shared by every outgoing request, so connections and TLS sessions are reused
+pub fn build_http_client() -> Result<reqwest::Client, reqwest::Error> {
+    reqwest::Client::builder()
+        .user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+}
+
+// Whether the Management API answers at all. Any HTTP response counts, since the request
+// carries no token and is expected to be turned away.
+pub async fn check_reachable(http: &reqwest::Client, timeout: Duration) -> Result<(), String> {
+    http.get(format!("{}/projects", API_BASE_URL))
+        .timeout(timeout)
+        .send()
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+// Calls the Management API with a user's OAuth access token
+#[derive(Clone)]
+pub struct SupabaseManagementClient {
+    http: reqwest::Client,
+    token: String,
+    retry: RetryPolicy,
+    timeout: Duration,
+    cache: Option<(ResponseCache, String)>,
+    read_cache: bool,
+    breaker: Option<CircuitBreaker>,
+}
+
+impl SupabaseManagementClient {
+    pub fn new(http: reqwest::Client, token: String) -> Self {
+        SupabaseManagementClient {
+            http,
+            token,
+            retry: RetryPolicy::default(),
+            timeout: DEFAULT_TIMEOUT,
+            cache: None,
+            read_cache: false,
+            breaker: None,
+        }
+    }
+
+    // Applies to each attempt separately, so retries get the full timeout again
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    // Fresh GET responses are stored in the cache and writes invalidate the token's entries.
+    // Cached responses are only served once `read_cache` is turned on.
+    pub fn with_cache(mut self, cache: ResponseCache) -> Self {
+        let token_key = ResponseCache::token_key(&self.token);
+        self.cache = Some((cache, token_key));
+        self
+    }
+
+    pub fn read_cache(mut self, read_cache: bool) -> Self {
+        self.read_cache = read_cache;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    // Shared between clients so every caller backs off while the API is failing
+    pub fn with_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.breaker = Some(breaker);
+        self
+    }
+
+    fn builder(&self, method: Method, path: &str) -> reqwest::RequestBuilder {
+        use reqwest::header::AUTHORIZATION;
+
+        self.http
+            .request(method, format!("{}{}", API_BASE_URL, path))
+            .timeout(self.timeout)
+            .header(AUTHORIZATION, format!("Bearer {}", self.token))
+    }
+}
+
+impl ManagementApi for SupabaseManagementClient {
+    async fn request(&self, method: Method, path: &str, body: Option<&Value>) -> Result<String, AppError> {
+        use reqwest::header::ACCEPT;
+
+        let is_read = method == Method::GET;
+        if is_read
+            && self.read_cache
+            && let Some((cache, token_key)) = &self.cache
+            && let Some(cached) = cache.get(token_key, path)
+        {
+            tracing::debug!(target: LOG_TARGET, %method, path, "served from cache");
+            return Ok(cached);
+        }
+
+        tracing::debug!(
+            target: LOG_TARGET,
+            %method,
+            path,
+            body = %body.map_or_else(String::new, |body| redact_body(&body.to_string())),
+            "request"
+        );
+
+        let mut request = self.builder(method, path).header(ACCEPT, "application/json");
+
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+
+        let response_text = self
+            .send(request)
+            .await?
+            .text()
+            .await
+            .map_err(|e| AppError::ApiError(format!("Error reading response body as text: {:?}", e)))?;
+
+        tracing::debug!(target: LOG_TARGET, path, body = %redact_body(&response_text), "response");
+
+        if let Some((cache, token_key)) = &self.cache {
+            if is_read {
+                cache.insert(token_key, path, response_text.clone());
+            } else {
+                cache.invalidate(token_key);
+            }
+        }
+
+        Ok(response_text)
+    }
+
+    async fn download(&self, path: &str) -> Result<Vec<u8>, AppError> {
+        tracing::debug!(target: LOG_TARGET, method = %Method::GET, path, "download");
+        let bytes = self.send(self.builder(Method::GET, path))
+            .await?
+            .bytes()
+            .await
+            .map_err(|e| AppError::ApiError(format!("Error reading response body: {:?}", e)))?;
+        Ok(bytes.to_vec())
+    }
+
+    async fn upload(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, String)],
+        content_type: &str,
+        body: Vec<u8>,
+    ) -> Result<String, AppError> {
+        use reqwest::header::{ACCEPT, CONTENT_TYPE};
+
+        tracing::debug!(target: LOG_TARGET, %method, path, content_type, bytes = body.len(), "upload");
+
+        let request = self
+            .builder(method, path)
+            .header(ACCEPT, "application/json")
+            .header(CONTENT_TYPE, content_type)
+            .query(query)
+            .body(body);
+
+        let response = self.send(request).await;
+        if let Some((cache, token_key)) = &self.cache {
+            cache.invalidate(token_key);
+        }
+
+        response?
+            .text()
+            .await
+            .map_err(|e| AppError::ApiError(format!("Error reading response body as text: {:?}", e)))
+    }
+}
+
+impl SupabaseManagementClient {
+    // Sends the request and logs its outcome, one event per upstream call
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, AppError> {
+        let (method, path) = request
+            .try_clone()
+            .and_then(|request| request.build().ok())
+            .map(|request| (request.method().clone(), request.url().path().to_string()))
+            .unwrap_or((Method::GET, String::new()));
+        // A span per call, so exported traces show which upstream requests a slow preview waited on
+        let span = tracing::info_span!(target: LOG_TARGET, "upstream_call", %method, path, status = tracing::field::Empty);
+        let started = Instant::now();
+
+        let result = self.send_through_breaker(request).instrument(span.clone()).await;
+
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        span.in_scope(|| match &result {
+            Ok(response) => {
+                let status = response.status().as_u16();
+                span.record("status", status);
+                tracing::info!(target: LOG_TARGET, %method, path, status, elapsed_ms, "upstream call")
+            }
+            Err(e) => tracing::warn!(target: LOG_TARGET, %method, path, elapsed_ms, error = %e, "upstream call failed"),
+        });
+        result
+    }
+
+    async fn send_through_breaker(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, AppError> {
+        let Some(breaker) = &self.breaker else {
+            return self.send_with_retry(request).await;
+        };
+
+        breaker.acquire().map_err(|wait| {
+            AppError::Unavailable(
+                "Supabase Management API is failing, requests are paused".to_string(),
+                wait.as_secs() + u64::from(wait.subsec_nanos() > 0),
+            )
+        })?;
+
+        let result = self.send_with_retry(request).await;
+        breaker.record(upstream_failed(&result));
+        result
+    }
+
+    async fn send_with_retry(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, AppError> {
+        // A write that timed out or failed upstream may still have landed, so repeating it could
+        // deploy or delete twice. Only reads are retried then; writes only when no connection
+        // was made.
+        let idempotent = request
+            .try_clone()
+            .and_then(|request| request.build().ok())
+            .is_some_and(|request| matches!(*request.method(), Method::GET | Method::HEAD));
+        let mut attempt = 1;
+
+        loop {
+            // Bodies are always buffered, so this only fails for streams, which are never retried
+            let Some(current) = request.try_clone() else {
+                return check_response(request.send().await).await;
+            };
+
+            let outcome = current.send().await;
+
+            if let Ok(response) = &outcome
+                && response.status() == StatusCode::TOO_MANY_REQUESTS
+            {
+                if attempt >= self.retry.max_attempts {
+                    return Err(AppError::RateLimited(format!(
+                        "Rate limited by the Supabase Management API, retried {} times",
+                        attempt - 1
+                    )));
+                }
+                let wait = retry_after(response)
+                    .unwrap_or_else(|| self.retry.delay(attempt))
+                    .min(MAX_RETRY_AFTER);
+                tracing::debug!(target: LOG_TARGET, attempt, wait_ms = wait.as_millis() as u64, "rate limited");
+                tokio::time::sleep(wait).await;
+                attempt += 1;
+                continue;
+            }
+
+            let transient = match &outcome {
+                Ok(response) => idempotent && response.status().is_server_error(),
+                Err(e) => e.is_connect() || (idempotent && e.is_timeout()),
+            };
+
+            if !transient || attempt >= self.retry.max_attempts {
+                return check_response(outcome).await;
+            }
+
+            tracing::debug!(target: LOG_TARGET, attempt, "transient failure, retrying");
+            tokio::time::sleep(self.retry.delay(attempt)).await;
+            attempt += 1;
+        }
+    }
+}
+
+// Errors from the API itself count against the breaker; client errors and rate limits do not
+fn upstream_failed(result: &Result<reqwest::Response, AppError>) -> bool {
+    match result {
+        Err(AppError::Timeout(_)) | Err(AppError::ApiError(_)) => true,
+        Err(AppError::Management(error)) => error.status >= 500,
+        _ => false,
+    }
+}
+
+// Retry-After in seconds; the HTTP-date form falls back to the backoff delay
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+async fn check_response(
+    outcome: Result<reqwest::Response, reqwest::Error>,
+) -> Result<reqwest::Response, AppError> {
+    let api_response = outcome?;
+
+    if api_response.status().is_success() {
+        Ok(api_response)
+    } else {
+        let status_code = api_response.status().as_u16();
+        let error_text = api_response
+            .text()
+            .await
+            .unwrap_or_else(|e| format!("Error reading response body: {}", e));
+        tracing::debug!(
+            target: LOG_TARGET,
+            status = status_code,
+            body = %redact_body(&error_text),
+            "error response"
+        );
+        Err(AppError::Management(MgmtApiError::from_body(status_code, &error_text)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::supabase_client::mock::MockApi;
+
+    #[tokio::test]
+    async fn test_get_all_pages_merges_until_short_page() {
+        let first_page: Vec<Value> = (0..PAGE_SIZE).map(|i| json!({"slug": format!("fn-{}", i)})).collect();
+        let api = MockApi::default()
+            .with_response(Method::GET, "/projects/src/functions?page=1&limit=100", json!(first_page))
+            .with_response(Method::GET, "/projects/src/functions?page=2&limit=100", json!([{"slug": "last"}]));
+
+        let functions = api.get_all_pages("src", "/functions").await.unwrap();
+
+        assert_eq!(functions.as_array().unwrap().len(), PAGE_SIZE + 1);
+        assert_eq!(api.calls().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_typed_reads_parse_their_models() {
+        let api = MockApi::default()
+            .with_response(Method::GET, "/projects/src/config/auth", json!({"site_url": "https://a.dev", "jwt_exp": 3600}))
+            .with_response(
+                Method::GET,
+                "/projects/src/functions?page=1&limit=100",
+                json!([{"slug": "hello", "name": "Hello", "verify_jwt": true, "version": 3}]),
+            )
+            .with_response(Method::GET, "/projects/src/secrets?page=1&limit=100", json!([{"name": "API_KEY", "value": "x"}]));
+
+        let auth = api.get_auth_config("src").await.unwrap();
+        assert_eq!(auth.site_url.as_deref(), Some("https://a.dev"));
+        assert_eq!(auth.jwt_exp, Some(3600));
+
+        let functions = api.list_functions("src").await.unwrap();
+        assert_eq!(functions[0].slug, "hello");
+        assert_eq!(functions[0].verify_jwt, Some(true));
+
+        let secrets = api.list_secrets("src").await.unwrap();
+        assert_eq!(secrets[0].name, "API_KEY");
+
+        // Payloads that do not fit the model are errors, not empty configs
+        let api = MockApi::default().with_response(Method::GET, "/projects/src/secrets?page=1&limit=100", json!([{"value": "x"}]));
+        assert!(api.list_secrets("src").await.is_err());
+    }
+
+    #[test]
+    fn test_error_envelope_is_parsed() {
+        let error = MgmtApiError::from_body(404, r#"{"message": "Project not found", "code": "not_found"}"#);
+        assert_eq!(error.code.as_deref(), Some("not_found"));
+        assert_eq!(error.message, "Project not found");
+        assert_eq!(error.status, 404);
+
+        let error = MgmtApiError::from_body(502, "Bad Gateway");
+        assert_eq!(error.code, None);
+        assert_eq!(error.message, "HTTP request failed with status 502: Bad Gateway");
+    }
+
+    #[tokio::test]
+    async fn test_only_reads_are_retried_on_server_errors() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counted = hits.clone();
+        let app = axum::Router::new().fallback(move || {
+            counted.fetch_add(1, Ordering::SeqCst);
+            async { axum::http::StatusCode::SERVICE_UNAVAILABLE }
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/secrets", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let client = SupabaseManagementClient::new(reqwest::Client::new(), "token".to_string()).with_retry(RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+        });
+
+        assert!(client.send_with_retry(client.http.post(&url).json(&json!([]))).await.is_err());
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        assert!(client.send_with_retry(client.http.get(&url)).await.is_err());
+        assert_eq!(hits.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_retry_delay_backs_off_with_bounded_jitter() {
+        let retry = RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(100),
+        };
+
+        for (attempt, backoff_ms) in [(1, 100), (2, 200), (3, 400)] {
+            let delay = retry.delay(attempt);
+            assert!(delay >= Duration::from_millis(backoff_ms));
+            assert!(delay <= Duration::from_millis(backoff_ms * 3 / 2));
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use serde_json::Value;
+
+// Target of the Management API traffic log, enabled with MGMT_API_DEBUG_LOG or RUST_LOG
+pub const LOG_TARGET: &str = "management_api";
+
+// Keys whose string values are masked in logs: secret values, SMTP passwords, provider
+// client secrets, SMS credentials and tokens of any kind
+const SENSITIVE_KEYS: [&str; 8] = [
+    "secret",
+    "pass",
+    "token",
+    "api_key",
+    "access_key",
+    "authorization",
+    "value",
+    "jwt",
+];
+
+pub const MASK: &str = "***";
+
+// Body as it may be logged. Anything that is not JSON is reduced to its size.
+pub fn redact_body(body: &str) -> String {
+    match serde_json::from_str::<Value>(body) {
+        Ok(mut value) => {
+            redact_value(&mut value);
+            value.to_string()
+        }
+        Err(_) => format!("<{} bytes>", body.len()),
+    }
+}
+
+pub fn redact_value(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, field) in map.iter_mut() {
+                let key = key.to_lowercase();
+                if SENSITIVE_KEYS.iter().any(|sensitive| key.contains(sensitive)) {
+                    mask(field);
+                } else {
+                    redact_value(field);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_value),
+        Value::String(text) => *text = redact_bearer(text),
+        _ => {}
+    }
+}
+
+fn mask(value: &mut Value) {
+    match value {
+        Value::String(text) if !text.is_empty() => *text = MASK.to_string(),
+        Value::Object(_) | Value::Array(_) => redact_value(value),
+        _ => {}
+    }
+}
+
+// Bearer tokens can also appear inside strings, e.g. webhook headers in SQL
+fn redact_bearer(text: &str) -> String {
+    let mut redacted = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("Bearer ") {
+        let (before, after) = rest.split_at(start + "Bearer ".len());
+        redacted.push_str(before);
+        redacted.push_str(MASK);
+        let end = after
+            .find(|c: char| c.is_whitespace() || c == '"' || c == '\'')
+            .unwrap_or(after.len());
+        rest = &after[end..];
+    }
+
+    redacted.push_str(rest);
+    redacted
+}
+
+// Fields whose values are masked in diff output. Narrower than the log list, because diffs
+// are read by people and flags like refresh_token_rotation_enabled must stay visible.
+pub fn is_sensitive_field(field: &str) -> bool {
+    let field = field.to_lowercase();
+    field == "value"
+        || field.contains("password")
+        || ["secret", "secrets", "_pass", "_token", "api_key"]
+            .iter()
+            .any(|suffix| field.ends_with(suffix))
+}
+
+// A formatted diff value as it may be shown. JSON values are masked field by field; plain
+// values of a sensitive field are masked unless they are a flag or a number, so settings like
+// password_min_length stay readable.
+pub fn redact_diff_value(field: &str, value: &str) -> String {
+    match serde_json::from_str::<Value>(value) {
+        Ok(Value::Null | Value::Bool(_) | Value::Number(_)) => value.to_string(),
+        Ok(mut json @ (Value::Object(_) | Value::Array(_))) => {
+            mask_sensitive_fields(&mut json);
+            json.to_string()
+        }
+        _ if is_sensitive_field(field) && !value.is_empty() => MASK.to_string(),
+        _ => value.to_string(),
+    }
+}
+
+pub fn mask_sensitive_fields(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, field) in map.iter_mut() {
+                match field {
+                    Value::String(text) if is_sensitive_field(key) && !text.is_empty() => *text = MASK.to_string(),
+                    _ => mask_sensitive_fields(field),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(mask_sensitive_fields),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_redact_body_masks_credentials() {
+        let body = json!({
+            "site_url": "https://example.com",
+            "smtp_pass": "hunter2",
+            "external_google_secret": "google-secret",
+            "sms_twilio_auth_token": "twilio",
+            "refresh_token_rotation_enabled": true,
+            "secrets": [{"name": "API_KEY", "value": "abc"}],
+            "query": "select '{\"Authorization\": \"Bearer eyJhbGc\"}'"
+        });
+
+        let redacted: Value = serde_json::from_str(&redact_body(&body.to_string())).unwrap();
+        assert_eq!(
+            redacted,
+            json!({
+                "site_url": "https://example.com",
+                "smtp_pass": "***",
+                "external_google_secret": "***",
+                "sms_twilio_auth_token": "***",
+                "refresh_token_rotation_enabled": true,
+                "secrets": [{"name": "API_KEY", "value": "***"}],
+                "query": "select '{\"Authorization\": \"Bearer ***\"}'"
+            })
+        );
+        assert_eq!(redact_body("not json"), "<8 bytes>");
+    }
+
+    #[test]
+    fn test_redact_diff_value_keeps_flags_readable() {
+        assert_eq!(redact_diff_value("smtp_pass", "hunter2"), "***");
+        assert_eq!(redact_diff_value("external_github_secret", "null"), "null");
+        assert_eq!(redact_diff_value("password_min_length", "8"), "8");
+        assert_eq!(redact_diff_value("refresh_token_rotation_enabled", "true"), "true");
+        assert_eq!(redact_diff_value("site_url", "https://example.com"), "https://example.com");
+        assert_eq!(
+            redact_diff_value("id:API_KEY", r#"{"name":"API_KEY","value":"abc"}"#),
+            r#"{"name":"API_KEY","value":"***"}"#
+        );
+    }
+}
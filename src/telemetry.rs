@@ -0,0 +1,127 @@
+use crate::handlers::csrf::CSRF_HEADER;
+use crate::handlers::panic::PANIC_LOG_TARGET;
+use crate::models::health::BuildInfo;
+use crate::models::AppConfig;
+
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+use sentry::integrations::tracing::{default_event_filter, EventFilter};
+use sentry::protocol::Event;
+use std::sync::Arc;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+// Installs the log output and, with OTEL_EXPORTER_OTLP_ENDPOINT set, span export to a collector.
+// The returned provider has to be shut down on exit so the last batch of spans is sent.
+pub fn init_tracing(config: &AppConfig) -> Result<Option<SdkTracerProvider>, String> {
+    // RUST_LOG takes precedence over the debug flag
+    let default_filter = if config.api_debug_log { "info,management_api=debug" } else { "info" };
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_filter));
+
+    let fmt_layer = if config.log_json {
+        tracing_subscriber::fmt::layer().json().with_current_span(true).boxed()
+    } else {
+        tracing_subscriber::fmt::layer().boxed()
+    };
+
+    let provider = config
+        .otlp_endpoint
+        .as_deref()
+        .map(|endpoint| tracer_provider(endpoint, &config.otel_service_name))
+        .transpose()?;
+    let otel_layer = provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer().with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+    });
+    // Error events become Sentry issues, lower levels the breadcrumbs leading up to them
+    let sentry_layer = config.sentry_dsn.as_ref().map(|_| {
+        sentry::integrations::tracing::layer().event_filter(|metadata| match metadata.target() {
+            // Already reported by Sentry's panic integration
+            PANIC_LOG_TARGET => EventFilter::Ignore,
+            _ => default_event_filter(metadata),
+        })
+    });
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt_layer)
+        .with(otel_layer)
+        .with(sentry_layer)
+        .init();
+
+    if let Some(endpoint) = &config.otlp_endpoint {
+        tracing::info!(endpoint, "exporting spans over OTLP");
+    }
+    Ok(provider)
+}
+
+fn tracer_provider(endpoint: &str, service_name: &str) -> Result<SdkTracerProvider, String> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint))
+        .build()
+        .map_err(|e| format!("Failed to create OTLP exporter for {}: {}", endpoint, e))?;
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(service_name.to_string()).build())
+        .build())
+}
+
+// Reports error events and panics to Sentry when SENTRY_DSN is set. Reporting stops when the
+// returned guard is dropped, so it has to live as long as the server.
+pub fn init_sentry(config: &AppConfig) -> Option<sentry::ClientInitGuard> {
+    let dsn = config.sentry_dsn.as_deref()?;
+    let build = BuildInfo::CURRENT;
+    let release = match build.git_sha {
+        Some(git_sha) => format!("{}@{}+{}", env!("CARGO_PKG_NAME"), build.version, git_sha),
+        None => format!("{}@{}", env!("CARGO_PKG_NAME"), build.version),
+    };
+
+    let mut options = sentry::ClientOptions::default();
+    options.release = Some(release.into());
+    options.environment = config.sentry_environment.clone().map(Into::into);
+    options.before_send = Some(Arc::new(|event| Some(scrub_event(event))));
+    Some(sentry::init((dsn, options)))
+}
+
+// Sentry already leaves out cookies and the Authorization header; this also drops the CSRF
+// token and query strings, which carry OAuth codes and state
+fn scrub_event(mut event: Event<'static>) -> Event<'static> {
+    if let Some(request) = event.request.as_mut() {
+        request.headers.retain(|name, _| !name.eq_ignore_ascii_case(CSRF_HEADER));
+        request.query_string = None;
+        if let Some(url) = request.url.as_mut() {
+            url.set_query(None);
+        }
+    }
+    event
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sentry::protocol::Request;
+
+    #[test]
+    fn test_reported_requests_scrubbed() {
+        let event = Event {
+            request: Some(Request {
+                url: "https://mm.example.com/connect-supabase/callback?code=secret&state=abc".parse().ok(),
+                query_string: Some("code=secret&state=abc".to_string()),
+                headers: [
+                    ("X-CSRF-Token".to_string(), "token".to_string()),
+                    ("x-request-id".to_string(), "req-1".to_string()),
+                ]
+                .into_iter()
+                .collect(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let request = scrub_event(event).request.unwrap();
+        assert_eq!(request.url.unwrap().as_str(), "https://mm.example.com/connect-supabase/callback");
+        assert_eq!(request.query_string, None);
+        assert_eq!(request.headers.keys().collect::<Vec<_>>(), vec!["x-request-id"]);
+    }
+}
@@ -0,0 +1,86 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use std::fmt;
+
+// Marks a sealed token, so tokens saved before TOKEN_ENCRYPTION_KEY was set are still read
+const SEALED_PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+
+// Encrypts tokens with AES-256-GCM before they are written anywhere that outlives the process
+#[derive(Clone)]
+pub struct TokenCipher {
+    cipher: Aes256Gcm,
+}
+
+impl fmt::Debug for TokenCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenCipher").finish_non_exhaustive()
+    }
+}
+
+impl TokenCipher {
+    pub fn new(key: &[u8; 32]) -> Self {
+        TokenCipher {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)),
+        }
+    }
+
+    // A fresh nonce per token, stored in front of the ciphertext. Sealed tokens stay as they are.
+    pub fn seal(&self, token: &str) -> String {
+        if token.starts_with(SEALED_PREFIX) {
+            return token.to_string();
+        }
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        // Encrypting into a Vec only fails on inputs of gigabytes
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, token.as_bytes())
+            .expect("token too large to encrypt");
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        format!("{}{}", SEALED_PREFIX, URL_SAFE_NO_PAD.encode(sealed))
+    }
+
+    // Fails for tokens sealed with another key or tampered with. Tokens that were never
+    // sealed are returned as they are.
+    pub fn open(&self, value: &str) -> Result<String, String> {
+        let Some(encoded) = value.strip_prefix(SEALED_PREFIX) else {
+            return Ok(value.to_string());
+        };
+
+        let sealed = URL_SAFE_NO_PAD
+            .decode(encoded)
+            .map_err(|e| format!("sealed token is not valid base64: {}", e))?;
+        if sealed.len() < NONCE_LEN {
+            return Err("sealed token is too short".to_string());
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let token = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "sealed token does not decrypt with TOKEN_ENCRYPTION_KEY".to_string())?;
+        String::from_utf8(token).map_err(|e| format!("sealed token is not UTF-8: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens_sealed_and_opened() {
+        let cipher = TokenCipher::new(&[7; 32]);
+        let sealed = cipher.seal("sbp_secret");
+        assert!(sealed.starts_with(SEALED_PREFIX));
+        assert!(!sealed.contains("sbp_secret"));
+        assert_ne!(cipher.seal("sbp_secret"), sealed);
+        assert_eq!(cipher.seal(&sealed), sealed);
+
+        assert_eq!(cipher.open(&sealed).unwrap(), "sbp_secret");
+        assert_eq!(cipher.open("sbp_plain").unwrap(), "sbp_plain");
+        assert!(TokenCipher::new(&[8; 32]).open(&sealed).is_err());
+        assert!(cipher.open(&format!("{}AAAA", SEALED_PREFIX)).is_err());
+    }
+}